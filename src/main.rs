@@ -1,4 +1,6 @@
 use clap::Parser;
+use env_logger::WriteStyle;
+use ipak::modules::system::{config, path};
 use ipak::prelude::ipak::args::CommandExecution;
 use ipak::utils::args::Args;
 use ipak::utils::error::Error;
@@ -23,6 +25,21 @@ fn main() -> Result<(), Error> {
         log_builder.filter_level(LevelFilter::Warn);
     }
 
+    // 設定は色付きの出力を決めるため、ロガーより先に読み込む
+    if let Some(root) = &args.root {
+        path::set_root(root)?;
+    }
+    config::init(&args.config)?;
+    let config = config::get();
+    log_builder.write_style(if config.color() {
+        WriteStyle::Always
+    } else {
+        WriteStyle::Never
+    });
+
     log_builder.init();
+    for warning in config.warnings() {
+        log::warn!("{}", warning);
+    }
     args.command.exec()
 }
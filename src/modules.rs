@@ -5,6 +5,7 @@
 //! // - `messages`: エラーメッセージやユーザ向けメッセージの管理
 //! - `pkg`: パッケージメタデータとインストール操作の処理
 //! - `project`: プロジェクト設定と構成の管理
+//! - `repo`: パッケージリポジトリとインデックスの管理
 //! - `system`: システム情報と環境設定の取得
 //! - `utils`: ユーティリティ関数と補助機能
 //! - `version`: バージョニングとバージョン範囲の管理
@@ -18,6 +19,9 @@ pub mod pkg;
 /// プロジェクトの設定や構成を管理します。
 pub mod project;
 
+/// パッケージリポジトリとそのインデックスを管理します。
+pub mod repo;
+
 /// システム情報や環境設定を取得します。
 pub mod system;
 
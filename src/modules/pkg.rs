@@ -16,17 +16,25 @@ use crate::utils::{
     shell::{markdown, username},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::Display;
 
 // モジュール宣言
+pub mod autoremove;
+pub mod configure;
 pub mod depend;
 pub mod install;
 pub mod list;
 pub mod lock;
+pub mod manifest;
+pub mod mark;
 pub mod metadata;
 pub mod purge;
+pub mod query;
 pub mod remove;
-pub mod configure;
+pub mod signature;
+pub mod transaction;
+pub mod verify;
 /// パッケージのインストールモードを定義する列挙型。
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub enum Mode {
@@ -64,6 +72,9 @@ pub struct PackageData {
     /// 依存関係および関連情報
     #[serde(skip_serializing_if = "RelationData::is_empty")]
     pub relation: RelationData,
+    /// パッケージがシェルの環境に追加する環境変数
+    #[serde(skip_serializing_if = "EnvData::is_empty")]
+    pub env: EnvData,
 }
 
 /// 作者およびパッケージ固有のメタデータを含みます。
@@ -126,6 +137,21 @@ pub struct RelationData {
     pub provide_cmds: Vec<String>,
 }
 
+/// パッケージが`ipak system env`の出力に追加する環境変数を表します。
+///
+/// パスの値は、絶対パス、`~/`で始まるホームディレクトリからのパス、
+/// またはインストールされたパッケージのディレクトリからの相対パスで指定します。
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct EnvData {
+    /// 先頭にディレクトリを追加するパス形式の環境変数（例: `PATH`、`MANPATH`、`LD_LIBRARY_PATH`）
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub prepend: BTreeMap<String, Vec<String>>,
+    /// 値を設定する環境変数
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, String>,
+}
+
 /// バージョンの制約を持つパッケージ依存関係を表します。
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
@@ -367,6 +393,13 @@ impl RelationData {
     }
 }
 
+impl EnvData {
+    /// 環境変数が1つも指定されていないかどうかを確認します。
+    pub fn is_empty(&self) -> bool {
+        self.prepend.is_empty() && self.set.is_empty()
+    }
+}
+
 /// コマンドライン引数に基づいてパッケージ関連のコマンドを処理します。
 ///
 /// # 引数
@@ -376,15 +409,65 @@ impl RelationData {
 /// コマンドの処理中にエラーが発生した場合、`Error`を返します。
 pub fn pkg(args: PkgCommands) -> Result<(), Error> {
     match args {
-        PkgCommands::Install { file_paths, local, global } => {
-            install::install(&file_paths, (local, global).into())
+        PkgCommands::Install {
+            packages,
+            local,
+            global,
+            allow_downgrade,
+            no_install_recommends,
+            install_suggests,
+            allow_unsigned,
+        } => {
+            let install_options = install::InstallOptions {
+                install_mode: (local, global).into(),
+                allow_downgrade,
+                install_recommends: !no_install_recommends,
+                install_suggests,
+                allow_unsigned,
+            };
+            let file_paths = crate::modules::repo::resolve_targets(
+                &packages,
+                install_options.install_mode,
+            )?;
+            install::install(&file_paths, install_options)
         }
-        PkgCommands::Remove { package_names, local, global } => {
-            remove::remove(&package_names, (local, global).into())
+        PkgCommands::Remove { package_names, local, global, cascade } => {
+            if cascade {
+                remove::remove_cascade(
+                    &package_names,
+                    (local, global).into(),
+                )
+            } else {
+                remove::remove(&package_names, (local, global).into())
+            }
         }
         PkgCommands::Purge { package_names, local, global } => {
             purge::purge(&package_names, (local, global).into())
         }
+        PkgCommands::Autoremove { local, global } => {
+            autoremove::autoremove((local, global).into())
+        }
+        PkgCommands::Depends {
+            package_name,
+            tree,
+            reverse,
+            dot,
+            local,
+            global,
+        } => query::depends(
+            &package_name,
+            query::DependsOptions { tree, reverse, dot },
+            (local, global).into(),
+        ),
+        PkgCommands::Why { package_name, dot, local, global } => {
+            query::why(&package_name, dot, (local, global).into())
+        }
+        PkgCommands::Verify { package_name, local, global } => {
+            verify::verify(package_name.as_deref(), (local, global).into())
+        }
+        PkgCommands::Mark { mark_as, package_names, local, global } => {
+            mark::mark(mark_as, &package_names, (local, global).into())
+        }
         PkgCommands::List { local, global } => {
             list::list((local, global).into())
         }
@@ -0,0 +1,51 @@
+//! このモジュールは、不要になった自動インストールのパッケージを削除する機能を提供します。
+
+use super::depend;
+use super::list;
+use super::lock::LockManager;
+use super::remove;
+use crate::modules::project::ExecMode;
+use crate::utils::error::Error;
+
+/// 依存関係として自動的にインストールされ、現在はどのパッケージからも必要とされていない
+/// パッケージをすべて削除します。
+///
+/// 削除は依存元から順に行われるため、途中で依存関係が壊れることはありません。
+/// 不要なパッケージの判定から削除までを、1つの排他ロックの下で1つのトランザクションとして実行します。
+///
+/// # Arguments
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 不要なパッケージが削除された場合、または削除するパッケージがない場合。
+/// `Err(Error)` パッケージリストの読み込み、またはパッケージの削除に失敗した場合。
+pub fn autoremove(mode: ExecMode) -> Result<(), Error> {
+    let lock_manager = LockManager::new(matches!(mode, ExecMode::Global));
+    let _lock = lock_manager.acquire_lock()?;
+
+    let installed_packages = list::database(mode).load_all()?;
+    let graph = depend::DependencyGraph::from_installed_packages(
+        &installed_packages,
+    );
+
+    let orphan_names = graph
+        .get_orphaned_packages()
+        .iter()
+        .map(|pkg| pkg.about.package.name.clone())
+        .collect::<Vec<_>>();
+    if orphan_names.is_empty() {
+        log::info!("No packages to remove.");
+        return Ok(());
+    }
+
+    log::info!(
+        "Removing automatically installed packages that are no longer required: {}",
+        orphan_names.join(", ")
+    );
+    remove::uninstall_loaded_packages(
+        &orphan_names,
+        mode,
+        false,
+        installed_packages,
+    )
+}
@@ -2,7 +2,6 @@
 
 use crate::modules::project::ExecMode;
 use crate::modules::project::configure as project_configure;
-use crate::modules::system::path;
 use crate::utils::error::Error;
 use std::env;
 
@@ -21,17 +20,8 @@ pub fn configure(
 ) -> Result<(), Error> {
     for package_name in package_names {
         use super::list;
-        let installed_packages = match configure_mode {
-            ExecMode::Local => list::get_local()?,
-            ExecMode::Global => list::get_global()?,
-        };
-
-        let _ = installed_packages
-            .installed_packages
-            .iter()
-            .find(|pkgdata| {
-                &pkgdata.info.about.package.name == package_name
-            })
+        let _ = list::database(configure_mode)
+            .get(package_name)?
             .ok_or_else(|| {
                 Error::from(std::io::Error::new(
                     std::io::ErrorKind::NotFound,
@@ -39,26 +29,8 @@ pub fn configure(
                 ))
             })?; // パッケージが存在するか確認する
 
-        let final_pkg_destination_path = match configure_mode {
-            ExecMode::Local => {
-                path::local::packages_dirpath().join(package_name)
-            }
-            ExecMode::Global => {
-                let list_file_path = path::global::packageslist_filepath();
-                list_file_path
-                    .parent()
-                    .ok_or_else(|| {
-                        std::io::Error::new(
-                            std::io::ErrorKind::InvalidInput,
-                            format!(
-                                "Global packages list file path '{}' does not have a parent directory.",
-                                list_file_path.display()
-                            ),
-                        )
-                    })?
-                    .join(package_name)
-            }
-        };
+        let final_pkg_destination_path =
+            list::packages_dirpath(configure_mode).join(package_name);
 
         if !final_pkg_destination_path.exists() {
             log::error!(
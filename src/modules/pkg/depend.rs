@@ -1,6 +1,8 @@
 // モジュールの宣言
 pub mod error;
 pub mod graph;
+pub mod incompatibility;
+pub mod resolver;
 pub mod utils;
 
 #[cfg(test)]
@@ -9,4 +11,8 @@ mod tests;
 // モジュールから必要な型や関数を再公開
 pub use error::{InstallError, RemoveError};
 pub use graph::DependencyGraph;
-pub use utils::{are_depend_cmds_available, get_missing_depend_cmds};
+pub use incompatibility::{Derivation, Incompatibility, PackageId};
+pub use resolver::{ResolveOptions, Resolver};
+pub use utils::{
+    are_depend_cmds_available, get_missing_depend_cmds, provides,
+};
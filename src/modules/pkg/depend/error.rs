@@ -1,3 +1,4 @@
+use super::incompatibility::{Derivation, format_group, format_groups};
 use crate::modules::pkg::PackageRange;
 use std::fmt; // PackageRange を使用するために追加
 
@@ -22,6 +23,19 @@ pub enum InstallError {
     CyclicDependencies {
         packages: Vec<String>,
     },
+    DowngradeNotAllowed {
+        package: String,
+        installed: String,
+        requested: String,
+    },
+    BreaksDependents {
+        package: String,
+        version: String,
+        dependents: Vec<(String, Vec<PackageRange>)>,
+    },
+    Unsatisfiable {
+        derivation: Box<Derivation>,
+    },
 }
 
 impl fmt::Display for InstallError {
@@ -30,8 +44,9 @@ impl fmt::Display for InstallError {
             InstallError::MissingDependencies { package, missing } => {
                 write!(
                     f,
-                    "Package {} has missing dependencies: {:?}",
-                    package, missing
+                    "Package {} has missing dependencies: {}",
+                    package,
+                    format_groups(missing)
                 )
             }
             InstallError::ConflictsWithInstalled {
@@ -40,8 +55,9 @@ impl fmt::Display for InstallError {
             } => {
                 write!(
                     f,
-                    "Package {} conflicts with installed packages: {:?}",
-                    package, conflicts
+                    "Package {} conflicts with installed packages: {}",
+                    package,
+                    format_group(conflicts)
                 )
             }
             InstallError::ConflictsWithOtherPackages {
@@ -60,15 +76,56 @@ impl fmt::Display for InstallError {
             } => {
                 write!(
                     f,
-                    "Package {} requires unavailable system commands: {:?}",
-                    package, missing_cmds
+                    "Package {} requires unavailable system commands: {}",
+                    package,
+                    missing_cmds.join(", ")
                 )
             }
             InstallError::CyclicDependencies { packages } => {
                 write!(
                     f,
-                    "Cyclic dependencies detected among packages: {:?}",
-                    packages
+                    "Cyclic dependencies detected among packages: {}",
+                    packages.join(", ")
+                )
+            }
+            InstallError::DowngradeNotAllowed {
+                package,
+                installed,
+                requested,
+            } => {
+                write!(
+                    f,
+                    "Package {} {} is already installed. Use --allow-downgrade to install the older version {}",
+                    package, installed, requested
+                )
+            }
+            InstallError::BreaksDependents {
+                package,
+                version,
+                dependents,
+            } => {
+                let dependents = dependents
+                    .iter()
+                    .map(|(name, group)| {
+                        format!(
+                            "{} (requires {})",
+                            name,
+                            format_group(group)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(
+                    f,
+                    "Package {} {} would break installed packages: {}",
+                    package, version, dependents
+                )
+            }
+            InstallError::Unsatisfiable { derivation } => {
+                write!(
+                    f,
+                    "Dependencies cannot be satisfied:\n{}",
+                    derivation.to_string().trim_end()
                 )
             }
         }
@@ -80,8 +137,8 @@ impl std::error::Error for InstallError {}
 #[derive(Debug)]
 pub enum RemoveError {
     DependencyOfOtherPackages {
-        package: String,
-        dependent_packages: Vec<String>,
+        packages: Vec<String>,
+        dependents: Vec<(String, Vec<PackageRange>)>,
     },
 }
 
@@ -89,14 +146,26 @@ impl fmt::Display for RemoveError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             RemoveError::DependencyOfOtherPackages {
-                package,
-                dependent_packages,
+                packages,
+                dependents,
             } => {
-                write!(
+                writeln!(
                     f,
-                    "Package '{}' cannot be removed because the following packages depend on it: {:?}",
-                    package, dependent_packages
-                )
+                    "Package(s) {} cannot be removed because the following packages depend on them:",
+                    packages.join(", ")
+                )?;
+                let dependents = dependents
+                    .iter()
+                    .map(|(name, group)| {
+                        format!(
+                            "  {} (requires {})",
+                            name,
+                            format_group(group)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                write!(f, "{}", dependents)
             }
         }
     }
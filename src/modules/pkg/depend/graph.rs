@@ -3,7 +3,9 @@ use std::collections::{HashMap, HashSet, VecDeque};
 
 use super::error::{InstallError, RemoveError}; // 同じモジュール内のエラーをインポート
 use super::utils;
-use crate::modules::pkg::list::{InstalledPackageData, PackageListData};
+use crate::modules::pkg::list::{
+    InstallReason, InstalledPackageData, PackageListData,
+};
 use crate::modules::pkg::{PackageData, PackageRange};
 use crate::utils::version::Version; // utils::get_missing_depend_cmds を使用
 
@@ -137,9 +139,24 @@ impl DependencyGraph {
         &self,
         package: &PackageData,
     ) -> Vec<Vec<PackageRange>> {
-        package
-            .relation
-            .depend
+        self.get_unmet_groups(&package.relation.depend)
+    }
+
+    /// 依存関係グループのうち、現在の状態で満たされていないものを返します。
+    ///
+    /// 各グループはOR条件として扱われ、いずれか1つが満たされていればグループは満たされています。
+    /// `depend`だけでなく、`recommends`や`suggests`にも同じ意味で使用できます。
+    ///
+    /// # Arguments
+    /// * `groups` - 確認する依存関係グループのリスト。
+    ///
+    /// # Returns
+    /// 満たされていない依存関係グループのリスト。
+    pub fn get_unmet_groups(
+        &self,
+        groups: &[Vec<PackageRange>],
+    ) -> Vec<Vec<PackageRange>> {
+        groups
             .iter()
             .filter(|group| {
                 !group.iter().any(|dep| self.is_dependency_satisfied(dep))
@@ -249,12 +266,181 @@ impl DependencyGraph {
         Ok(())
     }
 
+    /// 既にインストールされているパッケージを新しいバージョンに置き換えても、
+    /// 他のインストール済みパッケージの依存関係が満たされるかを確認します。
+    ///
+    /// 置き換え前には満たされていて、置き換え後に満たされなくなる依存関係グループを
+    /// 持つパッケージがあればエラーを返します。
+    ///
+    /// # Arguments
+    /// * `upgrading_packages` - インストールしようとしている新しいバージョンのパッケージ。
+    ///
+    /// # Returns
+    /// `Ok(())` すべての依存関係が引き続き満たされる場合。
+    /// `Err(InstallError::BreaksDependents)` 依存関係が満たされなくなるパッケージがある場合。
+    pub fn is_packages_upgradable(
+        &self,
+        upgrading_packages: &[PackageData],
+    ) -> Result<(), InstallError> {
+        let upgrading_names = upgrading_packages
+            .iter()
+            .map(|pkg| pkg.about.package.name.as_str())
+            .collect::<Vec<_>>();
+        let upgraded_graph = self
+            .without_packages(&upgrading_names)
+            .with_additional_packages(upgrading_packages);
+
+        for upgrading in upgrading_packages {
+            let upgrading_name = &upgrading.about.package.name;
+            // 旧バージョンが提供していた仮想パッケージ名も対象に含める
+            let provided_names = self
+                .installed_package_data
+                .iter()
+                .filter(|pkg| {
+                    pkg.info.about.package.name == *upgrading_name
+                })
+                .flat_map(|pkg| pkg.info.relation.virtuals.iter())
+                .map(|virtual_pkg| virtual_pkg.name.as_str())
+                .chain(std::iter::once(upgrading_name.as_str()))
+                .collect::<HashSet<_>>();
+            let mut dependents = Vec::new();
+            for installed_pkg_data in &self.installed_package_data {
+                let installed = &installed_pkg_data.info;
+                if upgrading_names
+                    .contains(&installed.about.package.name.as_str())
+                {
+                    continue;
+                }
+                for group in &installed.relation.depend {
+                    let refers_upgrading = group.iter().any(|dep| {
+                        provided_names.contains(dep.name.as_str())
+                    });
+                    let was_satisfied = group
+                        .iter()
+                        .any(|dep| self.is_dependency_satisfied(dep));
+                    let still_satisfied = group.iter().any(|dep| {
+                        upgraded_graph.is_dependency_satisfied(dep)
+                    });
+                    if refers_upgrading
+                        && was_satisfied
+                        && !still_satisfied
+                    {
+                        dependents.push((
+                            installed.about.package.name.clone(),
+                            group.clone(),
+                        ));
+                    }
+                }
+            }
+            if !dependents.is_empty() {
+                return Err(InstallError::BreaksDependents {
+                    package: upgrading_name.clone(),
+                    version: upgrading.about.package.version.to_string(),
+                    dependents,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 手動でインストールされたパッケージから到達できない、自動インストールされたパッケージを返します。
+    ///
+    /// 手動でインストールされたパッケージを起点に、`depend`、`recommends`、`suggests`を満たす
+    /// インストール済みパッケージ（仮想パッケージの提供元を含む）をたどり、
+    /// 到達できなかったパッケージを孤立したパッケージとみなします。
+    ///
+    /// # Returns
+    /// 孤立したパッケージのリスト。依存元が依存先より先に来るように（削除できる順に）並んでいます。
+    pub fn get_orphaned_packages(&self) -> Vec<PackageData> {
+        let mut reachable: HashSet<&str> = HashSet::new();
+        let mut queue: VecDeque<&PackageData> = VecDeque::new();
+        for installed in &self.installed_package_data {
+            if !installed.install_reason.is_auto() {
+                reachable.insert(&installed.info.about.package.name);
+                queue.push_back(&installed.info);
+            }
+        }
+
+        while let Some(package) = queue.pop_front() {
+            let relation = &package.relation;
+            let deps = relation
+                .depend
+                .iter()
+                .chain(&relation.recommends)
+                .chain(&relation.suggests)
+                .flatten();
+            for dep in deps {
+                for provider in &self.installed_package_data {
+                    if utils::provides(&provider.info, dep)
+                        && reachable
+                            .insert(&provider.info.about.package.name)
+                    {
+                        queue.push_back(&provider.info);
+                    }
+                }
+            }
+        }
+
+        let orphans = self
+            .installed_package_data
+            .iter()
+            .filter(|installed| {
+                !reachable
+                    .contains(installed.info.about.package.name.as_str())
+            })
+            .map(|installed| installed.info.clone())
+            .collect::<Vec<_>>();
+
+        sort_for_removal(orphans)
+    }
+
+    /// 指定されたパッケージを削除できるかを確認します。
+    ///
+    /// 削除によって満たされなくなる依存関係グループを持つパッケージがあれば、
+    /// それらをすべて、満たされなくなるグループとともにエラーとして返します。
+    ///
+    /// # Arguments
+    /// * `packages_to_remove_names` - 削除するパッケージの名前。
+    ///
+    /// # Returns
+    /// `Ok(())` すべての依存関係が引き続き満たされる場合。
+    /// `Err(RemoveError::DependencyOfOtherPackages)` 依存関係が満たされなくなるパッケージがある場合。
     pub fn is_packages_removable(
         &self,
         packages_to_remove_names: &[&str],
     ) -> Result<(), RemoveError> {
+        let dependents =
+            self.get_broken_dependents(packages_to_remove_names);
+        if dependents.is_empty() {
+            Ok(())
+        } else {
+            Err(RemoveError::DependencyOfOtherPackages {
+                packages: packages_to_remove_names
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect(),
+                dependents,
+            })
+        }
+    }
+
+    /// 指定されたパッケージを削除すると満たされなくなる依存関係グループを返します。
+    ///
+    /// 削除前には満たされていて、削除後に満たされなくなるグループのみが対象です。
+    ///
+    /// # Arguments
+    /// * `packages_to_remove_names` - 削除するパッケージの名前。
+    ///
+    /// # Returns
+    /// 依存元のパッケージ名と、満たされなくなる依存関係グループの組のリスト。
+    pub fn get_broken_dependents(
+        &self,
+        packages_to_remove_names: &[&str],
+    ) -> Vec<(String, Vec<PackageRange>)> {
         let temp_graph = self.without_packages(packages_to_remove_names);
 
+        let mut dependents = Vec::new();
         for installed_pkg_data in &self.installed_package_data {
             let current_pkg_name =
                 &installed_pkg_data.info.about.package.name;
@@ -265,18 +451,117 @@ impl DependencyGraph {
                 continue;
             }
 
-            if !temp_graph
-                .are_dependencies_satisfied(&installed_pkg_data.info)
-            {
-                let dependent_packages = vec![current_pkg_name.clone()];
-                return Err(RemoveError::DependencyOfOtherPackages {
-                    package: packages_to_remove_names.join(", "),
-                    dependent_packages,
-                });
+            for group in &installed_pkg_data.info.relation.depend {
+                let was_satisfied = group
+                    .iter()
+                    .any(|dep| self.is_dependency_satisfied(dep));
+                let still_satisfied = group
+                    .iter()
+                    .any(|dep| temp_graph.is_dependency_satisfied(dep));
+                if was_satisfied && !still_satisfied {
+                    dependents
+                        .push((current_pkg_name.clone(), group.clone()));
+                }
             }
         }
+        dependents
+    }
 
-        Ok(())
+    /// 指定されたパッケージに推移的に依存しているパッケージをすべて返します。
+    ///
+    /// 指定されたパッケージを削除すると依存関係が満たされなくなるパッケージを、
+    /// 新たに見つからなくなるまで繰り返し削除対象に加えます。
+    ///
+    /// # Arguments
+    /// * `package_names` - 削除するパッケージの名前。
+    ///
+    /// # Returns
+    /// 一緒に削除する必要があるパッケージのリスト（`package_names`自身は含みません）。
+    /// 依存元が依存先より先に来るように並んでいます。
+    pub fn get_reverse_dependencies(
+        &self,
+        package_names: &[&str],
+    ) -> Vec<PackageData> {
+        let mut removing = package_names
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        loop {
+            let names =
+                removing.iter().map(String::as_str).collect::<Vec<_>>();
+            let new_dependents = self
+                .get_broken_dependents(&names)
+                .into_iter()
+                .map(|(name, _)| name)
+                .filter(|name| !removing.contains(name))
+                .collect::<HashSet<_>>();
+            if new_dependents.is_empty() {
+                break;
+            }
+            removing.extend(new_dependents);
+        }
+
+        let dependents = self
+            .installed_package_data
+            .iter()
+            .map(|installed| &installed.info)
+            .filter(|info| {
+                let name = info.about.package.name.as_str();
+                removing.iter().any(|removing| removing == name)
+                    && !package_names.contains(&name)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        sort_for_removal(dependents)
+    }
+
+    /// 指定されたパッケージを、依存元が依存先より先に来るように並べ替えます。
+    ///
+    /// インストールされていないパッケージ名は、元の順序のまま末尾に置かれます。
+    ///
+    /// # Arguments
+    /// * `package_names` - 削除するパッケージの名前。
+    ///
+    /// # Returns
+    /// 削除する順に並べたパッケージ名。
+    pub fn get_removal_order(
+        &self,
+        package_names: &[String],
+    ) -> Vec<String> {
+        let installed = self
+            .installed_package_data
+            .iter()
+            .map(|installed| &installed.info)
+            .filter(|info| {
+                package_names.contains(&info.about.package.name)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut ordered = sort_for_removal(installed)
+            .into_iter()
+            .map(|info| info.about.package.name)
+            .collect::<Vec<_>>();
+        for name in package_names {
+            if !ordered.contains(name) {
+                ordered.push(name.clone());
+            }
+        }
+        ordered
+    }
+}
+
+/// パッケージ同士の依存関係のみを考慮して、依存元が依存先より先に来るように並べ替えます。
+///
+/// 循環依存がある場合は元の順序のまま返します。
+fn sort_for_removal(packages: Vec<PackageData>) -> Vec<PackageData> {
+    match DependencyGraph::new()
+        .topological_sort_packages_for_install(&packages)
+    {
+        Ok(mut sorted) => {
+            sorted.reverse();
+            sorted
+        }
+        Err(_) => packages,
     }
 }
 
@@ -338,6 +623,8 @@ impl DependencyGraphOperations for DependencyGraph {
             new_graph.installed_package_data.push(InstalledPackageData {
                 info: package.clone(),
                 last_modified: Local::now(),
+                previous_version: None,
+                install_reason: InstallReason::default(),
             });
         }
 
@@ -372,21 +659,32 @@ impl DependencyGraphOperations for DependencyGraph {
                 }
 
                 if !group_satisfied_by_installed {
-                    let mut depends_on_internal = false;
-                    for dep in dep_group {
-                        if package_map.contains_key(&dep.name) {
-                            adj_list
-                                .entry(dep.name.clone())
-                                .or_default()
-                                .push(pkg_name.clone());
-                            depends_on_internal = true;
+                    let mut internal_edges = 0;
+                    // 仮想パッケージを提供するパッケージも依存先として扱う
+                    let providers = dep_group.iter().flat_map(|dep| {
+                        packages_to_sort.iter().filter(|provider| {
+                            provider.about.package.name == dep.name
+                                || provider
+                                    .relation
+                                    .virtuals
+                                    .iter()
+                                    .any(|v| v.name == dep.name)
+                        })
+                    });
+                    for provider in providers {
+                        let provider_name = &provider.about.package.name;
+                        if provider_name == pkg_name {
+                            continue;
                         }
+                        adj_list
+                            .entry(provider_name.clone())
+                            .or_default()
+                            .push(pkg_name.clone());
+                        internal_edges += 1;
                     }
-                    if depends_on_internal {
-                        in_degree
-                            .entry(pkg_name.clone())
-                            .and_modify(|e| *e += 1);
-                    }
+                    in_degree
+                        .entry(pkg_name.clone())
+                        .and_modify(|e| *e += internal_edges);
                 }
             }
         }
@@ -0,0 +1,207 @@
+//! このモジュールは、依存関係を解決できない理由を表す構造化された非互換性モデルを提供します。
+//!
+//! リゾルバは候補を選択できなかった理由を`Incompatibility`として記録し、
+//! それらを依存関係ごとにまとめた`Derivation`の木を組み立てます。
+//! `Derivation`は「AはBに依存し、BはインストールされているCと競合するため、Aはインストールできない」
+//! のような、人が読める説明として表示できます。
+
+use crate::modules::pkg::{PackageData, PackageRange};
+use std::fmt;
+
+/// 特定のバージョンのパッケージを識別します。
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageId {
+    /// パッケージ名。
+    pub name: String,
+    /// パッケージのバージョン。
+    pub version: String,
+}
+
+impl From<&PackageData> for PackageId {
+    fn from(package: &PackageData) -> Self {
+        Self {
+            name: package.about.package.name.clone(),
+            version: package.about.package.version.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for PackageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.version)
+    }
+}
+
+/// 依存関係グラフの状態から直接観測された、同時には成り立たない事実です。
+#[derive(Debug, Clone)]
+pub enum Incompatibility {
+    /// 依存関係の範囲を満たすバージョンが存在しません。
+    NoVersions {
+        /// 満たせなかった依存関係のパッケージ名。
+        name: String,
+        /// 満たせなかった依存関係のバージョン範囲。
+        range: String,
+        /// 利用可能なバージョン。
+        available: Vec<String>,
+    },
+    /// 2つのパッケージが競合しています。
+    Conflict {
+        /// 選択しようとしたパッケージ。
+        package: PackageId,
+        /// 競合相手のパッケージ。
+        other: PackageId,
+        /// 競合相手がインストール済みかどうか。
+        other_installed: bool,
+    },
+    /// 同じ名前の別のバージョンが既に存在します。
+    AlreadyPresent {
+        /// 既に存在するパッケージ。
+        existing: PackageId,
+        /// 既存のパッケージがインストール済みかどうか。
+        installed: bool,
+    },
+    /// 必要なシステムコマンドが見つかりません。
+    MissingSystemCommands {
+        /// コマンドを必要とするパッケージ。
+        package: PackageId,
+        /// 見つからなかったコマンド。
+        missing_cmds: Vec<String>,
+    },
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Incompatibility::NoVersions { name, range, available } => {
+                if available.is_empty() {
+                    write!(f, "no package named {} is available", name)
+                } else {
+                    write!(
+                        f,
+                        "no version of {} matches {} (available: {})",
+                        name,
+                        range,
+                        available.join(", ")
+                    )
+                }
+            }
+            Incompatibility::Conflict {
+                package,
+                other,
+                other_installed,
+            } => {
+                write!(f, "{} conflicts with {}", package, other)?;
+                if *other_installed {
+                    write!(f, ", which is installed")?;
+                }
+                Ok(())
+            }
+            Incompatibility::AlreadyPresent { existing, installed } => {
+                if *installed {
+                    write!(f, "{} is already installed", existing)
+                } else {
+                    write!(f, "{} is already being installed", existing)
+                }
+            }
+            Incompatibility::MissingSystemCommands {
+                package,
+                missing_cmds,
+            } => {
+                write!(
+                    f,
+                    "{} requires unavailable system commands: {}",
+                    package,
+                    missing_cmds.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// パッケージをインストールできない理由を、前提となる非互換性から導いたものです。
+#[derive(Debug, Clone)]
+pub enum Derivation {
+    /// 直接観測された非互換性。
+    External(Incompatibility),
+    /// 依存関係グループのどの候補も選択できないため、パッケージをインストールできないという結論。
+    Derived {
+        /// インストールできないパッケージ。
+        package: PackageId,
+        /// 満たせなかった依存関係グループ。
+        dependency: Vec<PackageRange>,
+        /// グループ内の各候補を選択できなかった理由。
+        causes: Vec<Derivation>,
+    },
+}
+
+impl Derivation {
+    /// 説明を指定された深さのインデントで書き出します。
+    fn write_indented(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        depth: usize,
+    ) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        match self {
+            Derivation::External(incompatibility) => {
+                writeln!(f, "{}- {}", indent, incompatibility)
+            }
+            Derivation::Derived { package, dependency, causes } => {
+                let bullet = if depth == 0 { "" } else { "- " };
+                writeln!(
+                    f,
+                    "{}{}{} depends on {}, but:",
+                    indent,
+                    bullet,
+                    package,
+                    format_group(dependency)
+                )?;
+                for cause in causes {
+                    cause.write_indented(f, depth + 1)?;
+                }
+                let inner_indent = if depth == 0 {
+                    String::new()
+                } else {
+                    "  ".repeat(depth + 1)
+                };
+                writeln!(
+                    f,
+                    "{}so {} cannot be installed",
+                    inner_indent, package
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for Derivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+/// 依存関係グループ（OR条件）を読みやすい文字列に変換します。
+///
+/// # Arguments
+/// * `group` - 依存関係グループ。
+///
+/// # Returns
+/// `"a (>= 1.0) | b (*)"`のような文字列。
+pub fn format_group(group: &[PackageRange]) -> String {
+    group.iter().map(|dep| dep.to_string()).collect::<Vec<_>>().join(" | ")
+}
+
+/// 複数の依存関係グループを読みやすい文字列に変換します。
+///
+/// # Arguments
+/// * `groups` - 依存関係グループのリスト。
+///
+/// # Returns
+/// 各グループを`", "`で区切った文字列。
+pub fn format_groups(groups: &[Vec<PackageRange>]) -> String {
+    groups
+        .iter()
+        .map(|group| format_group(group))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,350 @@
+//! このモジュールは、不足している依存関係を利用可能なパッケージから補う依存関係リゾルバを提供します。
+//!
+//! `relation.depend`のORグループを順に調べ、範囲を満たす最も新しいバージョンを選択します。
+//! 競合や仮想パッケージを考慮し、選択が行き詰まった場合はバックトラックして別の候補を試します。
+//! 解決に失敗した場合は、各候補を選択できなかった理由を`Derivation`として返します。
+//!
+//! `recommends`と`suggests`は`ResolveOptions`で有効にした場合のみ、解決できる範囲で追加されます。
+
+use super::error::InstallError;
+use super::graph::{DependencyGraph, DependencyGraphOperations};
+use super::incompatibility::{
+    Derivation, Incompatibility, PackageId, format_group,
+};
+use super::utils;
+use crate::modules::pkg::{PackageData, PackageRange};
+use crate::utils::version::Version;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// 依存関係の解決方法を指定するオプションです。
+#[derive(Default, Clone, Copy)]
+pub struct ResolveOptions {
+    /// 推奨される依存関係（`recommends`）もインストールするかどうか。
+    pub install_recommends: bool,
+    /// 提案された依存関係（`suggests`）もインストールするかどうか。
+    pub install_suggests: bool,
+}
+
+impl ResolveOptions {
+    /// パッケージの依存関係グループのうち、オプションで有効にされた任意のものを返します。
+    ///
+    /// # Arguments
+    /// * `package` - 対象のパッケージ。
+    ///
+    /// # Returns
+    /// 有効な`recommends`と`suggests`のグループを順に返すイテレータ。
+    pub fn optional_groups<'p>(
+        &self,
+        package: &'p PackageData,
+    ) -> impl Iterator<Item = &'p Vec<PackageRange>> {
+        let recommends: &'p [Vec<PackageRange>] =
+            if self.install_recommends {
+                &package.relation.recommends
+            } else {
+                &[]
+            };
+        let suggests: &'p [Vec<PackageRange>] = if self.install_suggests {
+            &package.relation.suggests
+        } else {
+            &[]
+        };
+        recommends.iter().chain(suggests.iter())
+    }
+}
+
+/// 依存関係リゾルバです。
+///
+/// インストール済みのパッケージを表す`DependencyGraph`と、
+/// リポジトリなどから取得できるパッケージの一覧を元に依存関係を解決します。
+pub struct Resolver<'a> {
+    /// インストール済みパッケージの状態。
+    graph: &'a DependencyGraph,
+    /// パッケージ名（仮想パッケージ名を含む）ごとの候補。
+    /// 提供するバージョンの降順に並んでいます。
+    candidates: HashMap<&'a str, Vec<(&'a Version, &'a PackageData)>>,
+}
+
+impl<'a> Resolver<'a> {
+    /// 新しい`Resolver`を作成します。
+    ///
+    /// # Arguments
+    /// * `graph` - インストール済みパッケージの依存関係グラフ。
+    /// * `available` - 依存関係の解決に使用できるパッケージ。
+    pub fn new(
+        graph: &'a DependencyGraph,
+        available: &'a [PackageData],
+    ) -> Self {
+        let mut candidates: HashMap<&str, Vec<(&Version, &PackageData)>> =
+            HashMap::new();
+        for package in available {
+            candidates
+                .entry(&package.about.package.name)
+                .or_default()
+                .push((&package.about.package.version, package));
+            for virtual_pkg in &package.relation.virtuals {
+                candidates
+                    .entry(&virtual_pkg.name)
+                    .or_default()
+                    .push((&virtual_pkg.version, package));
+            }
+        }
+        for versions in candidates.values_mut() {
+            versions.sort_by(|(a, _), (b, _)| {
+                b.partial_cmp(a).unwrap_or(Ordering::Equal)
+            });
+        }
+        Self { graph, candidates }
+    }
+
+    /// 指定されたパッケージをインストールするための計画を作成します。
+    ///
+    /// 不足している依存関係は利用可能なパッケージから補われ、
+    /// 結果は依存関係に基づいてトポロジカルソートされます。
+    /// オプションで有効にされた`recommends`と`suggests`は、満たせる場合のみ追加されます。
+    ///
+    /// # Arguments
+    /// * `requested` - インストールが要求されたパッケージ。
+    /// * `opts` - 解決オプション。
+    ///
+    /// # Returns
+    /// `Ok(Vec<PackageData>)` 要求されたパッケージと追加の依存パッケージをインストール順に並べたもの。
+    /// `Err(InstallError)` 依存関係を満たすパッケージの組み合わせが見つからない場合。
+    /// `InstallError::Unsatisfiable`には解決できなかった理由の説明が含まれます。
+    pub fn resolve(
+        &self,
+        requested: &[PackageData],
+        opts: ResolveOptions,
+    ) -> Result<Vec<PackageData>, InstallError> {
+        let mut selected = Vec::new();
+        if let Err(derivation) = self.solve(requested, &mut selected) {
+            return Err(InstallError::Unsatisfiable {
+                derivation: Box::new(derivation),
+            });
+        }
+        self.solve_optional(requested, &mut selected, opts);
+        for package in &selected {
+            log::info!(
+                "Selected {} {} to satisfy dependencies",
+                package.about.package.name,
+                package.about.package.version
+            );
+        }
+        let plan = requested
+            .iter()
+            .chain(selected.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        self.graph.topological_sort_packages_for_install(&plan)
+    }
+
+    /// 満たされていない依存関係グループを1つずつ解決します。
+    ///
+    /// 候補を選択するたびに再帰し、以降の解決に失敗した場合は選択を取り消して次の候補を試します。
+    /// 候補を選択できなかった理由はすべて記録され、失敗時の説明に使用されます。
+    ///
+    /// # Returns
+    /// `Ok(())` すべての依存関係が満たされた場合。
+    /// `Err(Derivation)` 満たせなかった依存関係グループと、各候補を選択できなかった理由。
+    fn solve(
+        &self,
+        requested: &[PackageData],
+        selected: &mut Vec<PackageData>,
+    ) -> Result<(), Derivation> {
+        let state = self
+            .graph
+            .with_additional_packages(requested)
+            .with_additional_packages(selected);
+
+        let Some((package, group)) =
+            requested.iter().chain(selected.iter()).find_map(|package| {
+                state
+                    .get_missing_dependencies(package)
+                    .into_iter()
+                    .next()
+                    .map(|group| (PackageId::from(package), group))
+            })
+        else {
+            return Ok(());
+        };
+
+        let mut causes = Vec::new();
+        for dep in &group {
+            let candidates = self
+                .candidates
+                .get(dep.name.as_str())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let matching = candidates
+                .iter()
+                .filter(|(version, _)| dep.range.compare(version))
+                .collect::<Vec<_>>();
+            if matching.is_empty() {
+                causes.push(Derivation::External(
+                    Incompatibility::NoVersions {
+                        name: dep.name.clone(),
+                        range: dep.range.to_string(),
+                        available: candidates
+                            .iter()
+                            .map(|(version, _)| version.to_string())
+                            .collect(),
+                    },
+                ));
+                continue;
+            }
+            for (_, candidate) in matching {
+                let incompatibilities =
+                    self.incompatibilities(&state, candidate);
+                if !incompatibilities.is_empty() {
+                    causes.extend(
+                        incompatibilities
+                            .into_iter()
+                            .map(Derivation::External),
+                    );
+                    continue;
+                }
+                selected.push((*candidate).clone());
+                match self.solve(requested, selected) {
+                    Ok(()) => return Ok(()),
+                    Err(derivation) => causes.push(derivation),
+                }
+                selected.pop();
+            }
+        }
+        Err(Derivation::Derived { package, dependency: group, causes })
+    }
+
+    /// オプションで有効にされた任意の依存関係グループを、満たせる範囲で解決します。
+    ///
+    /// 候補を選択した結果、必須の依存関係が解決できなくなる場合はその候補を諦めます。
+    /// どの候補も選択できないグループは無視されます。
+    /// 新たに選択されたパッケージの任意の依存関係も同様に処理されます。
+    fn solve_optional(
+        &self,
+        requested: &[PackageData],
+        selected: &mut Vec<PackageData>,
+        opts: ResolveOptions,
+    ) {
+        let mut index = 0;
+        while let Some(package) =
+            requested.iter().chain(selected.iter()).nth(index).cloned()
+        {
+            index += 1;
+            for group in opts.optional_groups(&package) {
+                let state = self
+                    .graph
+                    .with_additional_packages(requested)
+                    .with_additional_packages(selected);
+                if state
+                    .get_unmet_groups(std::slice::from_ref(group))
+                    .is_empty()
+                {
+                    continue;
+                }
+                if !self
+                    .select_optional(requested, selected, &state, group)
+                {
+                    log::info!(
+                        "Skipping optional dependency {} of {}: no installable candidate",
+                        format_group(group),
+                        package.about.package.name
+                    );
+                }
+            }
+        }
+    }
+
+    /// 任意の依存関係グループを満たす候補を1つ選択します。
+    ///
+    /// # Returns
+    /// 候補を選択できた場合は`true`。
+    fn select_optional(
+        &self,
+        requested: &[PackageData],
+        selected: &mut Vec<PackageData>,
+        state: &DependencyGraph,
+        group: &[PackageRange],
+    ) -> bool {
+        for dep in group {
+            for (version, candidate) in self
+                .candidates
+                .get(dep.name.as_str())
+                .into_iter()
+                .flatten()
+            {
+                if !dep.range.compare(version)
+                    || !self.incompatibilities(state, candidate).is_empty()
+                {
+                    continue;
+                }
+                selected.push((*candidate).clone());
+                if self.solve(requested, selected).is_ok() {
+                    return true;
+                }
+                selected.pop();
+            }
+        }
+        false
+    }
+
+    /// 候補のパッケージを現在の状態に追加できない理由を列挙します。
+    ///
+    /// 同名のパッケージが既に存在する場合、いずれかの方向に競合が宣言されている場合、
+    /// または必要なシステムコマンドが不足している場合に非互換性が報告されます。
+    ///
+    /// # Returns
+    /// 候補を選択できない理由のリスト。空の場合は選択できます。
+    fn incompatibilities(
+        &self,
+        state: &DependencyGraph,
+        candidate: &PackageData,
+    ) -> Vec<Incompatibility> {
+        let candidate_id = PackageId::from(candidate);
+        let mut incompatibilities = Vec::new();
+        for member in &state.installed_package_data {
+            let member_id = PackageId::from(&member.info);
+            let installed = self.is_installed(&member_id);
+            if member_id.name == candidate_id.name {
+                incompatibilities.push(Incompatibility::AlreadyPresent {
+                    existing: member_id,
+                    installed,
+                });
+            } else if declares_conflict(candidate, &member.info)
+                || declares_conflict(&member.info, candidate)
+            {
+                incompatibilities.push(Incompatibility::Conflict {
+                    package: candidate_id.clone(),
+                    other: member_id,
+                    other_installed: installed,
+                });
+            }
+        }
+        let missing_cmds =
+            utils::get_missing_depend_cmds(&candidate.relation);
+        if !missing_cmds.is_empty() {
+            incompatibilities.push(
+                Incompatibility::MissingSystemCommands {
+                    package: candidate_id,
+                    missing_cmds,
+                },
+            );
+        }
+        incompatibilities
+    }
+
+    /// パッケージがインストール済みのパッケージかどうかを返します。
+    fn is_installed(&self, id: &PackageId) -> bool {
+        self.graph
+            .installed_package_data
+            .iter()
+            .any(|installed| PackageId::from(&installed.info) == *id)
+    }
+}
+
+/// `package`が`other`との競合を宣言しているかどうかを返します。
+fn declares_conflict(package: &PackageData, other: &PackageData) -> bool {
+    package.relation.conflicts.iter().any(|conflict| {
+        conflict.name == other.about.package.name
+            && conflict.range.compare(&other.about.package.version)
+    })
+}
@@ -1,10 +1,12 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::modules::pkg::depend::{
-        DependencyGraph, InstallError, RemoveError,
+        DependencyGraph, Derivation, Incompatibility, InstallError,
+        RemoveError, ResolveOptions, Resolver,
     };
     use crate::modules::pkg::list::{
-        InstalledPackageData, PackageListData,
+        InstallReason, InstalledPackageData, PackageListData,
     };
     use crate::modules::pkg::{
         AboutData, PackageAboutData, PackageData, PackageRange,
@@ -22,6 +24,8 @@ mod tests {
                 .map(|info| InstalledPackageData {
                     info,
                     last_modified: Local::now(),
+                    previous_version: None,
+                    install_reason: InstallReason::default(),
                 })
                 .collect(),
             last_modified: Local::now(), // 修正: Vec<_> から DateTime<Local> に変更
@@ -394,11 +398,11 @@ mod tests {
         let result = graph.is_packages_removable(&["pkgA"]);
 
         if let Err(RemoveError::DependencyOfOtherPackages {
-            ref dependent_packages,
+            ref dependents,
             ..
         }) = result
         {
-            assert!(dependent_packages.contains(&"pkgC".to_string()));
+            assert!(dependents.iter().any(|(name, _)| name == "pkgC"));
         } else {
             panic!(
                 "result was not `RemoveError::DependencyOfOtherPackages`"
@@ -441,13 +445,13 @@ mod tests {
         let result = graph.is_packages_removable(&["pkgA", "pkgB"]);
 
         if let Err(RemoveError::DependencyOfOtherPackages {
-            ref dependent_packages,
+            ref dependents,
             ..
         }) = result
         {
             assert!(
-                dependent_packages.contains(&"pkgC".to_string())
-                    || dependent_packages.contains(&"pkgD".to_string())
+                dependents.iter().any(|(name, _)| name == "pkgC")
+                    || dependents.iter().any(|(name, _)| name == "pkgD")
             );
         } else {
             panic!(
@@ -509,15 +513,460 @@ mod tests {
 
         let result = graph.is_packages_removable(&["pkgA"]);
         if let Err(RemoveError::DependencyOfOtherPackages {
-            ref dependent_packages,
+            ref dependents,
             ..
         }) = result
         {
-            assert!(dependent_packages.contains(&"pkgB".to_string()));
+            assert!(dependents.iter().any(|(name, _)| name == "pkgB"));
         } else {
             panic!(
                 "result was not `RemoveError::DependencyOfOtherPackages`"
             )
         }
     }
+
+    #[test]
+    fn test_is_packages_upgradable_within_dependent_range() {
+        // テストの目的: 依存元の範囲内でのアップグレードが許可されるか
+        let pkg_a = create_package("pkgA", "1.0", None, None, None, None);
+        let pkg_b = create_package(
+            "pkgB",
+            "1.0",
+            Some(vec![vec![PackageRange {
+                name: "pkgA".to_string(),
+                range: VersionRange::from_str(">= 1.0, < 2.0").unwrap(),
+            }]]),
+            None,
+            None,
+            None,
+        );
+        let installed_packages = setup_package_list(vec![pkg_a, pkg_b]);
+        let graph =
+            DependencyGraph::from_installed_packages(&installed_packages);
+
+        let new_pkg_a =
+            create_package("pkgA", "1.5", None, None, None, None);
+        assert!(graph.is_packages_upgradable(&[new_pkg_a]).is_ok());
+    }
+
+    #[test]
+    fn test_is_packages_upgradable_breaks_dependent() {
+        // テストの目的: 依存元の範囲外へのアップグレードが適切に失敗するか
+        let pkg_a = create_package("pkgA", "1.0", None, None, None, None);
+        let pkg_b = create_package(
+            "pkgB",
+            "1.0",
+            Some(vec![vec![PackageRange {
+                name: "pkgA".to_string(),
+                range: VersionRange::from_str(">= 1.0, < 2.0").unwrap(),
+            }]]),
+            None,
+            None,
+            None,
+        );
+        let installed_packages = setup_package_list(vec![pkg_a, pkg_b]);
+        let graph =
+            DependencyGraph::from_installed_packages(&installed_packages);
+
+        let new_pkg_a =
+            create_package("pkgA", "2.0", None, None, None, None);
+        let result = graph.is_packages_upgradable(&[new_pkg_a]);
+
+        if let Err(InstallError::BreaksDependents {
+            ref dependents, ..
+        }) = result
+        {
+            assert_eq!(dependents.len(), 1);
+            assert_eq!(dependents[0].0, "pkgB");
+        } else {
+            panic!("result was not `InstallError::BreaksDependents`")
+        }
+    }
+
+    #[test]
+    fn test_resolver_selects_highest_satisfying_version() {
+        // テストの目的: リゾルバが範囲を満たす最も新しいバージョンを選択するか
+        let pkg_b = create_package(
+            "pkgB",
+            "1.0",
+            Some(vec![vec![PackageRange {
+                name: "pkgA".to_string(),
+                range: VersionRange::from_str(">= 1.0, < 2.0").unwrap(),
+            }]]),
+            None,
+            None,
+            None,
+        );
+        let available = vec![
+            create_package("pkgA", "1.0", None, None, None, None),
+            create_package("pkgA", "1.5", None, None, None, None),
+            create_package("pkgA", "2.0", None, None, None, None),
+        ];
+        let graph = DependencyGraph::from_installed_packages(
+            &setup_package_list(vec![]),
+        );
+
+        let plan = Resolver::new(&graph, &available)
+            .resolve(&[pkg_b], ResolveOptions::default())
+            .unwrap();
+        let names = plan
+            .iter()
+            .map(|pkg| {
+                format!(
+                    "{}-{}",
+                    pkg.about.package.name, pkg.about.package.version
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["pkgA-1.5", "pkgB-1.0"]);
+    }
+
+    #[test]
+    fn test_resolver_backtracks_on_conflict() {
+        // テストの目的: 競合する候補を選んだ場合に別の候補へバックトラックするか
+        let pkg_c = create_package(
+            "pkgC",
+            "1.0",
+            Some(vec![
+                vec![PackageRange {
+                    name: "pkgA".to_string(),
+                    range: VersionRange::from_str(">= 1.0").unwrap(),
+                }],
+                vec![PackageRange {
+                    name: "pkgB".to_string(),
+                    range: VersionRange::from_str(">= 1.0").unwrap(),
+                }],
+            ]),
+            None,
+            None,
+            None,
+        );
+        let available = vec![
+            create_package("pkgA", "1.0", None, None, None, None),
+            create_package(
+                "pkgA",
+                "2.0",
+                None,
+                Some(vec![PackageRange {
+                    name: "pkgB".to_string(),
+                    range: VersionRange::from_str(">= 1.0").unwrap(),
+                }]),
+                None,
+                None,
+            ),
+            create_package("pkgB", "1.0", None, None, None, None),
+        ];
+        let graph = DependencyGraph::from_installed_packages(
+            &setup_package_list(vec![]),
+        );
+
+        let plan = Resolver::new(&graph, &available)
+            .resolve(&[pkg_c], ResolveOptions::default())
+            .unwrap();
+        let pkg_a = plan
+            .iter()
+            .find(|pkg| pkg.about.package.name == "pkgA")
+            .unwrap();
+        assert_eq!(pkg_a.about.package.version.to_string(), "1.0");
+        assert!(plan.iter().any(|pkg| pkg.about.package.name == "pkgB"));
+    }
+
+    #[test]
+    fn test_resolver_uses_virtual_provider() {
+        // テストの目的: 仮想パッケージへの依存が提供元のパッケージで解決されるか
+        let pkg_b = create_package(
+            "pkgB",
+            "1.0",
+            Some(vec![vec![PackageRange {
+                name: "virtA".to_string(),
+                range: VersionRange::from_str(">= 1.0").unwrap(),
+            }]]),
+            None,
+            None,
+            None,
+        );
+        let available = vec![create_package(
+            "pkgA",
+            "1.0",
+            None,
+            None,
+            Some(vec![PackageVersion {
+                name: "virtA".to_string(),
+                version: Version::from_str("1.0").unwrap(),
+            }]),
+            None,
+        )];
+        let graph = DependencyGraph::from_installed_packages(
+            &setup_package_list(vec![]),
+        );
+
+        let plan = Resolver::new(&graph, &available)
+            .resolve(&[pkg_b], ResolveOptions::default())
+            .unwrap();
+        assert_eq!(plan[0].about.package.name, "pkgA");
+    }
+
+    #[test]
+    fn test_resolver_fails_without_candidates() {
+        // テストの目的: 候補が存在しない場合に利用可能なバージョンを含む説明が返されるか
+        let pkg_b = create_package(
+            "pkgB",
+            "1.0",
+            Some(vec![vec![PackageRange {
+                name: "pkgA".to_string(),
+                range: VersionRange::from_str(">= 2.0").unwrap(),
+            }]]),
+            None,
+            None,
+            None,
+        );
+        let available =
+            vec![create_package("pkgA", "1.0", None, None, None, None)];
+        let graph = DependencyGraph::from_installed_packages(
+            &setup_package_list(vec![]),
+        );
+
+        let result = Resolver::new(&graph, &available)
+            .resolve(&[pkg_b], ResolveOptions::default());
+        let Err(InstallError::Unsatisfiable { derivation }) = result
+        else {
+            panic!("result was not `InstallError::Unsatisfiable`")
+        };
+        let Derivation::Derived { package, causes, .. } =
+            derivation.as_ref()
+        else {
+            panic!("derivation was not `Derivation::Derived`")
+        };
+        assert_eq!(package.name, "pkgB");
+        assert!(matches!(
+            causes.as_slice(),
+            [Derivation::External(Incompatibility::NoVersions { available, .. })]
+                if available.len() == 1
+        ));
+    }
+
+    #[test]
+    fn test_resolver_explains_conflict_with_installed() {
+        // テストの目的: 依存先がインストール済みのパッケージと競合する場合に、その経緯が説明されるか
+        let pkg_c = create_package("pkgC", "1.2", None, None, None, None);
+        let pkg_a = create_package(
+            "pkgA",
+            "2.0",
+            Some(vec![vec![PackageRange {
+                name: "pkgB".to_string(),
+                range: VersionRange::from_str(">= 3.0").unwrap(),
+            }]]),
+            None,
+            None,
+            None,
+        );
+        let available = vec![create_package(
+            "pkgB",
+            "3.0",
+            None,
+            Some(vec![PackageRange {
+                name: "pkgC".to_string(),
+                range: VersionRange::from_str("< 2.0").unwrap(),
+            }]),
+            None,
+            None,
+        )];
+        let graph = DependencyGraph::from_installed_packages(
+            &setup_package_list(vec![pkg_c]),
+        );
+
+        let result = Resolver::new(&graph, &available)
+            .resolve(&[pkg_a], ResolveOptions::default());
+        let Err(error @ InstallError::Unsatisfiable { .. }) = result
+        else {
+            panic!("result was not `InstallError::Unsatisfiable`")
+        };
+        let message = error.to_string();
+        assert!(message.contains("pkgA 2.0 depends on pkgB (>= 3.0)"));
+        assert!(message.contains(
+            "pkgB 3.0 conflicts with pkgC 1.2, which is installed"
+        ));
+        assert!(message.contains("so pkgA 2.0 cannot be installed"));
+    }
+
+    #[test]
+    fn test_resolver_installs_satisfiable_recommends() {
+        // テストの目的: 満たせる推奨パッケージのみが追加され、満たせないものは無視されるか
+        let mut pkg_b =
+            create_package("pkgB", "1.0", None, None, None, None);
+        pkg_b.relation.recommends = vec![
+            vec![PackageRange {
+                name: "pkgA".to_string(),
+                range: VersionRange::from_str(">= 1.0").unwrap(),
+            }],
+            vec![PackageRange {
+                name: "pkgMissing".to_string(),
+                range: VersionRange::from_str(">= 1.0").unwrap(),
+            }],
+        ];
+        let available =
+            vec![create_package("pkgA", "1.0", None, None, None, None)];
+        let graph = DependencyGraph::from_installed_packages(
+            &setup_package_list(vec![]),
+        );
+        let resolver = Resolver::new(&graph, &available);
+
+        let plan = resolver
+            .resolve(&[pkg_b.clone()], ResolveOptions::default())
+            .unwrap();
+        assert_eq!(plan.len(), 1);
+
+        let opts = ResolveOptions {
+            install_recommends: true,
+            install_suggests: false,
+        };
+        let plan = resolver.resolve(&[pkg_b], opts).unwrap();
+        assert_eq!(plan.len(), 2);
+        assert!(plan.iter().any(|pkg| pkg.about.package.name == "pkgA"));
+    }
+
+    #[test]
+    fn test_get_orphaned_packages() {
+        // テストの目的: 手動インストールのパッケージから到達できない自動インストールのパッケージが、
+        // 依存元から順に返されるか
+        let depends_on = |name: &str| {
+            Some(vec![vec![PackageRange {
+                name: name.to_string(),
+                range: VersionRange::from_str(">= 1.0").unwrap(),
+            }]])
+        };
+        let pkg_a = create_package(
+            "pkgA",
+            "1.0",
+            depends_on("pkgB"),
+            None,
+            None,
+            None,
+        );
+        let pkg_b = create_package("pkgB", "1.0", None, None, None, None);
+        let pkg_c = create_package(
+            "pkgC",
+            "1.0",
+            depends_on("pkgD"),
+            None,
+            None,
+            None,
+        );
+        let pkg_d = create_package("pkgD", "1.0", None, None, None, None);
+        let mut installed_packages =
+            setup_package_list(vec![pkg_a, pkg_b, pkg_d, pkg_c]);
+        for installed in installed_packages.installed_packages.iter_mut() {
+            if installed.info.about.package.name != "pkgA" {
+                installed.install_reason =
+                    InstallReason::Dependency { of: None };
+            }
+        }
+        let graph =
+            DependencyGraph::from_installed_packages(&installed_packages);
+
+        let orphans = graph
+            .get_orphaned_packages()
+            .iter()
+            .map(|pkg| pkg.about.package.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(orphans, vec!["pkgC", "pkgD"]);
+    }
+
+    #[test]
+    fn test_get_reverse_dependencies_transitive() {
+        // テストの目的: 推移的な逆依存がすべて、依存元から順に返されるか
+        // また、他の選択肢で満たされるORグループを持つパッケージは含まれないか
+        let depends_on = |names: &[&str]| {
+            Some(vec![
+                names
+                    .iter()
+                    .map(|name| PackageRange {
+                        name: name.to_string(),
+                        range: VersionRange::from_str(">= 1.0").unwrap(),
+                    })
+                    .collect(),
+            ])
+        };
+        let pkg_a = create_package("pkgA", "1.0", None, None, None, None);
+        let pkg_b = create_package(
+            "pkgB",
+            "1.0",
+            depends_on(&["pkgA"]),
+            None,
+            None,
+            None,
+        );
+        let pkg_c = create_package(
+            "pkgC",
+            "1.0",
+            depends_on(&["pkgB"]),
+            None,
+            None,
+            None,
+        );
+        let pkg_d = create_package(
+            "pkgD",
+            "1.0",
+            depends_on(&["pkgA", "pkgX"]),
+            None,
+            None,
+            None,
+        );
+        let pkg_x = create_package("pkgX", "1.0", None, None, None, None);
+        let installed_packages =
+            setup_package_list(vec![pkg_c, pkg_a, pkg_d, pkg_b, pkg_x]);
+        let graph =
+            DependencyGraph::from_installed_packages(&installed_packages);
+
+        let dependents = graph
+            .get_reverse_dependencies(&["pkgA"])
+            .iter()
+            .map(|pkg| pkg.about.package.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(dependents, vec!["pkgC", "pkgB"]);
+    }
+
+    #[test]
+    fn test_is_packages_removable_lists_all_dependents() {
+        // テストの目的: 削除できない場合に、すべての依存元と満たされなくなるグループが報告されるか
+        let pkg_a = create_package("pkgA", "1.0", None, None, None, None);
+        let depends_on_a = Some(vec![vec![PackageRange {
+            name: "pkgA".to_string(),
+            range: VersionRange::from_str(">= 1.0").unwrap(),
+        }]]);
+        let pkg_b = create_package(
+            "pkgB",
+            "1.0",
+            depends_on_a.clone(),
+            None,
+            None,
+            None,
+        );
+        let pkg_c =
+            create_package("pkgC", "1.0", depends_on_a, None, None, None);
+        let installed_packages =
+            setup_package_list(vec![pkg_a, pkg_b, pkg_c]);
+        let graph =
+            DependencyGraph::from_installed_packages(&installed_packages);
+
+        let result = graph.is_packages_removable(&["pkgA"]);
+        let Err(RemoveError::DependencyOfOtherPackages {
+            packages,
+            dependents,
+        }) = result
+        else {
+            panic!(
+                "result was not `RemoveError::DependencyOfOtherPackages`"
+            )
+        };
+        assert_eq!(packages, vec!["pkgA"]);
+        let names = dependents
+            .iter()
+            .map(|(name, group)| {
+                assert_eq!(group[0].name, "pkgA");
+                name.as_str()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["pkgB", "pkgC"]);
+    }
 }
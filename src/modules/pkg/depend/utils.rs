@@ -1,4 +1,4 @@
-use crate::modules::pkg::RelationData;
+use crate::modules::pkg::{PackageData, PackageRange, RelationData};
 use crate::utils::shell;
 
 pub fn are_depend_cmds_available(relation: &RelationData) -> bool {
@@ -13,3 +13,23 @@ pub fn get_missing_depend_cmds(relation: &RelationData) -> Vec<String> {
         .cloned()
         .collect()
 }
+
+/// パッケージが依存関係を満たすかどうかを返します。
+///
+/// パッケージ自身の名前とバージョン、または提供する仮想パッケージのいずれかが
+/// 依存関係の名前と範囲に一致する場合に満たすとみなします。
+///
+/// # Arguments
+/// * `package` - 確認するパッケージ。
+/// * `dep` - 依存関係。
+///
+/// # Returns
+/// 依存関係を満たす場合は`true`。
+pub fn provides(package: &PackageData, dep: &PackageRange) -> bool {
+    (package.about.package.name == dep.name
+        && dep.range.compare(&package.about.package.version))
+        || package.relation.virtuals.iter().any(|virtual_pkg| {
+            virtual_pkg.name == dep.name
+                && dep.range.compare(&virtual_pkg.version)
+        })
+}
@@ -5,11 +5,22 @@ use super::super::pkg;
 use super::super::project::ExecMode;
 use super::depend;
 use crate::modules::pkg::PackageData;
+use crate::modules::pkg::depend::InstallError;
+use crate::modules::pkg::list::{
+    InstallReason, InstalledPackageData, PackageListData,
+    REMOVED_MARKER_FILEPATH,
+};
 use crate::modules::pkg::lock::LockManager;
+use crate::modules::pkg::manifest::{
+    FileManifest, ManifestEntry, Snapshot,
+};
+use crate::modules::pkg::signature::{self, Verification};
+use crate::modules::pkg::transaction::{self, Transaction};
 use crate::modules::project;
 use crate::modules::system::path;
 use crate::utils::archive::extract_archive;
 use crate::utils::error::Error;
+use crate::utils::version::Version;
 use chrono::Local;
 use std::collections::HashMap;
 use std::env;
@@ -18,24 +29,49 @@ use std::path::Path;
 use std::path::PathBuf;
 use tempfile::tempdir;
 
+/// パッケージインストールのオプションを定義する構造体です。
+#[derive(Default, Clone, Copy)]
+pub struct InstallOptions {
+    /// インストールモード（ローカルまたはグローバル）。
+    pub install_mode: ExecMode,
+    /// インストール済みのバージョンより古いバージョンのインストールを許可するかどうか。
+    pub allow_downgrade: bool,
+    /// 推奨される依存関係（`recommends`）もインストールするかどうか。
+    pub install_recommends: bool,
+    /// 提案された依存関係（`suggests`）もインストールするかどうか。
+    pub install_suggests: bool,
+    /// 署名されていない、または署名が正しくないパッケージのインストールを許可するかどうか。
+    pub allow_unsigned: bool,
+}
+
 /// 指定されたパッケージアーカイブをシステムにインストールします。
 ///
 /// パッケージアーカイブを一時ディレクトリに展開し、指定されたインストールモード（ローカルまたはグローバル）
 /// に基づいて適切な場所にファイルを配置します。その後、パッケージリストを更新します。
+/// すべてのパッケージは1つのトランザクション内でインストールされ、
+/// いずれかのパッケージで失敗した場合はそれまでの変更がすべて取り消されます。
+///
+/// 既にインストールされているパッケージはアップグレードとして扱われ、
+/// 新しいバージョンでも他のパッケージの依存関係が満たされることを確認してから置き換えます。
+/// ダウングレードは`allow_downgrade`が指定された場合のみ許可されます。
+///
+/// 不足している依存関係は設定されたリポジトリから補われます。
+/// `install_recommends`や`install_suggests`が指定された場合は、それらも満たせる範囲で追加されます。
+/// インストール後、満たされていない`suggests`があれば表示します。
 ///
 /// # Arguments
 /// * `file_paths` - インストールするパッケージアーカイブへのパスのベクター。
-/// * `install_mode` - インストールモード（`ExecMode::Local`または`ExecMode::Global`）。
+/// * `opts` - インストールオプション。
 ///
 /// # Returns
 /// `Ok(())` パッケージが正常にインストールされた場合。
 /// `Err(Error)` ファイルが見つからない、アーカイブの展開、ファイルの配置、またはパッケージリストの更新中にエラーが発生した場合。
 pub fn install(
     file_paths: &Vec<PathBuf>,
-    install_mode: ExecMode,
+    opts: InstallOptions,
 ) -> Result<(), Error> {
     use super::depend::graph::DependencyGraphOperations;
-    use super::list;
+    let install_mode = opts.install_mode;
     pub trait PackageMetadata {
         /// パスからパッケージのメタデータを取得します。
         ///
@@ -61,6 +97,8 @@ pub fn install(
         pub path: PathBuf,
         /// 解析されたパッケージデータ。
         pub data: PackageData, // pkg::PackageData を格納
+        /// パッケージをインストールする理由。
+        pub install_reason: InstallReason,
     }
 
     let mut package_infos: Vec<PackageInfo> =
@@ -74,8 +112,11 @@ pub fn install(
         }
 
         let package_data = path.metadata()?;
-        let pkg_info =
-            PackageInfo { path: path.to_path_buf(), data: package_data };
+        let pkg_info = PackageInfo {
+            path: path.to_path_buf(),
+            data: package_data,
+            install_reason: InstallReason::Manual,
+        };
         package_info_map.insert(
             pkg_info.data.about.package.name.clone(),
             pkg_info.clone(),
@@ -83,25 +124,98 @@ pub fn install(
         package_infos.push(pkg_info);
     }
 
-    let installed_packages = match install_mode {
-        ExecMode::Global => list::get_global(),
-        ExecMode::Local => list::get_local(),
-    }?;
+    let lock_manager =
+        LockManager::new(matches!(install_mode, ExecMode::Global));
+    let _lock = lock_manager.acquire_lock()?;
+
+    let mut installed_packages =
+        pkg::list::database(install_mode).load_all()?;
 
-    let base_graph = depend::DependencyGraph::from_installed_packages(
+    let installed_graph = depend::DependencyGraph::from_installed_packages(
         &installed_packages,
     );
 
+    let mut upgrading_package_data = Vec::new();
+    for info in &package_infos {
+        let pkg_name = &info.data.about.package.name;
+        let Some(installed) = installed_packages
+            .installed_packages
+            .iter()
+            .find(|pkg| pkg.info.about.package.name == *pkg_name)
+        else {
+            continue;
+        };
+        let installed_version = &installed.info.about.package.version;
+        let requested_version = &info.data.about.package.version;
+        if requested_version < installed_version && !opts.allow_downgrade {
+            return Err(InstallError::DowngradeNotAllowed {
+                package: pkg_name.clone(),
+                installed: installed_version.to_string(),
+                requested: requested_version.to_string(),
+            }
+            .into());
+        }
+        upgrading_package_data.push(info.data.clone());
+    }
+    installed_graph.is_packages_upgradable(&upgrading_package_data)?;
+
+    // アップグレード対象の旧バージョンを除いたグラフで依存関係を解決する
+    let upgrading_names = upgrading_package_data
+        .iter()
+        .map(|pkg| pkg.about.package.name.as_str())
+        .collect::<Vec<_>>();
+    let base_graph = installed_graph.without_packages(&upgrading_names);
+
     let installing_package_data: Vec<PackageData> =
         package_infos.iter().map(|pi| pi.data.clone()).collect();
 
-    let sorted_package_data = base_graph
-        .topological_sort_packages_for_install(&installing_package_data)?;
+    // 不足している依存関係がある場合のみ、リポジトリから候補を読み込んで解決する
+    let resolve_options = depend::ResolveOptions {
+        install_recommends: opts.install_recommends,
+        install_suggests: opts.install_suggests,
+    };
+    let requested_graph =
+        base_graph.with_additional_packages(&installing_package_data);
+    let needs_resolution = installing_package_data.iter().any(|pkg| {
+        !requested_graph.get_missing_dependencies(pkg).is_empty()
+            || resolve_options.optional_groups(pkg).any(|group| {
+                !requested_graph
+                    .get_unmet_groups(std::slice::from_ref(group))
+                    .is_empty()
+            })
+    });
+    let available_packages = if needs_resolution {
+        crate::modules::repo::available_packages(install_mode)?
+    } else {
+        Vec::new()
+    };
+    let available_package_data: Vec<PackageData> =
+        available_packages.iter().map(|(_, data)| data.clone()).collect();
+
+    let sorted_package_data =
+        depend::Resolver::new(&base_graph, &available_package_data)
+            .resolve(&installing_package_data, resolve_options)?;
 
     let sorted_package_infos: Vec<PackageInfo> = sorted_package_data
         .iter()
         .filter_map(|pkg_data| {
-            package_info_map.remove(&pkg_data.about.package.name)
+            let name = &pkg_data.about.package.name;
+            package_info_map.remove(name).or_else(|| {
+                available_packages
+                    .iter()
+                    .find(|(_, data)| {
+                        data.about.package.name == *name
+                            && data.about.package.version
+                                == pkg_data.about.package.version
+                    })
+                    .map(|(path, data)| PackageInfo {
+                        path: path.clone(),
+                        data: data.clone(),
+                        install_reason: InstallReason::Dependency {
+                            of: find_requirer(data, &sorted_package_data),
+                        },
+                    })
+            })
         })
         .collect();
 
@@ -110,148 +224,439 @@ pub fn install(
 
     temp_graph.is_packages_installable(sorted_package_data.clone())?;
 
-    let file_paths: Vec<PathBuf> = sorted_package_infos
-        .iter()
-        .map(|info| info.path.clone())
-        .collect();
+    let mut transaction =
+        Transaction::begin(install_mode, &installed_packages)?;
+    let result = sorted_package_infos.iter().try_for_each(|info| {
+        install_package(
+            &info.path,
+            &info.data,
+            info.install_reason.clone(),
+            install_mode,
+            opts.allow_unsigned,
+            &mut installed_packages,
+            &mut transaction,
+        )
+    });
+    match result {
+        Ok(()) => transaction.commit()?,
+        Err(e) => {
+            log::error!("Installation failed: {}", e);
+            if let Err(rollback_error) = transaction.rollback() {
+                log::error!("Rollback incomplete: {}", rollback_error);
+            }
+            return Err(e);
+        }
+    }
 
-    let lock_manager =
-        LockManager::new(matches!(install_mode, ExecMode::Global));
-    lock_manager.acquire_lock()?;
+    report_unmet_suggests(&temp_graph, &sorted_package_data);
+    Ok(())
+}
 
-    for file_path in file_paths {
-        let target_path = env::current_dir()?.join(file_path);
+/// 自動的に追加されたパッケージを必要としたパッケージの名前を返します。
+///
+/// # Arguments
+/// * `package` - 自動的に追加されたパッケージ。
+/// * `plan` - インストールするすべてのパッケージ。
+fn find_requirer(
+    package: &PackageData,
+    plan: &[PackageData],
+) -> Option<String> {
+    plan.iter()
+        .find(|candidate| {
+            let relation = &candidate.relation;
+            relation
+                .depend
+                .iter()
+                .chain(&relation.recommends)
+                .chain(&relation.suggests)
+                .flatten()
+                .any(|dep| depend::provides(package, dep))
+        })
+        .map(|candidate| candidate.about.package.name.clone())
+}
 
-        if !target_path.is_file() {
-            log::error!(
-                "Couldn't find target file: {}",
-                target_path.display()
+/// インストールしたパッケージの`suggests`のうち、満たされていないものを表示します。
+///
+/// # Arguments
+/// * `graph` - インストール後の依存関係グラフ。
+/// * `installed` - インストールしたパッケージ。
+fn report_unmet_suggests(
+    graph: &depend::DependencyGraph,
+    installed: &[PackageData],
+) {
+    for package in installed {
+        let unmet = graph.get_unmet_groups(&package.relation.suggests);
+        if !unmet.is_empty() {
+            log::warn!(
+                "{} suggests: {}",
+                package.about.package.name,
+                depend::incompatibility::format_groups(&unmet)
             );
-            return Err(Error::from(std::io::ErrorKind::NotFound));
         }
+    }
+}
 
-        let temp_dir = tempdir()?;
-        log::debug!(
-            "Created temp directory at {}",
-            temp_dir.path().display()
-        );
-
-        let pkg_archive_in_temp = temp_dir.path().join(
-            target_path.file_name().ok_or_else(|| {
-                std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    "Target path has no filename",
-                )
-            })?,
-        );
+/// 展開されたパッケージの署名を検証します。
+///
+/// 署名が正しくないパッケージは、`allow_unsigned`が指定されない限り拒否されます。
+/// 署名されていないパッケージや信頼されていない鍵で署名されたパッケージは、
+/// グローバルモードでは拒否され、ローカルモードでは警告を表示してインストールを続けます。
+///
+/// # Arguments
+/// * `pkg_dir` - 展開されたパッケージのディレクトリ。
+/// * `pkg_name` - パッケージ名。
+/// * `install_mode` - インストールモード。
+/// * `allow_unsigned` - 署名を検証できないパッケージのインストールを許可するかどうか。
+///
+/// # Returns
+/// `Ok(())` パッケージをインストールしてよい場合。
+/// `Err(Error)` パッケージが拒否された場合、またはファイルの読み込みに失敗した場合。
+fn check_signature(
+    pkg_dir: &Path,
+    pkg_name: &str,
+    install_mode: ExecMode,
+    allow_unsigned: bool,
+) -> Result<(), Error> {
+    let verification = signature::verify_dir(
+        pkg_dir,
+        &signature::trusted_keys_dirs(install_mode),
+    )?;
+    let (problem, refuse) = match verification {
+        Verification::Trusted { key_name } => {
+            log::info!(
+                "Package '{}' is signed by trusted key '{}'",
+                pkg_name,
+                key_name
+            );
+            return Ok(());
+        }
+        Verification::Invalid { reason } => {
+            (format!("has an invalid signature ({})", reason), true)
+        }
+        Verification::Unsigned => (
+            "is not signed".to_string(),
+            matches!(install_mode, ExecMode::Global),
+        ),
+        Verification::Untrusted { public_key } => (
+            format!("is signed by an untrusted key {}", public_key),
+            matches!(install_mode, ExecMode::Global),
+        ),
+    };
+    if refuse && !allow_unsigned {
+        return Err(Error::from(format!(
+            "Package '{}' {}. Pass --allow-unsigned to install it anyway.",
+            pkg_name, problem
+        )));
+    }
+    log::warn!("Package '{}' {}", pkg_name, problem);
+    Ok(())
+}
 
-        fs::copy(&target_path, &pkg_archive_in_temp)?;
-        log::debug!(
-            "Copied package to temp directory: {}",
-            pkg_archive_in_temp.display()
-        );
+/// 1つのパッケージアーカイブをトランザクション内でインストールします。
+///
+/// アーカイブを一時ディレクトリに展開し、既存のパッケージディレクトリをバックアップしてから
+/// 最終的なインストール先へ配置し、そのディレクトリでインストールスクリプトを実行します。
+/// 行われた変更はすべて`transaction`に記録されます。
+///
+/// # Arguments
+/// * `file_path` - インストールするパッケージアーカイブへのパス。
+/// * `pkg_data` - アーカイブから読み込まれたパッケージデータ。
+/// * `install_reason` - パッケージをインストールする理由。
+/// * `install_mode` - インストールモード。
+/// * `allow_unsigned` - 署名を検証できないパッケージのインストールを許可するかどうか。
+/// * `installed_packages` - インストール済みパッケージ。インストールしたパッケージで更新されます。
+/// * `transaction` - 変更を記録するトランザクション。
+///
+/// # Returns
+/// `Ok(())` パッケージが正常にインストールされた場合。
+/// `Err(Error)` 展開、ファイルの配置、スクリプトの実行、またはパッケージリストの更新中にエラーが発生した場合。
+fn install_package(
+    file_path: &Path,
+    pkg_data: &PackageData,
+    install_reason: InstallReason,
+    install_mode: ExecMode,
+    allow_unsigned: bool,
+    installed_packages: &mut PackageListData,
+    transaction: &mut Transaction,
+) -> Result<(), Error> {
+    let target_path = env::current_dir()?.join(file_path);
 
-        log::debug!(
-            "Extracting archive from {} to {}",
-            pkg_archive_in_temp.display(),
-            temp_dir.path().display()
+    if !target_path.is_file() {
+        log::error!(
+            "Couldn't find target file: {}",
+            target_path.display()
         );
-        extract_archive(
-            &pkg_archive_in_temp,
-            &temp_dir.path().to_path_buf(),
-        )?;
-        fs::remove_file(&pkg_archive_in_temp)?;
-
-        let install_process_result = {
-            let original_cwd = env::current_dir()?;
-            env::set_current_dir(temp_dir.path())?;
-            log::debug!(
-                "Changed current directory to {}",
-                temp_dir.path().display()
-            );
+        return Err(Error::from(std::io::ErrorKind::NotFound));
+    }
 
-            let result = installation_process(install_mode);
+    let temp_dir = tempdir()?;
+    log::debug!("Created temp directory at {}", temp_dir.path().display());
 
-            env::set_current_dir(&original_cwd)?;
-            log::debug!(
-                "Restored current directory to {}",
-                original_cwd.display()
-            );
-            result
-        };
-        let pkg_data = install_process_result?;
-
-        let final_destination_base_dir: PathBuf = match install_mode {
-            ExecMode::Local => path::local::packages_dirpath(),
-            ExecMode::Global => {
-                let list_file_path = path::global::packageslist_filepath();
-                list_file_path.parent().ok_or_else(|| {
-                std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    format!(
-                        "Global packages list file path '{}' does not have a parent directory.",
-                        list_file_path.display()
-                    ),
-                )
-            })?.to_path_buf()
-            }
-        };
+    let pkg_archive_in_temp = temp_dir.path().join(
+        target_path.file_name().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Target path has no filename",
+            )
+        })?,
+    );
 
-        fs::create_dir_all(&final_destination_base_dir)?;
-        let pkg_name = pkg_data.about.package.name.clone();
-        let final_pkg_destination_path =
-            final_destination_base_dir.join(&pkg_name);
+    fs::copy(&target_path, &pkg_archive_in_temp)?;
+    log::debug!(
+        "Copied package to temp directory: {}",
+        pkg_archive_in_temp.display()
+    );
 
-        if final_pkg_destination_path.exists() {
-            if final_pkg_destination_path.is_dir() {
-                fs::remove_dir_all(&final_pkg_destination_path)?;
-            } else {
-                fs::remove_file(&final_pkg_destination_path)?;
-            }
+    log::debug!(
+        "Extracting archive from {} to {}",
+        pkg_archive_in_temp.display(),
+        temp_dir.path().display()
+    );
+    extract_archive(&pkg_archive_in_temp, temp_dir.path())?;
+    fs::remove_file(&pkg_archive_in_temp)?;
+    check_signature(
+        temp_dir.path(),
+        &pkg_data.about.package.name,
+        install_mode,
+        allow_unsigned,
+    )?;
+
+    // パッケージ名はパッケージディレクトリの名前になるため、ディレクトリの外を指す名前は拒否する
+    pkg::list::validate_package_name(&pkg_data.about.package.name)?;
+    let final_destination_base_dir =
+        pkg::list::packages_dirpath(install_mode);
+
+    fs::create_dir_all(&final_destination_base_dir)?;
+    let pkg_name = pkg_data.about.package.name.clone();
+    let final_pkg_destination_path =
+        final_destination_base_dir.join(&pkg_name);
+
+    let previous_version = installed_packages
+        .installed_packages
+        .iter()
+        .find(|pkg| pkg.info.about.package.name == pkg_name)
+        .map(|pkg| pkg.info.about.package.version.clone());
+    let kept_entries = match &previous_version {
+        Some(previous_version) => {
+            log_version_change(
+                &pkg_name,
+                previous_version,
+                &pkg_data.about.package.version,
+            );
+            replace_previous_version(
+                &pkg_name,
+                previous_version,
+                temp_dir.path(),
+                &final_pkg_destination_path,
+                install_mode,
+                transaction,
+            )?
         }
+        None => inherit_removed_version(
+            &pkg_name,
+            &final_pkg_destination_path,
+            |path| transaction.preserve(path),
+        )?,
+    };
 
-        fs::create_dir_all(&final_pkg_destination_path)?;
-        for entry in fs::read_dir(temp_dir.path())? {
-            let entry = entry?;
-            let target_path =
-                final_pkg_destination_path.join(entry.file_name());
-            if entry.path().is_dir() {
-                fs::create_dir_all(&target_path)?;
-                copy_dir_all(&entry.path(), &target_path)?;
-            } else {
-                fs::copy(entry.path(), &target_path)?;
-            }
+    transaction.backup(&final_pkg_destination_path)?;
+    transaction.create_dir(&final_pkg_destination_path)?;
+    for entry in fs::read_dir(temp_dir.path())? {
+        let entry = entry?;
+        let target_path =
+            final_pkg_destination_path.join(entry.file_name());
+        if entry.path().is_dir() {
+            fs::create_dir_all(&target_path)?;
+            copy_dir_all(&entry.path(), &target_path)?;
+        } else {
+            fs::copy(entry.path(), &target_path)?;
         }
+    }
 
+    transaction.start_install(&pkg_name, &final_pkg_destination_path)?;
+    let install_process_result = {
+        let original_cwd = env::current_dir()?;
+        env::set_current_dir(&final_pkg_destination_path)?;
         log::debug!(
-            "Successfully installed package to {}",
+            "Changed current directory to {}",
             final_pkg_destination_path.display()
         );
 
-        let installed_package_data = pkg::list::InstalledPackageData {
-            info: pkg_data,
-            last_modified: Local::now(),
-        };
+        let result =
+            installation_process(install_mode, installed_packages);
 
-        match install_mode {
-            ExecMode::Local => {
-                pkg::list::add_pkg_local(installed_package_data)?;
-                log::debug!("Added package '{}' to local list.", pkg_name);
-            }
-            ExecMode::Global => {
-                pkg::list::add_pkg_global(installed_package_data)?;
-                log::debug!(
-                    "Added package '{}' to global list.",
-                    pkg_name
-                );
-            }
+        env::set_current_dir(&original_cwd)?;
+        log::debug!(
+            "Restored current directory to {}",
+            original_cwd.display()
+        );
+        result
+    };
+    let (pkg_data, mut file_manifest) = install_process_result?;
+    // ロールバック時にスクリプトが作成したファイルを削除できるように、ステップを完了する前に保存する
+    file_manifest.save(&final_pkg_destination_path)?;
+    transaction.finish_step()?;
+
+    // 旧バージョンから引き継いだ変更済みファイルは、設定ファイルとして新しいバージョンの所有とする
+    for mut entry in kept_entries {
+        if !file_manifest.files.iter().any(|e| e.path == entry.path) {
+            entry.config = true;
+            file_manifest.files.push(entry);
         }
     }
 
-    lock_manager.release_lock()?;
+    file_manifest.record_package_files(&final_pkg_destination_path)?;
+    file_manifest.save(&final_pkg_destination_path)?;
+    log::debug!(
+        "Recorded {} installed file(s) for '{}'",
+        file_manifest.files.len(),
+        pkg_name
+    );
+
+    log::debug!(
+        "Successfully installed package to {}",
+        final_pkg_destination_path.display()
+    );
+
+    let installed_package_data = InstalledPackageData {
+        info: pkg_data,
+        last_modified: Local::now(),
+        previous_version,
+        install_reason,
+    };
+
+    pkg::list::database(install_mode)
+        .put(installed_package_data.clone())?;
+    log::debug!(
+        "Added package '{}' to {} database.",
+        pkg_name,
+        install_mode
+    );
+    installed_packages
+        .installed_packages
+        .retain(|pkg| pkg.info.about.package.name != pkg_name);
+    installed_packages.installed_packages.push(installed_package_data);
     Ok(())
 }
 
+/// バージョンの変更内容をログに出力します。
+fn log_version_change(pkg_name: &str, from: &Version, to: &Version) {
+    if to > from {
+        log::info!("Upgrading '{}' from {} to {}", pkg_name, from, to);
+    } else if to < from {
+        log::info!("Downgrading '{}' from {} to {}", pkg_name, from, to);
+    } else {
+        log::info!("Reinstalling '{}' {}", pkg_name, to);
+    }
+}
+
+/// インストール済みの旧バージョンを新しいバージョンで置き換える準備をします。
+///
+/// 新しいパッケージが`ipak/scripts/upgrade.sh`を持つ場合はそれを実行し、
+/// 持たない場合は旧バージョンの削除スクリプトを実行します。
+/// その後、旧バージョンが配置したファイルのうち変更されていないものを削除します。
+/// 旧バージョンのファイルはスクリプトの実行前にトランザクションへ保存されるため、
+/// 新しいバージョンのインストールに失敗した場合は元の内容に戻されます。
+///
+/// # Arguments
+/// * `pkg_name` - パッケージ名。
+/// * `previous_version` - インストール済みの旧バージョン。
+/// * `staged_dir` - 新しいバージョンが展開されているディレクトリ。
+/// * `installed_dir` - 旧バージョンがインストールされているディレクトリ。
+/// * `install_mode` - インストールモード。
+/// * `transaction` - 変更を記録するトランザクション。
+///
+/// # Returns
+/// `Ok(Vec<ManifestEntry>)` 変更されていたため残された旧バージョンのファイル。
+/// `Err(std::io::Error)` スクリプトの実行、またはファイルの削除に失敗した場合。
+fn replace_previous_version(
+    pkg_name: &str,
+    previous_version: &Version,
+    staged_dir: &Path,
+    installed_dir: &Path,
+    install_mode: ExecMode,
+    transaction: &mut Transaction,
+) -> Result<Vec<ManifestEntry>, std::io::Error> {
+    if !installed_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    // スクリプトが旧バージョンのファイルを書き換え・削除する前に、ロールバック用の複製を保存する
+    let previous_manifest = FileManifest::load(installed_dir)?;
+    for entry in &previous_manifest.files {
+        transaction.preserve(&entry.path)?;
+    }
+
+    transaction.start_remove(pkg_name, installed_dir)?;
+    if staged_dir.join("ipak/scripts/upgrade.sh").is_file() {
+        let opts = project::install::InstallOptions {
+            install_mode,
+            install_shell: project::ExecShell::default(),
+        };
+        transaction::run_in_dir(staged_dir, || {
+            project::install::upgrade(opts, previous_version)
+        })?;
+    } else if installed_dir.join("ipak/scripts/remove.sh").is_file() {
+        let opts = project::remove::RemoveOptions {
+            remove_mode: install_mode,
+            remove_shell: project::ExecShell::default(),
+        };
+        transaction::run_in_dir(installed_dir, || {
+            project::remove::remove(opts)
+        })?;
+    }
+    transaction.finish_step()?;
+
+    inherit_modified_files(previous_manifest)
+}
+
+/// `pkg remove`で削除され、ディレクトリだけが残されている旧バージョンのファイルを引き継ぎます。
+///
+/// `pkg remove`は変更されたファイルを設定ファイルとして残し、パッケージディレクトリに`ipak/removed`を作成します。
+/// そのようなディレクトリがある場合は、旧バージョンがインストールされている場合と同様に、
+/// 残されたファイルを`preserve`で保存してから新しいバージョンに引き継ぎます。
+/// 削除スクリプトは削除時に実行済みのため、ここでは実行しません。
+///
+/// # Arguments
+/// * `pkg_name` - パッケージ名。
+/// * `installed_dir` - 旧バージョンのパッケージディレクトリ。
+/// * `preserve` - ロールバック用にファイルを保存する関数。
+///
+/// # Returns
+/// `Ok(Vec<ManifestEntry>)` 削除時に残されたファイル。ディレクトリが`pkg remove`で残されたものでない場合は空。
+/// `Err(std::io::Error)` マニフェストの読み込み、ファイルの保存、または削除に失敗した場合。
+fn inherit_removed_version(
+    pkg_name: &str,
+    installed_dir: &Path,
+    mut preserve: impl FnMut(&Path) -> Result<(), std::io::Error>,
+) -> Result<Vec<ManifestEntry>, std::io::Error> {
+    if !installed_dir.join(REMOVED_MARKER_FILEPATH).is_file() {
+        return Ok(Vec::new());
+    }
+    log::info!(
+        "Reinstalling '{}' over the files kept when it was removed",
+        pkg_name
+    );
+    let previous_manifest = FileManifest::load(installed_dir)?;
+    for entry in &previous_manifest.files {
+        preserve(&entry.path)?;
+    }
+    inherit_modified_files(previous_manifest)
+}
+
+/// 旧バージョンのファイルのうち変更されていないものを削除し、変更されていたものを返します。
+fn inherit_modified_files(
+    previous_manifest: FileManifest,
+) -> Result<Vec<ManifestEntry>, std::io::Error> {
+    let kept_files = previous_manifest
+        .remove_files(true, |path| fs::remove_file(path))?;
+    Ok(previous_manifest
+        .files
+        .into_iter()
+        .filter(|entry| kept_files.contains(&entry.path))
+        .collect())
+}
+
 /// ディレクトリの内容を再帰的にコピーします。
 ///
 /// # Arguments
@@ -281,34 +686,51 @@ fn copy_dir_all(src: &PathBuf, dst: &Path) -> std::io::Result<()> {
 ///
 /// 依存関係グラフをチェックし、パッケージがインストール可能であれば、
 /// プロジェクトのインストールスクリプトを実行し、パッケージのメタデータを返します。
+/// スクリプトの実行前後でインストール先ディレクトリのスナップショットを取り、
+/// スクリプトが新たに作成したファイルをマニフェストとして記録します。
+/// スクリプトが失敗した場合は、それまでに作成されたファイルを削除します。既存のファイルは削除しません。
 ///
 /// # Arguments
 /// * `install_mode` - インストールモード。
+/// * `installed_packages` - インストール前のインストール済みパッケージ。
 ///
 /// # Returns
-/// `Ok(pkg::PackageData)` インストールされたパッケージのメタデータ。
+/// `Ok((pkg::PackageData, FileManifest))` インストールされたパッケージのメタデータとファイルマニフェスト。
 /// `Err(std::io::Error)` 依存関係の競合、またはインストールスクリプトの実行中にエラーが発生した場合。
 fn installation_process(
     install_mode: ExecMode,
-) -> Result<pkg::PackageData, std::io::Error> {
-    let installed_packages = match install_mode {
-        ExecMode::Local => pkg::list::get_local()?,
-        ExecMode::Global => pkg::list::get_global()?,
-    };
+    installed_packages: &PackageListData,
+) -> Result<(pkg::PackageData, FileManifest), std::io::Error> {
     let depend_graph = depend::DependencyGraph::from_installed_packages(
-        &installed_packages,
+        installed_packages,
     );
     let package_data = project::metadata::metadata()?; // Call once
+    let depend_graph =
+        depend_graph.without_packages(&[&package_data.about.package.name]);
     match depend_graph.is_packages_installable(vec![package_data.clone()])
     {
         Ok(()) => {
+            let install_roots = match install_mode {
+                ExecMode::Local => path::local::install_roots(),
+                ExecMode::Global => path::global::install_roots(),
+            };
+            let before = Snapshot::take(&install_roots);
             let opts = project::install::InstallOptions {
                 install_mode,
                 install_shell: project::ExecShell::default(),
             };
-            project::install::install(opts)
-                .map_err(std::io::Error::other)?;
-            Ok(package_data)
+            let script_result = project::install::install(opts);
+            let installed_files =
+                Snapshot::take(&install_roots).created_since(&before);
+            let file_manifest =
+                FileManifest::from_paths(&installed_files)?;
+            if let Err(e) = script_result {
+                // 失敗したスクリプトが途中まで配置したファイルを片付ける
+                file_manifest
+                    .remove_files(false, |path| fs::remove_file(path))?;
+                return Err(std::io::Error::other(e));
+            }
+            Ok((package_data, file_manifest))
         }
         Err(e) => {
             log::error!("You cannot install this package.\n{}", e);
@@ -316,3 +738,56 @@ fn installation_process(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reinstall_after_remove_inherits_kept_config_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg_dir = dir.path().join("pkg");
+        fs::create_dir(&pkg_dir).unwrap();
+        let binary = dir.path().join("tool");
+        let config = dir.path().join("tool.conf");
+        fs::write(&binary, "binary").unwrap();
+        fs::write(&config, "default").unwrap();
+        FileManifest::from_paths(&[binary.clone(), config.clone()])
+            .unwrap()
+            .save(&pkg_dir)
+            .unwrap();
+        fs::write(&config, "edited by user").unwrap();
+
+        // `pkg remove`で残されたディレクトリでなければ何も引き継がない
+        let no_preserve = |_: &Path| -> Result<(), std::io::Error> {
+            panic!("nothing should be preserved")
+        };
+        assert!(
+            inherit_removed_version("pkg", &pkg_dir, no_preserve)
+                .unwrap()
+                .is_empty()
+        );
+
+        // `pkg remove`と同じく、変更されていないファイルを削除して削除済みであることを記録する
+        FileManifest::load(&pkg_dir)
+            .unwrap()
+            .remove_files(true, |path| fs::remove_file(path))
+            .unwrap();
+        fs::write(pkg_dir.join(REMOVED_MARKER_FILEPATH), "").unwrap();
+        assert!(!binary.exists());
+
+        let mut preserved = Vec::new();
+        let kept_entries =
+            inherit_removed_version("pkg", &pkg_dir, |path| {
+                preserved.push(path.to_path_buf());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            kept_entries.iter().map(|e| &e.path).collect::<Vec<_>>(),
+            vec![&config]
+        );
+        assert_eq!(preserved, vec![binary, config.clone()]);
+        assert_eq!(fs::read_to_string(&config).unwrap(), "edited by user");
+    }
+}
@@ -1,21 +1,31 @@
 //! このモジュールは、インストールされているパッケージのリストを管理します。
-//! ローカルおよびグローバルなパッケージリストの読み込み、書き込み、追加、削除、表示機能を提供します。
+//! インストール済みパッケージのデータ型と表示機能、およびそれらを保存する`PackageDatabase`を提供します。
 
 use super::super::system::path;
 use super::PackageData;
+use super::lock::LockManager;
 use crate::modules::project::ExecMode;
 use crate::utils::color::colorize::*;
 use crate::utils::error::Error;
+use crate::utils::version::Version;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use serde_yaml;
 use std::fmt::{self, Display, Formatter};
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+mod database;
+pub(crate) use database::write_yaml;
+pub use database::{
+    DatabaseEntry, FileDatabase, IndexEntry, ListBackup, PackageDatabase,
+    PackageIndex, RECORD_FILEPATH, REMOVED_MARKER_FILEPATH,
+    validate_package_name,
+};
 
 /// パッケージリストのデータを表す構造体です。
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PackageListData {
     /// 最終更新日時。
     pub last_modified: DateTime<Local>,
@@ -41,6 +51,49 @@ pub struct InstalledPackageData {
     pub info: PackageData,
     /// 最終更新日時。
     pub last_modified: DateTime<Local>,
+    /// アップグレードまたはダウングレード前にインストールされていたバージョン。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_version: Option<Version>,
+    /// パッケージがインストールされた理由。
+    #[serde(default)]
+    pub install_reason: InstallReason,
+}
+
+/// パッケージがインストールされた理由を表す列挙型です。
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum InstallReason {
+    /// ユーザーが明示的にインストールしました。
+    #[default]
+    Manual,
+    /// 他のパッケージの依存関係として自動的にインストールされました。
+    Dependency {
+        /// このパッケージを必要としたパッケージの名前。
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        of: Option<String>,
+    },
+}
+
+impl InstallReason {
+    /// 自動的にインストールされたパッケージかどうかを返します。
+    pub fn is_auto(&self) -> bool {
+        matches!(self, InstallReason::Dependency { .. })
+    }
+}
+
+impl Display for InstallReason {
+    /// インストール理由を整形して表示します。
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            InstallReason::Manual => write!(f, "manual"),
+            InstallReason::Dependency { of: Some(name) } => {
+                write!(f, "dependency of {}", name)
+            }
+            InstallReason::Dependency { of: None } => {
+                write!(f, "dependency")
+            }
+        }
+    }
 }
 
 impl PackageListData {
@@ -55,7 +108,7 @@ impl PackageListData {
     /// `Ok(PackageListData)` 読み込まれたパッケージリストデータ。
     /// `Err(io::Error)` ファイルの読み込みまたはパースに失敗した場合。
     fn from_filepath(
-        list_filepath: &PathBuf,
+        list_filepath: &Path,
     ) -> Result<PackageListData, io::Error> {
         let packageslist_str = match fs::read_to_string(list_filepath) {
             Ok(s) => s,
@@ -79,7 +132,7 @@ impl PackageListData {
             io::Error::new(
                 io::ErrorKind::InvalidData,
                 format!(
-                    "Failed to parse packages list file '{}': {}",
+                    "Failed to parse packages list file '{}': {}. A previous version can be restored with `ipak system db restore`.",
                     list_filepath.display(),
                     e
                 ),
@@ -109,6 +162,64 @@ impl Display for PackageListData {
     }
 }
 
+#[cfg(test)]
+impl InstalledPackageData {
+    /// テスト用に、指定された名前とバージョンのパッケージを作成します。
+    pub(crate) fn for_test(name: &str, version: &str) -> Self {
+        let mut package = Self::default();
+        package.info.about.package.name = name.to_string();
+        package.info.about.package.version =
+            version.parse().expect("invalid test version");
+        package
+    }
+
+    /// テスト用に、指定されたパッケージへの依存関係（任意のバージョン）を追加します。
+    pub(crate) fn depending_on(mut self, names: &[&str]) -> Self {
+        self.info.relation.depend.extend(names.iter().map(|name| {
+            vec![super::PackageRange {
+                name: name.to_string(),
+                range: "*".parse().expect("invalid test range"),
+            }]
+        }));
+        self
+    }
+
+    /// テスト用に、いずれか1つを満たせばよい依存関係のグループを追加します。
+    pub(crate) fn depending_on_any(
+        mut self,
+        alternatives: &[(&str, &str)],
+    ) -> Self {
+        self.info.relation.depend.push(
+            alternatives
+                .iter()
+                .map(|(name, range)| super::PackageRange {
+                    name: name.to_string(),
+                    range: range.parse().expect("invalid test range"),
+                })
+                .collect(),
+        );
+        self
+    }
+
+    /// テスト用に、パッケージ自身のバージョンで提供する仮想パッケージを追加します。
+    pub(crate) fn providing(mut self, names: &[&str]) -> Self {
+        let version = self.info.about.package.version.clone();
+        self.info.relation.virtuals.extend(names.iter().map(|name| {
+            super::PackageVersion {
+                name: name.to_string(),
+                version: version.clone(),
+            }
+        }));
+        self
+    }
+
+    /// テスト用に、依存関係として自動的にインストールされたことにします。
+    pub(crate) fn installed_as_dependency(mut self) -> Self {
+        self.install_reason = InstallReason::Dependency { of: None };
+        self
+    }
+}
+
 impl Display for InstalledPackageData {
     /// `InstalledPackageData`を整形して表示します。
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -124,6 +235,20 @@ impl Display for InstalledPackageData {
             "Version".bold(),
             self.info.about.package.version
         )?;
+        if let Some(previous_version) = &self.previous_version {
+            writeln!(
+                f,
+                "    {}: {}",
+                "Previous Version".bold(),
+                previous_version
+            )?;
+        }
+        writeln!(
+            f,
+            "    {}: {}",
+            "Install Reason".bold(),
+            self.install_reason
+        )?;
         writeln!(
             f,
             "    {}: {} <{}>",
@@ -158,276 +283,31 @@ impl Display for InstalledPackageData {
 /// `Ok(())` パッケージリストが正常に表示された場合。
 /// `Err(Error)` パッケージリストの取得または表示中にエラーが発生した場合。
 pub fn list(mode: ExecMode) -> Result<(), Error> {
-    let packages_list_data = match mode {
-        ExecMode::Local => get_local().map_err(Error::from)?,
-        ExecMode::Global => get_global().map_err(Error::from)?,
-    };
+    let lock_manager = LockManager::new(matches!(mode, ExecMode::Global));
+    let _lock = lock_manager.acquire_shared_lock()?;
+    let packages_list_data = database(mode).load_all()?;
     log::info!("{}", packages_list_data);
     Ok(())
 }
 
-/// ローカルのインストール済みパッケージリストを取得します。
-///
-/// # Returns
-/// `Ok(PackageListData)` ローカルパッケージリストデータ。
-/// `Err(std::io::Error)` ファイルの読み込みまたはパースに失敗した場合。
-pub fn get_local() -> Result<PackageListData, std::io::Error> {
-    let local_filepath = path::local::packageslist_filepath();
-    PackageListData::from_filepath(&local_filepath)
-}
-
-/// グローバルのインストール済みパッケージリストを取得します。
-///
-/// # Returns
-/// `Ok(PackageListData)` グローバルパッケージリストデータ。
-/// `Err(std::io::Error)` ファイルの読み込みまたはパースに失敗した場合。
-pub fn get_global() -> Result<PackageListData, std::io::Error> {
-    let global_filepath = path::global::packageslist_filepath();
-    PackageListData::from_filepath(&global_filepath)
-}
-
-/// ローカルのパッケージリストにデータを適用し、ファイルに保存します。
-///
-/// # Arguments
-/// * `data` - 適用する`PackageListData`。
-///
-/// # Returns
-/// `Ok(())` データが正常に適用され、保存された場合。
-/// `Err(std::io::Error)` ファイルの書き込みまたはシリアライズに失敗した場合。
-pub fn apply_local(
-    mut data: PackageListData,
-) -> Result<(), std::io::Error> {
-    let local_filepath = path::local::packageslist_filepath();
-
-    if let Some(parent_dir) = local_filepath.parent() {
-        fs::create_dir_all(parent_dir).map_err(|e| {
-            io::Error::new(
-                e.kind(),
-                format!(
-                    "Failed to create parent directory for '{}': {}",
-                    local_filepath.display(),
-                    e
-                ),
-            )
-        })?;
-    }
-
-    data.last_modified = Local::now();
-
-    let yaml_string = serde_yaml::to_string(&data).map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!(
-                "Failed to serialize package list data for '{}': {}",
-                local_filepath.display(),
-                e
-            ),
-        )
-    })?;
-
-    fs::write(&local_filepath, yaml_string).map_err(|e| {
-        io::Error::new(
-            e.kind(),
-            format!(
-                "Failed to write package list data to '{}': {}",
-                local_filepath.display(),
-                e
-            ),
-        )
-    })?;
-
-    Ok(())
-}
-
-/// グローバルのパッケージリストにデータを適用し、ファイルに保存します。
-///
-/// # Arguments
-/// * `data` - 適用する`PackageListData`。
-///
-/// # Returns
-/// `Ok(())` データが正常に適用され、保存された場合。
-/// `Err(std::io::Error)` ファイルの書き込みまたはシリアライズに失敗した場合。
-pub fn apply_global(
-    mut data: PackageListData,
-) -> Result<(), std::io::Error> {
-    let global_filepath = path::global::packageslist_filepath();
-
-    if let Some(parent_dir) = global_filepath.parent() {
-        fs::create_dir_all(parent_dir).map_err(|e| {
-            io::Error::new(
-                e.kind(),
-                format!(
-                    "Failed to create parent directory for '{}': {}",
-                    global_filepath.display(),
-                    e
-                ),
-            )
-        })?;
-    }
-
-    data.last_modified = Local::now();
-
-    let yaml_string = serde_yaml::to_string(&data).map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!(
-                "Failed to serialize package list data for '{}': {}",
-                global_filepath.display(),
-                e
-            ),
-        )
-    })?;
-
-    fs::write(&global_filepath, yaml_string).map_err(|e| {
-        io::Error::new(
-            e.kind(),
-            format!(
-                "Failed to write package list data to '{}': {}",
-                global_filepath.display(),
-                e
-            ),
-        )
-    })?;
-
-    Ok(())
-}
-
-/// ローカルのパッケージリストに新しいパッケージを追加します。
-///
-/// 同じ名前のパッケージが既に存在する場合は、そのデータを更新します。
-///
-/// # Arguments
-/// * `new_pkg` - 追加する`InstalledPackageData`。
-///
-/// # Returns
-/// `Ok(())` パッケージが正常に追加または更新された場合。
-/// `Err(io::Error)` パッケージリストの読み込み、書き込み、または更新中にエラーが発生した場合。
-pub fn add_pkg_local(
-    new_pkg: InstalledPackageData,
-) -> Result<(), io::Error> {
-    let mut data = get_local()?;
-    let mut found = false;
-    for i in 0..data.installed_packages.len() {
-        if data.installed_packages[i].info.about.package.name
-            == new_pkg.info.about.package.name
-        {
-            data.installed_packages[i] = new_pkg.clone();
-            found = true;
-            log::error!(
-                "{} Package '{}' already exists locally. Updating its data.",
-                "Info:".blue().bold(),
-                data.installed_packages[i].info.about.package.name
-            );
-            break;
-        }
-    }
-
-    if !found {
-        data.installed_packages.push(new_pkg);
-        log::error!(
-            "{} Package added to local list.",
-            "Info:".blue().bold()
-        );
-    }
-
-    apply_local(data)?;
-    Ok(())
-}
-
-/// グローバルのパッケージリストに新しいパッケージを追加します。
-///
-/// 同じ名前のパッケージが既に存在する場合は、そのデータを更新します。
-///
-/// # Arguments
-/// * `new_pkg` - 追加する`InstalledPackageData`。
-///
-/// # Returns
-/// `Ok(())` パッケージが正常に追加または更新された場合。
-/// `Err(io::Error)` パッケージリストの読み込み、書き込み、または更新中にエラーが発生した場合。
-pub fn add_pkg_global(
-    new_pkg: InstalledPackageData,
-) -> Result<(), io::Error> {
-    let mut data = get_global()?;
-    let mut found = false;
-    for i in 0..data.installed_packages.len() {
-        if data.installed_packages[i].info.about.package.name
-            == new_pkg.info.about.package.name
-        {
-            data.installed_packages[i] = new_pkg.clone();
-            found = true;
-            log::error!(
-                "{} Package '{}' already exists globally. Updating its data.",
-                "Info:".blue().bold(),
-                data.installed_packages[i].info.about.package.name
-            );
-            break;
-        }
-    }
-
-    if !found {
-        data.installed_packages.push(new_pkg);
-        log::error!(
-            "{} Package added to global list.",
-            "Info:".blue().bold()
-        );
-    }
-
-    apply_global(data)?;
-    Ok(())
-}
-
-/// ローカルのパッケージリストから指定されたパッケージを削除します。
+/// 指定されたモードのインストール済みパッケージのデータベースを返します。
 ///
 /// # Arguments
-/// * `package_name` - 削除するパッケージの名前。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
 ///
 /// # Returns
-/// `Ok(true)` パッケージが正常に削除された場合。
-/// `Ok(false)` パッケージが見つからなかった場合。
-/// `Err(io::Error)` パッケージリストの読み込み、書き込み、または更新中にエラーが発生した場合。
-pub fn del_pkg_local(package_name: &str) -> Result<bool, io::Error> {
-    let mut data = get_local()?;
-    let initial_len = data.installed_packages.len();
-    data.installed_packages
-        .retain(|pkg| pkg.info.about.package.name != package_name);
-
-    if data.installed_packages.len() < initial_len {
-        apply_local(data)?;
-        Ok(true)
-    } else {
-        log::error!(
-            "{} Package '{}' not found in local installations.",
-            "Warning:".yellow().bold(),
-            package_name
-        );
-        Ok(false)
-    }
+/// パッケージディレクトリに保存された`PackageDatabase`。
+pub fn database(mode: ExecMode) -> Box<dyn PackageDatabase> {
+    Box::new(FileDatabase::new(packages_dirpath(mode)))
 }
 
-/// グローバルのパッケージリストから指定されたパッケージを削除します。
+/// 指定されたモードのパッケージディレクトリへのパスを返します。
 ///
 /// # Arguments
-/// * `package_name` - 削除するパッケージの名前。
-///
-/// # Returns
-/// `Ok(true)` パッケージが正常に削除された場合。
-/// `Ok(false)` パッケージが見つからなかった場合。
-/// `Err(io::Error)` パッケージリストの読み込み、書き込み、または更新中にエラーが発生した場合。
-pub fn del_pkg_global(package_name: &str) -> Result<bool, io::Error> {
-    let mut data = get_global()?;
-    let initial_len = data.installed_packages.len();
-    data.installed_packages
-        .retain(|pkg| pkg.info.about.package.name != package_name);
-
-    if data.installed_packages.len() < initial_len {
-        apply_global(data)?;
-        Ok(true)
-    } else {
-        log::error!(
-            "{} Package '{}' not found in global installations.",
-            "Warning:".yellow().bold(),
-            package_name
-        );
-        Ok(false)
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+pub fn packages_dirpath(mode: ExecMode) -> PathBuf {
+    match mode {
+        ExecMode::Local => path::local::packages_dirpath(),
+        ExecMode::Global => path::global::packages_dirpath(),
     }
 }
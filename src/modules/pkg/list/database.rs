@@ -0,0 +1,769 @@
+//! このモジュールは、インストール済みパッケージのデータベースを提供します。
+//!
+//! `FileDatabase`は、パッケージごとのレコードを`packages/<name>/ipak/installed.yaml`に、
+//! インストール済みパッケージの名前とバージョンだけを小さなインデックス`packages/index.yaml`に保存します。
+//! パッケージを1つ追加・削除する際には、そのパッケージのレコードとインデックスだけを書き換えます。
+//! 以前の単一の`list.yaml`は、最初の書き込み時にこの形式へ移行されます。
+//!
+//! すべての書き込みは一時ファイルを経由したアトミックな置き換えで行われます。
+//! 操作の前にはデータベース全体のスナップショットが`list.yaml.1`から`list.yaml.N`としてローテーションされ、
+//! `ipak system db restore`で復元できます。
+
+use super::{InstalledPackageData, PackageListData};
+use crate::modules::system::path;
+use crate::utils::version::Version;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// インデックスのファイル名です。
+const INDEX_FILENAME: &str = "index.yaml";
+/// 以前の形式のパッケージリストのファイル名です。バックアップの名前にも使用されます。
+const LEGACY_LIST_FILENAME: &str = "list.yaml";
+/// パッケージディレクトリ内のレコードのパスです。
+pub const RECORD_FILEPATH: &str = "ipak/installed.yaml";
+/// 削除されたパッケージのディレクトリに置かれる目印のパスです。
+/// 変更された設定ファイルをパージのために残したディレクトリであることを表します。
+pub const REMOVED_MARKER_FILEPATH: &str = "ipak/removed";
+/// 保持するスナップショットの数です。
+const BACKUP_COUNT: usize = 5;
+
+/// インストール済みパッケージのデータベースを表すトレイトです。
+pub trait PackageDatabase {
+    /// すべてのインストール済みパッケージを読み込みます。
+    ///
+    /// # Returns
+    /// `Ok(PackageListData)` インストール済みパッケージのリスト。
+    /// `Err(io::Error)` データベースの読み込みに失敗した場合。
+    fn load_all(&self) -> Result<PackageListData, io::Error>;
+
+    /// 指定された名前のインストール済みパッケージを読み込みます。
+    ///
+    /// # Arguments
+    /// * `name` - パッケージ名。
+    ///
+    /// # Returns
+    /// `Ok(Some(InstalledPackageData))` パッケージがインストールされている場合。
+    /// `Ok(None)` パッケージがインストールされていない場合。
+    /// `Err(io::Error)` データベースの読み込みに失敗した場合。
+    fn get(
+        &self,
+        name: &str,
+    ) -> Result<Option<InstalledPackageData>, io::Error>;
+
+    /// パッケージを追加します。同じ名前のパッケージが既に存在する場合は置き換えます。
+    ///
+    /// # Arguments
+    /// * `package` - 追加するパッケージ。
+    ///
+    /// # Returns
+    /// `Ok(())` 追加に成功した場合。
+    /// `Err(io::Error)` 書き込みに失敗した場合。
+    fn put(&self, package: InstalledPackageData) -> Result<(), io::Error>;
+
+    /// パッケージを削除します。
+    ///
+    /// # Arguments
+    /// * `name` - 削除するパッケージの名前。
+    ///
+    /// # Returns
+    /// `Ok(true)` パッケージが削除された場合。
+    /// `Ok(false)` パッケージが見つからなかった場合。
+    /// `Err(io::Error)` 書き込みに失敗した場合。
+    fn delete(&self, name: &str) -> Result<bool, io::Error>;
+
+    /// データベースの内容全体を置き換えます。
+    ///
+    /// # Arguments
+    /// * `data` - 新しい内容。
+    ///
+    /// # Returns
+    /// `Ok(())` 置き換えに成功した場合。
+    /// `Err(io::Error)` 書き込みに失敗した場合。
+    fn replace_all(&self, data: PackageListData) -> Result<(), io::Error>;
+
+    /// 現在の内容をスナップショットとして保存し、古いスナップショットをローテーションします。
+    ///
+    /// # Returns
+    /// `Ok(())` 保存に成功した場合。
+    /// `Err(io::Error)` 読み込みまたは書き込みに失敗した場合。
+    fn backup(&self) -> Result<(), io::Error>;
+
+    /// 読み込み済みの内容をスナップショットとして保存し、古いスナップショットをローテーションします。
+    ///
+    /// 操作の開始時に読み込んだ内容を渡すことで、データベースを読み直さずにスナップショットを取れます。
+    ///
+    /// # Arguments
+    /// * `data` - 現在のデータベースの内容。
+    ///
+    /// # Returns
+    /// `Ok(())` 保存に成功した場合。
+    /// `Err(io::Error)` 書き込みに失敗した場合。
+    fn snapshot(&self, data: &PackageListData) -> Result<(), io::Error>;
+
+    /// 保存されているスナップショットを新しい順に返します。
+    fn backups(&self) -> Vec<ListBackup>;
+
+    /// データベースを指定された世代のスナップショットで置き換えます。
+    ///
+    /// 置き換える前の内容もスナップショットとして保存されるため、復元は取り消すことができます。
+    ///
+    /// # Arguments
+    /// * `generation` - 復元するスナップショットの世代（`1`が最新）。
+    ///
+    /// # Returns
+    /// `Ok(PackageListData)` 復元された内容。
+    /// `Err(io::Error)` スナップショットが存在しない、読み込めない、または書き込みに失敗した場合。
+    fn restore_backup(
+        &self,
+        generation: usize,
+    ) -> Result<PackageListData, io::Error>;
+}
+
+/// データベースのスナップショットです。
+pub struct ListBackup {
+    /// スナップショットの世代。`1`が最も新しいスナップショットです。
+    pub generation: usize,
+    /// スナップショットファイルへのパス。
+    pub path: PathBuf,
+    /// スナップショットの内容。読み込みに失敗した場合はエラー。
+    pub data: Result<PackageListData, io::Error>,
+}
+
+/// インストール済みパッケージの名前とバージョンを並べたインデックスです。
+#[derive(Serialize, Deserialize, Default)]
+pub struct PackageIndex {
+    /// 最終更新日時。
+    pub last_modified: DateTime<Local>,
+    /// インストール済みパッケージ。
+    pub packages: Vec<IndexEntry>,
+}
+
+/// インデックスの1項目です。
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexEntry {
+    /// パッケージ名。
+    pub name: String,
+    /// インストールされているバージョン。
+    pub version: Version,
+}
+
+impl From<&InstalledPackageData> for IndexEntry {
+    fn from(package: &InstalledPackageData) -> Self {
+        Self {
+            name: package.info.about.package.name.clone(),
+            version: package.info.about.package.version.clone(),
+        }
+    }
+}
+
+/// データベースのエントリと、そのレコードの読み込み結果です。
+pub struct DatabaseEntry {
+    /// インデックスのエントリ。
+    pub entry: IndexEntry,
+    /// レコードの内容。読み込みに失敗した場合はエラー。
+    pub record: Result<InstalledPackageData, io::Error>,
+}
+
+/// パッケージディレクトリ内のファイルにパッケージごとのレコードを保存するデータベースです。
+pub struct FileDatabase {
+    packages_dir: PathBuf,
+}
+
+impl FileDatabase {
+    /// 指定されたパッケージディレクトリのデータベースを作成します。
+    ///
+    /// # Arguments
+    /// * `packages_dir` - パッケージがインストールされるディレクトリ。
+    pub fn new(packages_dir: PathBuf) -> Self {
+        Self { packages_dir }
+    }
+
+    /// インデックスファイルへのパスを返します。
+    pub fn index_filepath(&self) -> PathBuf {
+        self.packages_dir.join(INDEX_FILENAME)
+    }
+
+    /// 指定されたパッケージのレコードファイルへのパスを返します。
+    ///
+    /// パッケージ名がディレクトリ名として使えない場合はエラーを返します。
+    pub fn record_filepath(
+        &self,
+        name: &str,
+    ) -> Result<PathBuf, io::Error> {
+        validate_package_name(name)?;
+        Ok(self.packages_dir.join(name).join(RECORD_FILEPATH))
+    }
+
+    /// データベースのすべてのエントリを、レコードの読み込み結果と共に返します。
+    ///
+    /// `load_all`とは異なり、重複したエントリや読み込めないレコードもそのまま返すため、
+    /// データベースの整合性の検査に使用できます。
+    /// インデックスがまだ作成されていない場合は、以前の形式のパッケージリストの内容を返します。
+    ///
+    /// # Returns
+    /// `Ok(Vec<DatabaseEntry>)` インデックスに記録されている順のエントリ。
+    /// `Err(io::Error)` インデックスまたはパッケージリストの読み込みに失敗した場合。
+    pub fn entries(&self) -> Result<Vec<DatabaseEntry>, io::Error> {
+        let Some(index) = self.read_index()? else {
+            let data = PackageListData::from_filepath(
+                &self.legacy_list_filepath(),
+            )?;
+            return Ok(data
+                .installed_packages
+                .into_iter()
+                .map(|package| DatabaseEntry {
+                    entry: IndexEntry::from(&package),
+                    record: Ok(package),
+                })
+                .collect());
+        };
+        Ok(index
+            .packages
+            .into_iter()
+            .map(|entry| DatabaseEntry {
+                record: self.read_record(&entry.name),
+                entry,
+            })
+            .collect())
+    }
+
+    /// 別のディレクトリにあるデータベースのパッケージを、このデータベースへ移動します。
+    ///
+    /// パッケージディレクトリを移動してから、そのレコードをこのデータベースに追加します。
+    /// このデータベースに同じ名前のパッケージ、またはディレクトリが既にある場合は、移動せずに残します。
+    ///
+    /// # Arguments
+    /// * `other` - 移動元のデータベース。
+    ///
+    /// # Returns
+    /// `Ok(Vec<String>)` 移動せずに残したパッケージの名前。
+    /// `Err(io::Error)` データベースの読み込み、書き込み、またはディレクトリの移動に失敗した場合。
+    pub fn merge_from(
+        &self,
+        other: &FileDatabase,
+    ) -> Result<Vec<String>, io::Error> {
+        let installed = self.load_all()?;
+        let mut skipped = Vec::new();
+        for package in other.load_all()?.installed_packages {
+            let name = package.info.about.package.name.clone();
+            let from = other.packages_dir.join(&name);
+            let to = self.packages_dir.join(&name);
+            if to.symlink_metadata().is_ok()
+                || installed
+                    .installed_packages
+                    .iter()
+                    .any(|pkg| pkg.info.about.package.name == name)
+            {
+                skipped.push(name);
+                continue;
+            }
+            if from.is_dir() {
+                fs::create_dir_all(&self.packages_dir)?;
+                path::move_path(&from, &to)?;
+            }
+            self.put(package)?;
+        }
+        Ok(skipped)
+    }
+
+    /// 以前の形式のパッケージリストへのパスを返します。
+    fn legacy_list_filepath(&self) -> PathBuf {
+        self.packages_dir.join(LEGACY_LIST_FILENAME)
+    }
+
+    /// 指定された世代のスナップショットへのパスを返します。
+    fn backup_filepath(&self, generation: usize) -> PathBuf {
+        self.packages_dir
+            .join(format!("{}.{}", LEGACY_LIST_FILENAME, generation))
+    }
+
+    /// インデックスを読み込みます。
+    ///
+    /// # Returns
+    /// `Ok(None)` インデックスがまだ作成されていない場合。
+    fn read_index(&self) -> Result<Option<PackageIndex>, io::Error> {
+        let index_path = self.index_filepath();
+        match fs::read_to_string(&index_path) {
+            Ok(content) => parse_yaml(&content, &index_path).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(read_error(&index_path, e)),
+        }
+    }
+
+    /// インデックスを書き込みます。最終更新日時は現在時刻に更新されます。
+    fn write_index(
+        &self,
+        mut index: PackageIndex,
+    ) -> Result<(), io::Error> {
+        index.last_modified = Local::now();
+        write_yaml(&self.index_filepath(), &index)
+    }
+
+    /// パッケージのレコードを読み込みます。
+    fn read_record(
+        &self,
+        name: &str,
+    ) -> Result<InstalledPackageData, io::Error> {
+        let record_path = self.record_filepath(name)?;
+        let content = fs::read_to_string(&record_path)
+            .map_err(|e| read_error(&record_path, e))?;
+        parse_yaml(&content, &record_path)
+    }
+
+    /// パッケージのレコードを書き込みます。
+    fn write_record(
+        &self,
+        package: &InstalledPackageData,
+    ) -> Result<(), io::Error> {
+        write_yaml(
+            &self.record_filepath(&package.info.about.package.name)?,
+            package,
+        )
+    }
+
+    /// 以前の形式の`list.yaml`を、パッケージごとのレコードとインデックスに移行します。
+    ///
+    /// インデックスが既に存在する場合、または移行するリストがない場合は何もしません。
+    /// 移行したリストは最新のスナップショットとして残されます。
+    fn migrate(&self) -> Result<(), io::Error> {
+        let legacy_path = self.legacy_list_filepath();
+        if self.index_filepath().exists() || !legacy_path.is_file() {
+            return Ok(());
+        }
+        let data = PackageListData::from_filepath(&legacy_path)?;
+        for package in &data.installed_packages {
+            self.write_record(package)?;
+        }
+        self.write_index(PackageIndex {
+            last_modified: data.last_modified,
+            packages: data
+                .installed_packages
+                .iter()
+                .map(IndexEntry::from)
+                .collect(),
+        })?;
+        self.rotate_backups()?;
+        fs::rename(&legacy_path, self.backup_filepath(1))?;
+        log::info!(
+            "Migrated {} package(s) from {} to per-package records",
+            data.installed_packages.len(),
+            legacy_path.display()
+        );
+        Ok(())
+    }
+
+    /// スナップショットを1世代ずつ古い方へずらします。最も古いものは上書きされます。
+    fn rotate_backups(&self) -> Result<(), io::Error> {
+        for generation in (1..BACKUP_COUNT).rev() {
+            let from = self.backup_filepath(generation);
+            if from.is_file() {
+                fs::rename(&from, self.backup_filepath(generation + 1))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PackageDatabase for FileDatabase {
+    fn load_all(&self) -> Result<PackageListData, io::Error> {
+        let Some(index) = self.read_index()? else {
+            return PackageListData::from_filepath(
+                &self.legacy_list_filepath(),
+            );
+        };
+        let mut installed_packages =
+            Vec::with_capacity(index.packages.len());
+        for entry in &index.packages {
+            match self.read_record(&entry.name) {
+                Ok(package) => installed_packages.push(package),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    log::warn!(
+                        "The record of '{}' is missing: {}",
+                        entry.name,
+                        e
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(PackageListData {
+            last_modified: index.last_modified,
+            installed_packages,
+        })
+    }
+
+    fn get(
+        &self,
+        name: &str,
+    ) -> Result<Option<InstalledPackageData>, io::Error> {
+        let Some(index) = self.read_index()? else {
+            return Ok(self
+                .load_all()?
+                .installed_packages
+                .into_iter()
+                .find(|pkg| pkg.info.about.package.name == name));
+        };
+        if !index.packages.iter().any(|entry| entry.name == name) {
+            return Ok(None);
+        }
+        self.read_record(name).map(Some)
+    }
+
+    fn put(&self, package: InstalledPackageData) -> Result<(), io::Error> {
+        validate_package_name(&package.info.about.package.name)?;
+        self.migrate()?;
+        self.write_record(&package)?;
+        let mut index = self.read_index()?.unwrap_or_default();
+        let entry = IndexEntry::from(&package);
+        match index.packages.iter_mut().find(|e| e.name == entry.name) {
+            Some(existing) => {
+                log::debug!("Updating the record of '{}'", entry.name);
+                *existing = entry;
+            }
+            None => {
+                log::debug!("Adding the record of '{}'", entry.name);
+                index.packages.push(entry);
+            }
+        }
+        self.write_index(index)
+    }
+
+    fn delete(&self, name: &str) -> Result<bool, io::Error> {
+        self.migrate()?;
+        let mut index = self.read_index()?.unwrap_or_default();
+        let initial_len = index.packages.len();
+        index.packages.retain(|entry| entry.name != name);
+        if index.packages.len() == initial_len {
+            log::warn!("Package '{}' is not in the database", name);
+            return Ok(false);
+        }
+        self.write_index(index)?;
+        remove_if_exists(&self.record_filepath(name)?)?;
+        log::debug!("Removed the record of '{}'", name);
+        Ok(true)
+    }
+
+    fn replace_all(&self, data: PackageListData) -> Result<(), io::Error> {
+        self.migrate()?;
+        let previous = self.read_index()?.unwrap_or_default();
+        for package in &data.installed_packages {
+            self.write_record(package)?;
+        }
+        self.write_index(PackageIndex {
+            last_modified: data.last_modified,
+            packages: data
+                .installed_packages
+                .iter()
+                .map(IndexEntry::from)
+                .collect(),
+        })?;
+        for entry in previous.packages {
+            let still_installed = data
+                .installed_packages
+                .iter()
+                .any(|pkg| pkg.info.about.package.name == entry.name);
+            if !still_installed {
+                remove_if_exists(&self.record_filepath(&entry.name)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn backup(&self) -> Result<(), io::Error> {
+        self.snapshot(&self.load_all()?)
+    }
+
+    fn snapshot(&self, data: &PackageListData) -> Result<(), io::Error> {
+        self.migrate()?;
+        if !self.index_filepath().exists() {
+            return Ok(());
+        }
+        self.rotate_backups()?;
+        write_yaml(&self.backup_filepath(1), data)
+    }
+
+    fn backups(&self) -> Vec<ListBackup> {
+        (1..=BACKUP_COUNT)
+            .map(|generation| {
+                (generation, self.backup_filepath(generation))
+            })
+            .filter(|(_, path)| path.is_file())
+            .map(|(generation, path)| ListBackup {
+                generation,
+                data: PackageListData::from_filepath(&path),
+                path,
+            })
+            .collect()
+    }
+
+    fn restore_backup(
+        &self,
+        generation: usize,
+    ) -> Result<PackageListData, io::Error> {
+        let backup_path = self.backup_filepath(generation);
+        if !backup_path.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Backup '{}' does not exist",
+                    backup_path.display()
+                ),
+            ));
+        }
+        let data = PackageListData::from_filepath(&backup_path)?;
+        self.backup()?;
+        self.replace_all(data.clone())?;
+        Ok(data)
+    }
+}
+
+/// YAML文字列をパースします。
+/// パッケージ名が、パッケージディレクトリ内のディレクトリ名として安全に使えるかどうかを検証します。
+///
+/// パッケージ名はパッケージディレクトリへのパスにそのまま使われるため、
+/// 空の名前、`/`や`\\`を含む名前、`.`で始まる名前（`..`を含む）は拒否します。
+///
+/// # Arguments
+/// * `name` - 検証するパッケージ名。
+///
+/// # Returns
+/// `Ok(())` 名前が使える場合。
+/// `Err(io::Error)` 名前が使えない場合（`io::ErrorKind::InvalidInput`）。
+pub fn validate_package_name(name: &str) -> Result<(), io::Error> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\', '\0'])
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid package name '{}'", name),
+        ));
+    }
+    Ok(())
+}
+
+fn parse_yaml<T: for<'de> Deserialize<'de>>(
+    content: &str,
+    path: &Path,
+) -> Result<T, io::Error> {
+    serde_yaml::from_str(content).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Failed to parse '{}': {}. A previous version can be restored with `ipak system db restore`.",
+                path.display(),
+                e
+            ),
+        )
+    })
+}
+
+/// 読み込みエラーにファイルのパスを付け加えます。
+fn read_error(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(
+        e.kind(),
+        format!("Failed to read '{}': {}", path.display(), e),
+    )
+}
+
+/// ファイルが存在する場合に削除します。
+fn remove_if_exists(path: &Path) -> Result<(), io::Error> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// 値をYAMLとして一時ファイルに書き込み、アトミックに置き換えます。
+///
+/// 一時ファイルは同じディレクトリに作成され、ディスクに書き込まれてから名前を変更されるため、
+/// 書き込みの途中でクラッシュしても既存のファイルが壊れることはありません。
+///
+/// # Arguments
+/// * `path` - 書き込むファイルへのパス。
+/// * `value` - 書き込む値。
+///
+/// # Returns
+/// `Ok(())` 書き込みに成功した場合。
+/// `Err(io::Error)` シリアライズ、一時ファイルへの書き込み、または置き換えに失敗した場合。
+pub(crate) fn write_yaml<T: Serialize>(
+    path: &Path,
+    value: &T,
+) -> Result<(), io::Error> {
+    let parent_dir = path.parent().unwrap_or(Path::new("."));
+    let write_error = |e: io::Error| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to write '{}': {}", path.display(), e),
+        )
+    };
+    fs::create_dir_all(parent_dir).map_err(write_error)?;
+
+    let yaml_string = serde_yaml::to_string(value).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Failed to serialize '{}': {}", path.display(), e),
+        )
+    })?;
+
+    let mut temp_file = tempfile::Builder::new()
+        .prefix(".ipak-")
+        .suffix(".tmp")
+        .tempfile_in(parent_dir)
+        .map_err(write_error)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        temp_file
+            .as_file()
+            .set_permissions(fs::Permissions::from_mode(0o644))
+            .map_err(write_error)?;
+    }
+    temp_file.write_all(yaml_string.as_bytes()).map_err(write_error)?;
+    temp_file.as_file().sync_all().map_err(write_error)?;
+    temp_file.persist(path).map_err(|e| write_error(e.error))?;
+    // 名前の変更をディスクに反映する
+    #[cfg(unix)]
+    fs::File::open(parent_dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn names(data: &PackageListData) -> Vec<&str> {
+        data.installed_packages
+            .iter()
+            .map(|pkg| pkg.info.about.package.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_put_get_and_delete_touch_single_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = FileDatabase::new(dir.path().to_path_buf());
+
+        db.put(InstalledPackageData::for_test("a", "1.0")).unwrap();
+        db.put(InstalledPackageData::for_test("b", "1.0")).unwrap();
+        db.put(InstalledPackageData::for_test("a", "2.0")).unwrap();
+        assert!(db.record_filepath("a").unwrap().is_file());
+        assert_eq!(names(&db.load_all().unwrap()), vec!["a", "b"]);
+        let a = db.get("a").unwrap().unwrap();
+        assert_eq!(a.info.about.package.version.to_string(), "2.0");
+
+        assert!(db.delete("a").unwrap());
+        assert!(!db.delete("a").unwrap());
+        assert!(!db.record_filepath("a").unwrap().exists());
+        assert!(db.get("a").unwrap().is_none());
+        assert_eq!(names(&db.load_all().unwrap()), vec!["b"]);
+    }
+
+    #[test]
+    fn test_legacy_list_is_migrated_on_first_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = FileDatabase::new(dir.path().to_path_buf());
+        let legacy = PackageListData {
+            installed_packages: vec![InstalledPackageData::for_test(
+                "a", "1.0",
+            )],
+            ..Default::default()
+        };
+        fs::write(
+            db.legacy_list_filepath(),
+            serde_yaml::to_string(&legacy).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(names(&db.load_all().unwrap()), vec!["a"]);
+        db.put(InstalledPackageData::for_test("b", "1.0")).unwrap();
+        assert!(!db.legacy_list_filepath().exists());
+        assert!(db.index_filepath().is_file());
+        assert_eq!(names(&db.load_all().unwrap()), vec!["a", "b"]);
+        let backups = db.backups();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(names(backups[0].data.as_ref().unwrap()), vec!["a"]);
+    }
+
+    #[test]
+    fn test_merge_from_moves_packages_and_keeps_existing_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = FileDatabase::new(dir.path().join("legacy"));
+        let db = FileDatabase::new(dir.path().join("new"));
+        for name in ["a", "b"] {
+            fs::create_dir_all(dir.path().join("legacy").join(name))
+                .unwrap();
+            legacy
+                .put(InstalledPackageData::for_test(name, "1.0"))
+                .unwrap();
+        }
+        db.put(InstalledPackageData::for_test("b", "2.0")).unwrap();
+
+        assert_eq!(db.merge_from(&legacy).unwrap(), vec!["b"]);
+        let data = db.load_all().unwrap();
+        assert_eq!(names(&data), vec!["b", "a"]);
+        assert_eq!(
+            data.installed_packages[0].info.about.package.version,
+            Version::from_str("2.0").unwrap()
+        );
+        assert!(db.record_filepath("a").unwrap().is_file());
+        assert!(!dir.path().join("legacy/a").exists());
+        assert!(legacy.record_filepath("b").unwrap().is_file());
+    }
+
+    #[test]
+    fn test_backups_rotate_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = FileDatabase::new(dir.path().to_path_buf());
+        for count in 0..BACKUP_COUNT + 2 {
+            db.backup().unwrap();
+            db.put(InstalledPackageData::for_test(
+                &format!("p{}", count),
+                "1.0",
+            ))
+            .unwrap();
+        }
+        let backups = db.backups();
+        assert_eq!(backups.len(), BACKUP_COUNT);
+        let len = |backup: &ListBackup| {
+            backup.data.as_ref().unwrap().installed_packages.len()
+        };
+        assert_eq!(len(&backups[0]), BACKUP_COUNT + 1);
+        assert_eq!(len(&backups[BACKUP_COUNT - 1]), 2);
+
+        let restored = db.restore_backup(BACKUP_COUNT).unwrap();
+        assert_eq!(names(&restored), vec!["p0", "p1"]);
+        assert_eq!(names(&db.load_all().unwrap()), vec!["p0", "p1"]);
+        assert!(!db.record_filepath("p2").unwrap().exists());
+        let leftovers = fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().ends_with(".tmp")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn test_package_names_cannot_leave_the_packages_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = FileDatabase::new(dir.path().join("packages"));
+
+        for name in ["../../x", "a/b", "..", ".hidden", "", "a\\b"] {
+            assert!(validate_package_name(name).is_err(), "{}", name);
+            assert!(db.record_filepath(name).is_err());
+            assert!(
+                db.put(InstalledPackageData::for_test(name, "1.0"))
+                    .is_err()
+            );
+        }
+        assert!(!dir.path().join("x").exists());
+        assert!(!dir.path().join("packages").join("a").exists());
+        assert!(validate_package_name("lib-foo_2.0").is_ok());
+    }
+}
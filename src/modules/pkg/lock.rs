@@ -2,21 +2,57 @@
 //!
 //! `ipak`が複数のプロセスで同時にパッケージを操作しようとした際の競合を防ぎ、
 //! 安全なパッケージ管理を実現します。
+//!
+//! ロックはロックファイルに対するアドバイザリロック（`flock`）で実現されており、
+//! プロセスが終了するとカーネルによって自動的に解放されます。
+//! パッケージリストを変更する操作は排他ロックを、読み取るだけの操作は共有ロックを取得します。
+//! 設定の`lock.timeout`を指定すると、ロックの解放を待つ時間に上限を設けられます。
 
-use std::fs::{self, File, OpenOptions};
+use std::fs::{self, File, OpenOptions, TryLockError};
 use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::time::{Duration, SystemTime};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use super::list::FileDatabase;
+use super::transaction;
+use crate::modules::project::ExecMode;
+use crate::modules::system::config;
 use crate::modules::system::path::{global, local};
 
-const LOCK_TIMEOUT: Duration = Duration::from_secs(60); // 1 minute
+/// タイムアウトが設定されている場合に、ロックの取得を再試行する間隔
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 /// ロックファイルとタスクファイルを管理する構造体
 pub struct LockManager {
+    is_global: bool,
     lock_path: PathBuf,
     tasks_path: PathBuf,
+    /// ロックを取得する際に、以前の場所に残っている状態を移動するかどうか
+    migrates: bool,
+}
+
+/// 取得したロックを保持する構造体
+///
+/// この値がドロップされるとロックが解放されます。
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct LockGuard {
+    file: Option<File>,
+    exclusive: bool,
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        if self.exclusive {
+            // 保持者のPIDを消してから解放する
+            let _ = file.set_len(0);
+        }
+        let _ = file.unlock();
+    }
 }
 
 impl LockManager {
@@ -26,68 +62,288 @@ impl LockManager {
     ///
     /// * `is_global` - グローバルなロックファイルを管理するかどうか
     pub fn new(is_global: bool) -> Self {
-        if is_global {
-            Self {
-                lock_path: global::lock_filepath(),
-                tasks_path: global::tasks_filepath(),
-            }
+        let lock_manager = if is_global {
+            Self::at(
+                true,
+                global::lock_filepath(),
+                global::tasks_filepath(),
+            )
         } else {
-            Self {
-                lock_path: local::lock_filepath(),
-                tasks_path: local::tasks_filepath(),
-            }
+            Self::at(
+                false,
+                local::lock_filepath(),
+                local::tasks_filepath(),
+            )
+        };
+        Self { migrates: true, ..lock_manager }
+    }
+
+    /// 指定されたパスのロックファイルとタスクファイルを管理する`LockManager`を作成します。
+    ///
+    /// この`LockManager`は、ロックを取得する際に以前の場所に残っている状態を移動しません。
+    fn at(
+        is_global: bool,
+        lock_path: PathBuf,
+        tasks_path: PathBuf,
+    ) -> Self {
+        Self { is_global, lock_path, tasks_path, migrates: false }
+    }
+
+    /// 排他ロックを取得します。
+    ///
+    /// 他のプロセスがロックを保持している場合は、そのプロセスのPIDを表示して解放されるまで待機します。
+    /// ロックを取得すると、ロックファイルに自身のPIDを書き込みます。
+    /// 前回のプロセスが中断した操作が残っている場合は、先に回復します。
+    /// 以前の場所に状態が残っている場合は、ロックを取得する前に`migrate`で移動します。
+    ///
+    /// # Returns
+    ///
+    /// `Ok(LockGuard)` - ロックの取得に成功した場合。ドロップするとロックが解放されます。
+    /// `Err(io::Error)` - 状態の移動、ロックファイルのオープン、またはロックの取得に失敗した場合
+    pub fn acquire_lock(&self) -> io::Result<LockGuard> {
+        if self.migrates {
+            self.migrate()?;
         }
+        let guard = self.lock_exclusive()?;
+        self.run_pending_tasks()?;
+        Ok(guard)
     }
 
-    /// ロックを取得します。
+    /// 中断された操作を回復せずに排他ロックを取得します。
     ///
-    /// ロックが既に取得されている場合は、タイムアウトまで待機します。
-    /// タイムアウトした場合は、エラーを返します。
+    /// ロックファイルを置くディレクトリが存在しない場合は作成します。
+    fn lock_exclusive(&self) -> io::Result<LockGuard> {
+        if let Some(parent) = self.lock_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.lock_path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => self.wait(&file, false)?,
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        file.set_len(0)?;
+        file.write_all(process::id().to_string().as_bytes())?;
+        Ok(LockGuard { file: Some(file), exclusive: true })
+    }
+
+    /// 共有ロックを取得します。
+    ///
+    /// 共有ロックは複数のプロセスが同時に保持でき、排他ロックとのみ競合します。
+    /// ロックファイルを作成する権限がなく、まだ存在しない場合はロックせずに続行します。
+    /// 以前の場所に状態が残っている場合は移動せずに警告を表示します。
+    /// 移動は排他ロックを取得する操作と`ipak system configure`が行います。
     ///
     /// # Returns
     ///
-    /// `Ok(())` - ロックの取得に成功した場合
+    /// `Ok(LockGuard)` - ロックの取得に成功した場合。ドロップするとロックが解放されます。
     /// `Err(io::Error)` - ロックの取得に失敗した場合
-    pub fn acquire_lock(&self) -> io::Result<()> {
-        let start_time = SystemTime::now();
-        loop {
-            if self.is_lock_stale()? {
-                self.clear_stale_lock()?;
-                self.run_pending_tasks()?;
-            }
+    pub fn acquire_shared_lock(&self) -> io::Result<LockGuard> {
+        if self.migrates {
+            self.warn_pending_migration();
+        }
+        let file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.lock_path)
+        {
+            Ok(file) => file,
+            Err(_) => match File::open(&self.lock_path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    return Ok(LockGuard { file: None, exclusive: false });
+                }
+                Err(e) => return Err(e),
+            },
+        };
+        match file.try_lock_shared() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => self.wait(&file, true)?,
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        Ok(LockGuard { file: Some(file), exclusive: false })
+    }
 
-            if let Ok(mut file) = OpenOptions::new()
-                .write(true)
-                .create_new(true)
-                .open(&self.lock_path)
-            {
-                file.write_all(process::id().to_string().as_bytes())?;
-                return Ok(());
-            }
+    /// 以前のバージョンの場所に残っている状態を、新しい場所へ移動します。
+    ///
+    /// 以前のバージョンの`ipak`や他のプロセスと同時に移動しないように、
+    /// 以前の場所のロックファイルの排他ロックを保持したまま移動します。
+    /// 以前の場所に中断された操作が残っている場合は、移動の前にその場所で回復します。
+    /// グローバルモードで移動先にパッケージが既にある場合は、データベース単位で統合します。
+    /// 移動する状態がない場合は何もしません。
+    /// `acquire_lock`と`recover`も移動を行うため、
+    /// この関数は初期設定の前など、ロックを取得せずに移動したい場合に使用します。
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` - 以前の場所で中断された操作を回復した場合
+    /// `Ok(false)` - 回復する操作がなかった場合、または移動する状態がなかった場合
+    /// `Err(io::Error)` - 回復または移動に失敗した場合
+    pub fn migrate(&self) -> io::Result<bool> {
+        if self.is_global {
+            self.migrate_global().map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "Global packages in {} have not been moved to {}: {}. Run `ipak system configure --global` as root to move them",
+                        global::legacy_packages_dirpath().display(),
+                        global::packages_dirpath().display(),
+                        e
+                    ),
+                )
+            })
+        } else {
+            self.migrate_local()
+        }
+    }
 
-            if start_time.elapsed().unwrap_or_default() > LOCK_TIMEOUT {
-                return Err(io::Error::new(
-                    io::ErrorKind::TimedOut,
-                    "Failed to acquire lock",
-                ));
-            }
+    /// 以前の場所に移動されていない状態が残っている場合に、警告を表示します。
+    fn warn_pending_migration(&self) {
+        if self.is_global && global::needs_migration() {
+            log::warn!(
+                "Global packages in {} have not been moved to {} yet. Run `ipak system configure --global` as root to move them",
+                global::legacy_packages_dirpath().display(),
+                global::packages_dirpath().display()
+            );
+        } else if !self.is_global && local::needs_migration() {
+            log::warn!(
+                "Local data in {} has not been moved to {} yet. Run `ipak system configure --local` or any command that changes packages to move it",
+                local::legacy_dirpath().display(),
+                local::data_dirpath().display()
+            );
+        }
+    }
 
-            std::thread::sleep(Duration::from_millis(100));
+    /// `~/.ipak`に残っているデータを、XDG Base Directoryなどで指定された場所へ移動します。
+    fn migrate_local(&self) -> io::Result<bool> {
+        if !local::needs_migration() {
+            return Ok(false);
+        }
+        let legacy_dir = local::legacy_dirpath();
+        let legacy = Self::at(
+            false,
+            legacy_dir.join("lock"),
+            legacy_dir.join("tasks"),
+        );
+        let _guard = legacy.lock_exclusive()?;
+        let recovered = legacy.run_pending_tasks()?;
+        for (from, to) in local::migrate()? {
+            log::warn!("Moved {} to {}", from.display(), to.display());
         }
+        Ok(recovered)
     }
 
-    /// ロックを解放します。
+    /// `/etc/ipak`に残っているパッケージとタスクファイルを`/var/lib/ipak`へ移動します。
+    ///
+    /// 移動先に既にパッケージディレクトリがある場合は、残っているパッケージを統合し、
+    /// 統合できなかったパッケージを含む以前のディレクトリを`/etc/ipak/packages.old`として残します。
+    fn migrate_global(&self) -> io::Result<bool> {
+        if !global::needs_migration() {
+            return Ok(false);
+        }
+        let config_dir = global::config_dirpath();
+        let legacy = Self::at(
+            true,
+            config_dir.join("lock"),
+            config_dir.join("tasks"),
+        );
+        let _guard = legacy.lock_exclusive()?;
+        let recovered = legacy.run_pending_tasks()?;
+        for (from, to) in global::migrate()? {
+            log::warn!("Moved {} to {}", from.display(), to.display());
+        }
+
+        let legacy_packages_dir = global::legacy_packages_dirpath();
+        if !legacy_packages_dir.is_dir()
+            || legacy_packages_dir.is_symlink()
+        {
+            return Ok(recovered);
+        }
+        let packages_dir = global::packages_dirpath();
+        let skipped = FileDatabase::new(packages_dir.clone())
+            .merge_from(&FileDatabase::new(legacy_packages_dir.clone()))?;
+        let kept_dir = legacy_packages_dir.with_extension("old");
+        fs::rename(&legacy_packages_dir, &kept_dir)?;
+        log::warn!(
+            "Merged the packages in {} into {}",
+            legacy_packages_dir.display(),
+            packages_dir.display()
+        );
+        if !skipped.is_empty() {
+            log::warn!(
+                "Kept the copies in {} of the packages installed in both: {}. The others are left in {}",
+                packages_dir.display(),
+                skipped.join(", "),
+                kept_dir.display()
+            );
+        }
+        Ok(recovered)
+    }
+
+    /// 他のプロセスがロックを解放するまで待機し、ロックを取得します。
+    ///
+    /// 設定の`lock.timeout`が指定されている場合は、その時間を過ぎると待機をやめます。
     ///
     /// # Returns
     ///
-    /// `Ok(())` - ロックの解放に成功した場合
-    /// `Err(io::Error)` - ロックの解放に失敗した場合
-    pub fn release_lock(&self) -> io::Result<()> {
-        fs::remove_file(&self.lock_path)
+    /// `Ok(())` - ロックの取得に成功した場合
+    /// `Err(io::Error)` - タイムアウトした場合（`io::ErrorKind::TimedOut`）、またはロックの取得に失敗した場合
+    fn wait(&self, file: &File, shared: bool) -> io::Result<()> {
+        self.report_waiting();
+        let Some(timeout) = config::get().lock_timeout() else {
+            return if shared { file.lock_shared() } else { file.lock() };
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            thread::sleep(LOCK_POLL_INTERVAL);
+            let result = if shared {
+                file.try_lock_shared()
+            } else {
+                file.try_lock()
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(TryLockError::WouldBlock)
+                    if Instant::now() < deadline => {}
+                Err(TryLockError::WouldBlock) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!(
+                            "Timed out after {}s waiting for the lock on {}",
+                            timeout.as_secs(),
+                            self.lock_path.display()
+                        ),
+                    ));
+                }
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+        }
+    }
+
+    /// ロックの解放を待機していることを表示します。
+    fn report_waiting(&self) {
+        match read_holder_pid(&self.lock_path) {
+            Some(pid) => {
+                log::warn!("Waiting for lock held by PID {}...", pid)
+            }
+            None => log::warn!(
+                "Waiting for lock held by another process ({})...",
+                self.lock_path.display()
+            ),
+        }
     }
 
     /// タスクを追加します。
     ///
+    /// タスクはジャーナルとしてタスクファイルに追記され、ディスクに書き込まれてから戻ります。
+    ///
     /// # Arguments
     ///
     /// * `task` - 追加するタスク
@@ -102,38 +358,120 @@ impl LockManager {
             .create(true)
             .open(&self.tasks_path)?;
         writeln!(file, "{}", task)?;
-        Ok(())
+        file.sync_data()
+    }
+
+    /// すべてのタスクを削除します。
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` - タスクの削除に成功した場合、またはタスクが存在しない場合
+    /// `Err(io::Error)` - タスクファイルの削除に失敗した場合
+    pub fn clear_tasks(&self) -> io::Result<()> {
+        match fs::remove_file(&self.tasks_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// 前回のプロセスが中断した操作を回復します。
+    ///
+    /// 排他ロックを取得してから保留中のタスクを処理します。
+    /// 以前のバージョンの場所に中断された操作が残っている場合は、`migrate`でそれも回復します。
+    /// `acquire_lock`も同じ回復を行うため、この関数は明示的に回復したい場合に使用します。
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` - 中断された操作を回復した場合
+    /// `Ok(false)` - 回復する操作がなかった場合
+    /// `Err(io::Error)` - ロックの取得、または回復に失敗した場合
+    pub fn recover(&self) -> io::Result<bool> {
+        let recovered_legacy = self.migrates && self.migrate()?;
+        let _guard = self.lock_exclusive()?;
+        Ok(self.run_pending_tasks()? || recovered_legacy)
     }
 
     /// 保留中のタスクを実行します。
-    fn run_pending_tasks(&self) -> io::Result<()> {
+    ///
+    /// 排他ロックを取得した時点でタスクが残っている場合、
+    /// それは途中で終了したプロセスが残したジャーナルです。
+    /// ジャーナルを元に、中断されたトランザクションを確定またはロールバックします。
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` - 保留中のタスクを処理した場合
+    /// `Ok(false)` - 保留中のタスクがなかった場合
+    /// `Err(io::Error)` - タスクの処理に失敗した場合
+    fn run_pending_tasks(&self) -> io::Result<bool> {
         if !self.tasks_path.exists() {
-            return Ok(());
+            return Ok(false);
         }
 
         let mut tasks = String::new();
         File::open(&self.tasks_path)?.read_to_string(&mut tasks)?;
+        if tasks.trim().is_empty() {
+            self.clear_tasks()?;
+            return Ok(false);
+        }
+
+        log::warn!(
+            "Found an operation interrupted in a previous run; recovering..."
+        );
+        let mode = if self.is_global {
+            ExecMode::Global
+        } else {
+            ExecMode::Local
+        };
+        transaction::recover(mode, self, &tasks)?;
+        Ok(true)
+    }
+}
 
-        // TODO: Implement task execution logic here
+/// ロックファイルに書き込まれた、排他ロックを保持しているプロセスのPIDを読み取ります。
+fn read_holder_pid(lock_path: &Path) -> Option<u32> {
+    fs::read_to_string(lock_path).ok()?.trim().parse().ok()
+}
 
-        fs::remove_file(&self.tasks_path)?;
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock_manager(dir: &Path) -> LockManager {
+        LockManager::at(false, dir.join("lock"), dir.join("tasks"))
     }
 
-    /// ロックが古いかどうかを確認します。
-    fn is_lock_stale(&self) -> io::Result<bool> {
-        if !self.lock_path.exists() {
-            return Ok(false);
-        }
+    #[test]
+    fn test_exclusive_lock_records_pid_until_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_manager = lock_manager(dir.path());
 
-        let metadata = fs::metadata(&self.lock_path)?;
-        let modified_time = metadata.modified()?;
+        let guard = lock_manager.acquire_lock().unwrap();
+        assert_eq!(
+            read_holder_pid(&lock_manager.lock_path),
+            Some(process::id())
+        );
+        let other = File::open(&lock_manager.lock_path).unwrap();
+        assert!(matches!(
+            other.try_lock_shared(),
+            Err(TryLockError::WouldBlock)
+        ));
 
-        Ok(modified_time.elapsed().unwrap_or_default() > LOCK_TIMEOUT)
+        drop(guard);
+        assert_eq!(read_holder_pid(&lock_manager.lock_path), None);
+        assert!(other.try_lock_shared().is_ok());
     }
 
-    /// 古いロックをクリアします。
-    fn clear_stale_lock(&self) -> io::Result<()> {
-        fs::remove_file(&self.lock_path)
+    #[test]
+    fn test_shared_locks_exclude_only_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_manager = lock_manager(dir.path());
+
+        let first = lock_manager.acquire_shared_lock().unwrap();
+        let second = lock_manager.acquire_shared_lock().unwrap();
+        let other = File::open(&lock_manager.lock_path).unwrap();
+        assert!(matches!(other.try_lock(), Err(TryLockError::WouldBlock)));
+
+        drop((first, second));
+        assert!(other.try_lock().is_ok());
     }
 }
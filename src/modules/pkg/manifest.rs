@@ -0,0 +1,531 @@
+//! このモジュールは、パッケージがディスク上に配置したファイルのマニフェストを管理します。
+//!
+//! インストールスクリプトの実行前後でインストール先ディレクトリのスナップショットを取り、
+//! 新たに作成されたファイルのパス、ハッシュ、パーミッションを記録します。
+//! また、パッケージディレクトリにコピーされたファイルのハッシュも記録します。
+//! 記録したマニフェストは、削除・パージ時にスクリプトが残したファイルの後片付けと、
+//! `ipak pkg verify`によるファイルの誤った編集や破損の検出に使用されます。
+
+use super::list::RECORD_FILEPATH;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// パッケージディレクトリ内でマニフェストを保存する相対パスです。
+const MANIFEST_FILEPATH: &str = "ipak/files.yaml";
+
+/// パッケージディレクトリ内でipakが書き込む管理用のファイルです。インストール後に書き換えられるため検証の対象外です。
+const BOOKKEEPING_FILEPATHS: [&str; 2] =
+    [MANIFEST_FILEPATH, RECORD_FILEPATH];
+
+/// マニフェストに記録される個々のファイルを表す構造体です。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ManifestEntry {
+    /// ファイルのパス。
+    /// スクリプトが配置したファイルは絶対パス、パッケージディレクトリ内のファイルはそこからの相対パスです。
+    pub path: PathBuf,
+    /// ファイル内容のSHA-256ハッシュ（16進数）。
+    /// シンボリックリンクの場合はリンク先パスのハッシュです。
+    pub sha256: String,
+    /// ファイルのパーミッション（Unixのモードビット）。
+    pub mode: u32,
+    /// 旧バージョンから引き継いだ、利用者が変更した設定ファイルかどうか。
+    /// このパッケージが作成したファイルではないため、インストールの取り消し時には削除しません。
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub config: bool,
+}
+
+/// パッケージがインストール時に配置したファイルの一覧です。
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct FileManifest {
+    /// インストールスクリプトによって作成されたファイル。
+    pub files: Vec<ManifestEntry>,
+    /// パッケージディレクトリにコピーされたファイル。
+    pub package_files: Vec<ManifestEntry>,
+}
+
+/// 検証で見つかったファイルの問題を表す列挙型です。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileStatus {
+    /// ファイルが存在しない。
+    Missing,
+    /// ファイルの内容が記録時と異なる。
+    Modified,
+    /// ファイルのパーミッションが記録時と異なる。
+    ModeChanged {
+        /// 記録時のパーミッション。
+        recorded: u32,
+        /// 現在のパーミッション。
+        actual: u32,
+    },
+    /// パッケージディレクトリに記録されていないファイルがある。
+    Extra,
+}
+
+impl Display for FileStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FileStatus::Missing => write!(f, "missing"),
+            FileStatus::Modified => write!(f, "modified"),
+            FileStatus::ModeChanged { recorded, actual } => {
+                write!(f, "mode {:04o} -> {:04o}", recorded, actual)
+            }
+            FileStatus::Extra => write!(f, "extra"),
+        }
+    }
+}
+
+/// インストール先ディレクトリのある時点での状態です。
+///
+/// 存在するファイルのパスのみを保持し、内容は読み込みません。
+pub struct Snapshot {
+    paths: HashSet<PathBuf>,
+}
+
+impl Snapshot {
+    /// 指定されたディレクトリ以下のファイルのスナップショットを取得します。
+    ///
+    /// 存在しないディレクトリと、他のディレクトリに含まれるディレクトリは無視されます。
+    ///
+    /// # Arguments
+    /// * `roots` - スナップショットを取得するディレクトリのリスト。
+    pub fn take(roots: &[PathBuf]) -> Self {
+        let mut paths = HashSet::new();
+        let roots = roots.iter().filter(|root| {
+            root.is_dir()
+                && !roots
+                    .iter()
+                    .any(|other| other != *root && root.starts_with(other))
+        });
+        for root in roots {
+            for entry in WalkDir::new(root).follow_links(false) {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        log::debug!("Skipping unreadable entry: {}", e);
+                        continue;
+                    }
+                };
+                if !entry.file_type().is_dir() {
+                    paths.insert(entry.into_path());
+                }
+            }
+        }
+        Self { paths }
+    }
+
+    /// `before`の時点では存在せず、新たに作成されたファイルのパスを返します。
+    ///
+    /// 既に存在していたファイルは、スクリプトが書き換えた場合でもパッケージのものとはみなしません。
+    /// 削除時やインストール失敗時に、利用者や他のパッケージのファイルを消さないためです。
+    ///
+    /// # Arguments
+    /// * `before` - 比較対象となる以前のスナップショット。
+    ///
+    /// # Returns
+    /// 作成されたファイルのパス（ソート済み）。
+    pub fn created_since(&self, before: &Snapshot) -> Vec<PathBuf> {
+        let mut created = self
+            .paths
+            .difference(&before.paths)
+            .cloned()
+            .collect::<Vec<_>>();
+        created.sort();
+        created
+    }
+}
+
+impl ManifestEntry {
+    /// 指定されたパスのファイルからマニフェストのエントリを作成します。
+    ///
+    /// # Arguments
+    /// * `path` - 記録するファイルのパス。
+    ///
+    /// # Returns
+    /// `Ok(ManifestEntry)` 作成されたエントリ。
+    /// `Err(io::Error)` ファイルの読み込みに失敗した場合。
+    pub fn from_path(path: &Path) -> Result<Self, io::Error> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            sha256: hash_file(path)?,
+            mode: file_mode(&path.symlink_metadata()?),
+            config: false,
+        })
+    }
+
+    /// ファイルを記録時の状態と比較します。
+    ///
+    /// # Arguments
+    /// * `base_dir` - 相対パスで記録されたファイルの基準となるディレクトリ。
+    ///
+    /// # Returns
+    /// `Ok(None)` ファイルが記録時と同じ場合。
+    /// `Ok(Some(FileStatus))` ファイルが存在しない、または内容かパーミッションが異なる場合。
+    /// `Err(io::Error)` ファイルの読み込みに失敗した場合。
+    pub fn check(
+        &self,
+        base_dir: &Path,
+    ) -> Result<Option<FileStatus>, io::Error> {
+        let path = base_dir.join(&self.path);
+        let metadata = match path.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Some(FileStatus::Missing));
+            }
+            Err(e) => return Err(e),
+        };
+        if hash_file(&path)? != self.sha256 {
+            return Ok(Some(FileStatus::Modified));
+        }
+        let actual = file_mode(&metadata);
+        if actual != self.mode {
+            return Ok(Some(FileStatus::ModeChanged {
+                recorded: self.mode,
+                actual,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// ファイルが記録時から変更されているかどうかを確認します。
+    ///
+    /// # Returns
+    /// `Ok(true)` ファイルの内容が記録時と異なる場合。
+    /// `Ok(false)` ファイルの内容が記録時と同じ場合。
+    /// `Err(io::Error)` ファイルが存在しない、または読み込みに失敗した場合。
+    pub fn is_modified(&self) -> Result<bool, io::Error> {
+        Ok(hash_file(&self.path)? != self.sha256)
+    }
+}
+
+impl FileManifest {
+    /// 指定されたファイルのリストからマニフェストを作成します。
+    ///
+    /// # Arguments
+    /// * `paths` - 記録するファイルのパス。
+    ///
+    /// # Returns
+    /// `Ok(FileManifest)` 作成されたマニフェスト。
+    /// `Err(io::Error)` ファイルの読み込みに失敗した場合。
+    pub fn from_paths(paths: &[PathBuf]) -> Result<Self, io::Error> {
+        let files = paths
+            .iter()
+            .map(|path| ManifestEntry::from_path(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { files, ..Default::default() })
+    }
+
+    /// パッケージディレクトリ内のファイルを`package_files`に記録します。
+    ///
+    /// ipakが管理用に書き込むファイル（マニフェスト自身とパッケージのレコード）は記録しません。
+    ///
+    /// # Arguments
+    /// * `pkg_dir` - パッケージがインストールされているディレクトリ。
+    ///
+    /// # Returns
+    /// `Ok(())` 記録に成功した場合。
+    /// `Err(io::Error)` ディレクトリまたはファイルの読み込みに失敗した場合。
+    pub fn record_package_files(
+        &mut self,
+        pkg_dir: &Path,
+    ) -> Result<(), io::Error> {
+        let mut package_files = Vec::new();
+        for relative_path in package_file_paths(pkg_dir)? {
+            let mut entry =
+                ManifestEntry::from_path(&pkg_dir.join(&relative_path))?;
+            entry.path = relative_path;
+            package_files.push(entry);
+        }
+        self.package_files = package_files;
+        Ok(())
+    }
+
+    /// 記録されたファイルを検証し、問題のあるファイルを返します。
+    ///
+    /// パッケージディレクトリにコピーされたファイルとスクリプトが配置したファイルを記録時の状態と比較し、
+    /// パッケージディレクトリのファイルが記録されている場合は、記録にない余分なファイルも報告します。
+    /// 旧バージョンから引き継いだ設定ファイルは利用者が変更したものであるため、検証しません。
+    ///
+    /// # Arguments
+    /// * `pkg_dir` - パッケージがインストールされているディレクトリ。
+    ///
+    /// # Returns
+    /// `Ok(Vec<(PathBuf, FileStatus)>)` 問題のあるファイルのパスと状態（パス順）。
+    /// `Err(io::Error)` ファイルの読み込みに失敗した場合。
+    pub fn verify(
+        &self,
+        pkg_dir: &Path,
+    ) -> Result<Vec<(PathBuf, FileStatus)>, io::Error> {
+        let mut issues = Vec::new();
+        let entries = self.package_files.iter().chain(&self.files);
+        for entry in entries.filter(|entry| !entry.config) {
+            if let Some(status) = entry.check(pkg_dir)? {
+                issues.push((pkg_dir.join(&entry.path), status));
+            }
+        }
+        if !self.package_files.is_empty() {
+            let recorded = self
+                .package_files
+                .iter()
+                .map(|entry| entry.path.as_path())
+                .collect::<HashSet<_>>();
+            for relative_path in package_file_paths(pkg_dir)? {
+                if !recorded.contains(relative_path.as_path()) {
+                    issues.push((
+                        pkg_dir.join(relative_path),
+                        FileStatus::Extra,
+                    ));
+                }
+            }
+        }
+        issues.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(issues)
+    }
+
+    /// パッケージディレクトリに保存されたマニフェストを読み込みます。
+    ///
+    /// マニフェストが存在しない場合は、空のマニフェストを返します。
+    ///
+    /// # Arguments
+    /// * `pkg_dir` - パッケージがインストールされているディレクトリ。
+    ///
+    /// # Returns
+    /// `Ok(FileManifest)` 読み込まれたマニフェスト。
+    /// `Err(io::Error)` ファイルの読み込みまたはパースに失敗した場合。
+    pub fn load(pkg_dir: &Path) -> Result<Self, io::Error> {
+        let manifest_path = pkg_dir.join(MANIFEST_FILEPATH);
+        let manifest_str = match fs::read_to_string(&manifest_path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(e),
+        };
+        serde_yaml::from_str(&manifest_str).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse file manifest '{}': {}",
+                    manifest_path.display(),
+                    e
+                ),
+            )
+        })
+    }
+
+    /// マニフェストをパッケージディレクトリに保存します。
+    ///
+    /// # Arguments
+    /// * `pkg_dir` - パッケージがインストールされているディレクトリ。
+    ///
+    /// # Returns
+    /// `Ok(())` 保存に成功した場合。
+    /// `Err(io::Error)` シリアライズまたは書き込みに失敗した場合。
+    pub fn save(&self, pkg_dir: &Path) -> Result<(), io::Error> {
+        let manifest_path = pkg_dir.join(MANIFEST_FILEPATH);
+        if let Some(parent_dir) = manifest_path.parent() {
+            fs::create_dir_all(parent_dir)?;
+        }
+        let yaml_string = serde_yaml::to_string(self).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed to serialize file manifest: {}", e),
+            )
+        })?;
+        fs::write(&manifest_path, yaml_string)
+    }
+
+    /// マニフェストに記録されたファイルのうち、まだ残っているものを削除します。
+    ///
+    /// 削除は`remove`に任せるため、呼び出し元はトランザクションのバックアップへ移動することもできます。
+    ///
+    /// # Arguments
+    /// * `keep_modified` - `true`の場合、記録時から内容が変更されたファイル（設定ファイルなど）は削除しません。
+    /// * `remove` - ファイルを削除する関数。
+    ///
+    /// # Returns
+    /// `Ok(Vec<PathBuf>)` 変更されていたため残されたファイルのパス。
+    /// `Err(io::Error)` ファイルの削除に失敗した場合。
+    pub fn remove_files(
+        &self,
+        keep_modified: bool,
+        mut remove: impl FnMut(&Path) -> Result<(), io::Error>,
+    ) -> Result<Vec<PathBuf>, io::Error> {
+        let mut kept = Vec::new();
+        for entry in &self.files {
+            if entry.path.symlink_metadata().is_err() {
+                continue;
+            }
+            if keep_modified && entry.is_modified()? {
+                log::warn!(
+                    "Keeping modified file: {}",
+                    entry.path.display()
+                );
+                kept.push(entry.path.clone());
+                continue;
+            }
+            remove(&entry.path)?;
+            log::debug!("Removed file: {}", entry.path.display());
+        }
+        Ok(kept)
+    }
+}
+
+/// パッケージディレクトリ内のファイルの相対パスを返します。管理用のファイルは除外されます。
+fn package_file_paths(pkg_dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut paths = Vec::new();
+    for entry in
+        WalkDir::new(pkg_dir).follow_links(false).sort_by_file_name()
+    {
+        let entry = entry.map_err(io::Error::from)?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let relative_path = entry
+            .path()
+            .strip_prefix(pkg_dir)
+            .map_err(io::Error::other)?
+            .to_path_buf();
+        if !BOOKKEEPING_FILEPATHS
+            .iter()
+            .any(|bookkeeping| relative_path == Path::new(bookkeeping))
+        {
+            paths.push(relative_path);
+        }
+    }
+    Ok(paths)
+}
+
+/// ファイルのパーミッション（Unixのモードビット）を返します。
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o7777
+    }
+    #[cfg(not(unix))]
+    {
+        if metadata.permissions().readonly() { 0o444 } else { 0o644 }
+    }
+}
+
+/// ファイルのSHA-256ハッシュを計算します。
+///
+/// シンボリックリンクの場合は、リンク先のパス文字列をハッシュします。
+///
+/// # Arguments
+/// * `path` - ハッシュを計算するファイルのパス。
+///
+/// # Returns
+/// `Ok(String)` 16進数で表現されたハッシュ値。
+/// `Err(io::Error)` ファイルの読み込みに失敗した場合。
+pub fn hash_file(path: &Path) -> Result<String, io::Error> {
+    let mut hasher = Sha256::new();
+    if path.symlink_metadata()?.file_type().is_symlink() {
+        let target = fs::read_link(path)?;
+        hasher.update(target.to_string_lossy().as_bytes());
+    } else {
+        let mut file = File::open(path)?;
+        io::copy(&mut file, &mut hasher)?;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_snapshot_claims_only_created_files() {
+        let temp_dir = TempDir::with_prefix("manifest_snapshot").unwrap();
+        let root = temp_dir.path().to_path_buf();
+        fs::create_dir(root.join("bin")).unwrap();
+        let roots = vec![root.clone(), root.join("bin")];
+        let untouched = root.join("untouched");
+        let modified = root.join("modified");
+        fs::write(&untouched, b"same").unwrap();
+        fs::write(&modified, b"old").unwrap();
+
+        let before = Snapshot::take(&roots);
+        fs::write(&modified, b"new content").unwrap();
+        let created = root.join("bin").join("tool");
+        File::create(&created).unwrap().write_all(b"binary").unwrap();
+        let after = Snapshot::take(&roots);
+
+        assert_eq!(after.created_since(&before), vec![created]);
+    }
+
+    #[test]
+    fn test_remove_files_keeps_modified_files() {
+        let temp_dir = TempDir::with_prefix("manifest_remove").unwrap();
+        let binary = temp_dir.path().join("tool");
+        let config = temp_dir.path().join("tool.conf");
+        fs::write(&binary, b"binary").unwrap();
+        fs::write(&config, b"default").unwrap();
+
+        let manifest =
+            FileManifest::from_paths(&[binary.clone(), config.clone()])
+                .unwrap();
+        manifest.save(temp_dir.path()).unwrap();
+        let manifest = FileManifest::load(temp_dir.path()).unwrap();
+        assert_eq!(manifest.files.len(), 2);
+
+        fs::write(&config, b"edited by user").unwrap();
+        let kept = manifest
+            .remove_files(true, |path| fs::remove_file(path))
+            .unwrap();
+        assert_eq!(kept, vec![config.clone()]);
+        assert!(!binary.exists());
+        assert!(config.exists());
+
+        let kept = manifest
+            .remove_files(false, |path| fs::remove_file(path))
+            .unwrap();
+        assert!(kept.is_empty());
+        assert!(!config.exists());
+    }
+
+    #[test]
+    fn test_verify_reports_modified_missing_and_extra_files() {
+        let temp_dir = TempDir::with_prefix("manifest_verify").unwrap();
+        let pkg_dir = temp_dir.path();
+        fs::create_dir_all(pkg_dir.join("ipak")).unwrap();
+        fs::write(pkg_dir.join("ipak/project.yaml"), b"metadata").unwrap();
+        fs::write(pkg_dir.join("data"), b"original").unwrap();
+        fs::write(pkg_dir.join("doc"), b"readme").unwrap();
+
+        let mut manifest = FileManifest::default();
+        manifest.record_package_files(pkg_dir).unwrap();
+        manifest.save(pkg_dir).unwrap();
+        assert_eq!(manifest.package_files.len(), 3);
+        assert!(manifest.verify(pkg_dir).unwrap().is_empty());
+
+        fs::write(pkg_dir.join("data"), b"tampered").unwrap();
+        fs::remove_file(pkg_dir.join("doc")).unwrap();
+        fs::write(pkg_dir.join("ipak/extra"), b"unexpected").unwrap();
+        let config_dir = TempDir::with_prefix("manifest_config").unwrap();
+        let config_path = config_dir.path().join("tool.conf");
+        fs::write(&config_path, b"edited").unwrap();
+        let mut config = ManifestEntry::from_path(&config_path).unwrap();
+        config.sha256 = "stale".to_string();
+        config.config = true;
+        manifest.files.push(config);
+        let issues = manifest.verify(pkg_dir).unwrap();
+        assert_eq!(
+            issues,
+            vec![
+                (pkg_dir.join("data"), FileStatus::Modified),
+                (pkg_dir.join("doc"), FileStatus::Missing),
+                (pkg_dir.join("ipak/extra"), FileStatus::Extra),
+            ]
+        );
+    }
+}
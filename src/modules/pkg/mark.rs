@@ -0,0 +1,107 @@
+//! このモジュールは、インストール済みパッケージのインストール理由を変更する機能を提供します。
+//!
+//! 自動インストールとしてマークされたパッケージは、どのパッケージからも必要とされなくなると
+//! `pkg autoremove`で削除されます。
+
+use super::depend;
+use super::list::{self, InstallReason};
+use crate::modules::pkg::lock::LockManager;
+use crate::modules::project::ExecMode;
+use crate::utils::error::Error;
+use std::io;
+
+/// 設定するインストール理由を定義する列挙型です。
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkAs {
+    /// 依存関係として自動的にインストールされたものとします。
+    Auto,
+    /// ユーザーが明示的にインストールしたものとします。
+    Manual,
+}
+
+/// 指定されたパッケージのインストール理由を変更します。
+///
+/// `auto`を指定した場合は、そのパッケージを必要としているインストール済みパッケージが
+/// あれば、その名前を依存元として記録します。
+///
+/// # Arguments
+/// * `mark_as` - 設定するインストール理由。
+/// * `package_names` - 対象のパッケージ名。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` すべてのパッケージのインストール理由が変更された場合。
+/// `Err(Error)` パッケージがインストールされていない、またはパッケージリストの更新に失敗した場合。
+pub fn mark(
+    mark_as: MarkAs,
+    package_names: &[String],
+    mode: ExecMode,
+) -> Result<(), Error> {
+    let lock_manager = LockManager::new(matches!(mode, ExecMode::Global));
+    let _lock = lock_manager.acquire_lock()?;
+    mark_packages(mark_as, package_names, mode)
+}
+
+/// ロックを取得した状態でインストール理由を変更します。
+fn mark_packages(
+    mark_as: MarkAs,
+    package_names: &[String],
+    mode: ExecMode,
+) -> Result<(), Error> {
+    let database = list::database(mode);
+    let mut data = database.load_all()?;
+    let snapshot = data.clone();
+
+    for package_name in package_names {
+        let install_reason = match mark_as {
+            MarkAs::Manual => InstallReason::Manual,
+            MarkAs::Auto => InstallReason::Dependency {
+                of: find_dependent(&data.installed_packages, package_name),
+            },
+        };
+        let installed = data
+            .installed_packages
+            .iter_mut()
+            .find(|pkg| pkg.info.about.package.name == *package_name)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Package '{}' is not installed", package_name),
+                )
+            })?;
+        log::info!("Marked '{}' as {}", package_name, install_reason);
+        installed.install_reason = install_reason;
+    }
+
+    database.snapshot(&snapshot)?;
+    for installed in data.installed_packages {
+        if package_names.contains(&installed.info.about.package.name) {
+            database.put(installed)?;
+        }
+    }
+    Ok(())
+}
+
+/// 指定されたパッケージを必要としているインストール済みパッケージの名前を返します。
+fn find_dependent(
+    installed_packages: &[list::InstalledPackageData],
+    package_name: &str,
+) -> Option<String> {
+    let package = &installed_packages
+        .iter()
+        .find(|pkg| pkg.info.about.package.name == package_name)?
+        .info;
+    installed_packages
+        .iter()
+        .find(|installed| {
+            let relation = &installed.info.relation;
+            relation
+                .depend
+                .iter()
+                .chain(&relation.recommends)
+                .chain(&relation.suggests)
+                .flatten()
+                .any(|dep| depend::provides(package, dep))
+        })
+        .map(|installed| installed.info.about.package.name.clone())
+}
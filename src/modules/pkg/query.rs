@@ -0,0 +1,538 @@
+//! このモジュールは、インストール済みパッケージの依存関係を調べる機能を提供します。
+//!
+//! `pkg depends`はパッケージが何に依存しているか（または何から依存されているか）を、
+//! `pkg why`はパッケージがなぜインストールされているかを表示します。
+//! どちらもORグループのうち実際に満たしている選択肢と、仮想パッケージの提供元を示し、
+//! Graphviz の DOT 形式でも出力できます。
+
+use super::depend::{self, DependencyGraph};
+use super::list::{self, InstalledPackageData};
+use super::lock::LockManager;
+use crate::modules::pkg::{PackageRange, PackageVersion};
+use crate::modules::project::ExecMode;
+use crate::utils::error::Error;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+/// 依存関係の種類を表す列挙型です。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelationKind {
+    /// 必須の依存関係（`depend`）。
+    Depend,
+    /// 推奨される依存関係（`recommends`）。
+    Recommends,
+    /// 提案された依存関係（`suggests`）。
+    Suggests,
+}
+
+impl Display for RelationKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RelationKind::Depend => write!(f, "depends"),
+            RelationKind::Recommends => write!(f, "recommends"),
+            RelationKind::Suggests => write!(f, "suggests"),
+        }
+    }
+}
+
+/// インストール済みパッケージから依存関係グループへの辺です。
+#[derive(Clone, Copy)]
+pub struct DependencyEdge<'a> {
+    /// 依存元のパッケージ。
+    pub from: &'a InstalledPackageData,
+    /// 依存関係の種類。
+    pub kind: RelationKind,
+    /// 依存関係グループ（OR条件）。
+    pub group: &'a [PackageRange],
+    /// グループ内で実際に満たされている選択肢と、それを満たすパッケージ。
+    pub satisfied_by: Option<(&'a PackageRange, &'a InstalledPackageData)>,
+}
+
+impl DependencyEdge<'_> {
+    /// 依存関係が仮想パッケージによって満たされている場合、その仮想パッケージを返します。
+    pub fn via_virtual(&self) -> Option<&PackageVersion> {
+        let (alternative, provider) = self.satisfied_by?;
+        if provider.info.about.package.name == alternative.name {
+            return None;
+        }
+        provider.info.relation.virtuals.iter().find(|virtual_pkg| {
+            virtual_pkg.name == alternative.name
+                && alternative.range.compare(&virtual_pkg.version)
+        })
+    }
+
+    /// 依存先のパッケージ名を返します。
+    fn target_name(&self) -> Option<&str> {
+        self.satisfied_by
+            .map(|(_, provider)| provider.info.about.package.name.as_str())
+    }
+
+    /// 依存元から見た辺の説明を返します。
+    ///
+    /// 例: `depends pkg-a (>= 1.0) | pkg-b (*) -> pkg-a 1.0`
+    fn describe_forward(&self) -> String {
+        let target = match self.satisfied_by {
+            None => "(not installed)".to_string(),
+            Some((alternative, provider)) => {
+                let mut target = format!(
+                    "{} {}",
+                    provider.info.about.package.name,
+                    provider.info.about.package.version
+                );
+                if let Some(virtual_pkg) = self.via_virtual() {
+                    target.push_str(&format!(
+                        " (provides {} {})",
+                        virtual_pkg.name, virtual_pkg.version
+                    ));
+                } else if self.group.len() > 1 {
+                    target.push_str(&format!(" (via {})", alternative));
+                }
+                target
+            }
+        };
+        format!(
+            "{} {} -> {}",
+            self.kind,
+            depend::incompatibility::format_group(self.group),
+            target
+        )
+    }
+
+    /// 依存先から見た辺の説明を返します。
+    ///
+    /// 例: `pkg-b 1.0 depends pkg-a (>= 1.0)`
+    fn describe_reverse(&self) -> String {
+        let mut description = format!(
+            "{} {} {} {}",
+            self.from.info.about.package.name,
+            self.from.info.about.package.version,
+            self.kind,
+            depend::incompatibility::format_group(self.group)
+        );
+        if let Some(virtual_pkg) = self.via_virtual() {
+            description.push_str(&format!(" (via {})", virtual_pkg.name));
+        }
+        description
+    }
+}
+
+/// パッケージの依存関係グループごとの辺を返します。
+///
+/// 各グループについて、インストール済みのパッケージで満たされている最初の選択肢を探します。
+///
+/// # Arguments
+/// * `graph` - インストール済みパッケージの依存関係グラフ。
+/// * `package` - 依存元のパッケージ。
+///
+/// # Returns
+/// `depend`、`recommends`、`suggests`の順に並んだ辺のリスト。
+pub fn dependency_edges<'a>(
+    graph: &'a DependencyGraph,
+    package: &'a InstalledPackageData,
+) -> Vec<DependencyEdge<'a>> {
+    let relation = &package.info.relation;
+    [
+        (RelationKind::Depend, &relation.depend),
+        (RelationKind::Recommends, &relation.recommends),
+        (RelationKind::Suggests, &relation.suggests),
+    ]
+    .into_iter()
+    .flat_map(|(kind, groups)| {
+        groups.iter().map(move |group| DependencyEdge {
+            from: package,
+            kind,
+            group,
+            satisfied_by: group.iter().find_map(|alternative| {
+                graph
+                    .installed_package_data
+                    .iter()
+                    .find(|installed| {
+                        depend::provides(&installed.info, alternative)
+                    })
+                    .map(|installed| (alternative, installed))
+            }),
+        })
+    })
+    .collect()
+}
+
+/// 指定されたパッケージによって満たされている、他のパッケージからの辺を返します。
+///
+/// # Arguments
+/// * `graph` - インストール済みパッケージの依存関係グラフ。
+/// * `package_name` - 依存先のパッケージ名。
+///
+/// # Returns
+/// 依存先が`package_name`である辺のリスト。
+pub fn reverse_dependency_edges<'a>(
+    graph: &'a DependencyGraph,
+    package_name: &str,
+) -> Vec<DependencyEdge<'a>> {
+    graph
+        .installed_package_data
+        .iter()
+        .flat_map(|installed| dependency_edges(graph, installed))
+        .filter(|edge| edge.target_name() == Some(package_name))
+        .collect()
+}
+
+/// `pkg depends`のオプションを定義する構造体です。
+#[derive(Default, Clone, Copy)]
+pub struct DependsOptions {
+    /// 依存関係を再帰的にたどってツリー表示するかどうか。
+    pub tree: bool,
+    /// 依存先ではなく依存元（逆依存）を表示するかどうか。
+    pub reverse: bool,
+    /// Graphviz の DOT 形式で出力するかどうか。
+    pub dot: bool,
+}
+
+/// パッケージの依存関係、または逆依存を表示します。
+///
+/// # Arguments
+/// * `package_name` - 対象のパッケージ名。
+/// * `opts` - 表示オプション。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 表示に成功した場合。
+/// `Err(Error)` パッケージがインストールされていない、またはパッケージリストの読み込みに失敗した場合。
+pub fn depends(
+    package_name: &str,
+    opts: DependsOptions,
+    mode: ExecMode,
+) -> Result<(), Error> {
+    let graph = installed_graph(mode)?;
+    let package = find_installed(&graph, package_name)?;
+
+    let mut lines = vec![format!(
+        "{} {}",
+        package.info.about.package.name,
+        package.info.about.package.version
+    )];
+    let mut dot = DotGraph::default();
+    let mut visited = HashSet::from([package_name.to_string()]);
+    walk(&graph, package, opts, 1, &mut visited, &mut lines, &mut dot);
+
+    if opts.dot {
+        println!("{}", dot);
+    } else {
+        log::info!("{}", lines.join("\n"));
+    }
+    Ok(())
+}
+
+/// 依存関係（または逆依存）を再帰的にたどり、表示する行と DOT の辺を集めます。
+fn walk(
+    graph: &DependencyGraph,
+    package: &InstalledPackageData,
+    opts: DependsOptions,
+    depth: usize,
+    visited: &mut HashSet<String>,
+    lines: &mut Vec<String>,
+    dot: &mut DotGraph,
+) {
+    let indent = "  ".repeat(depth);
+    let edges = if opts.reverse {
+        reverse_dependency_edges(graph, &package.info.about.package.name)
+    } else {
+        dependency_edges(graph, package)
+    };
+    if edges.is_empty() && depth == 1 {
+        let message = if opts.reverse {
+            "(no installed package depends on this package)"
+        } else {
+            "(no dependencies)"
+        };
+        lines.push(format!("{}{}", indent, message));
+    }
+
+    for edge in edges {
+        dot.add_edge(&edge);
+        let (description, next) = if opts.reverse {
+            (edge.describe_reverse(), Some(edge.from))
+        } else {
+            (edge.describe_forward(), edge.satisfied_by.map(|(_, p)| p))
+        };
+        let Some(next) = next.filter(|_| opts.tree) else {
+            lines.push(format!("{}{}", indent, description));
+            continue;
+        };
+        let next_name = &next.info.about.package.name;
+        if !visited.insert(next_name.clone()) {
+            lines.push(format!(
+                "{}{} (already shown)",
+                indent, description
+            ));
+            continue;
+        }
+        lines.push(format!("{}{}", indent, description));
+        walk(graph, next, opts, depth + 1, visited, lines, dot);
+    }
+}
+
+/// パッケージがインストールされている理由を、手動でインストールされたパッケージからの経路として表示します。
+///
+/// # Arguments
+/// * `package_name` - 対象のパッケージ名。
+/// * `dot` - Graphviz の DOT 形式で出力するかどうか。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 表示に成功した場合。
+/// `Err(Error)` パッケージがインストールされていない、またはパッケージリストの読み込みに失敗した場合。
+pub fn why(
+    package_name: &str,
+    dot: bool,
+    mode: ExecMode,
+) -> Result<(), Error> {
+    let graph = installed_graph(mode)?;
+    let package = find_installed(&graph, package_name)?;
+
+    let paths = find_reasons(&graph, package_name);
+    let mut dot_graph = DotGraph::default();
+    let mut lines = vec![format!(
+        "{} {} ({})",
+        package.info.about.package.name,
+        package.info.about.package.version,
+        package.install_reason
+    )];
+    if paths.is_empty() && package.install_reason.is_auto() {
+        lines.push(
+            "  No installed package requires it. It can be removed with `pkg autoremove`."
+                .to_string(),
+        );
+    }
+    for path in &paths {
+        let root = path[0].from;
+        lines.push(format!(
+            "  {} {} ({})",
+            root.info.about.package.name,
+            root.info.about.package.version,
+            root.install_reason
+        ));
+        for (depth, edge) in path.iter().enumerate() {
+            dot_graph.add_edge(edge);
+            lines.push(format!(
+                "{}{}",
+                "  ".repeat(depth + 2),
+                edge.describe_forward()
+            ));
+        }
+    }
+
+    if dot {
+        println!("{}", dot_graph);
+    } else {
+        log::info!("{}", lines.join("\n"));
+    }
+    Ok(())
+}
+
+/// 手動でインストールされたパッケージから、指定されたパッケージまでの依存関係の経路を探します。
+///
+/// # Arguments
+/// * `graph` - インストール済みパッケージの依存関係グラフ。
+/// * `package_name` - 対象のパッケージ名。
+///
+/// # Returns
+/// 経路のリスト。各経路は手動でインストールされたパッケージからの辺を順に並べたものです。
+pub fn find_reasons<'a>(
+    graph: &'a DependencyGraph,
+    package_name: &str,
+) -> Vec<Vec<DependencyEdge<'a>>> {
+    let mut paths = Vec::new();
+    let mut visited = HashSet::from([package_name.to_string()]);
+    collect_reasons(
+        graph,
+        package_name,
+        &mut Vec::new(),
+        &mut visited,
+        &mut paths,
+    );
+    paths
+}
+
+/// 逆依存を深さ優先でたどり、手動でインストールされたパッケージに到達した経路を記録します。
+fn collect_reasons<'a>(
+    graph: &'a DependencyGraph,
+    package_name: &str,
+    path: &mut Vec<DependencyEdge<'a>>,
+    visited: &mut HashSet<String>,
+    paths: &mut Vec<Vec<DependencyEdge<'a>>>,
+) {
+    for edge in reverse_dependency_edges(graph, package_name) {
+        let from = edge.from;
+        let from_name = from.info.about.package.name.clone();
+        if !visited.insert(from_name.clone()) {
+            continue;
+        }
+        path.push(edge);
+        if from.install_reason.is_auto() {
+            collect_reasons(graph, &from_name, path, visited, paths);
+        } else {
+            paths.push(path.iter().rev().copied().collect());
+        }
+        path.pop();
+        visited.remove(&from_name);
+    }
+}
+
+/// Graphviz の DOT 形式のグラフを組み立てる構造体です。
+#[derive(Default)]
+struct DotGraph {
+    nodes: BTreeSet<String>,
+    edges: BTreeSet<String>,
+}
+
+impl DotGraph {
+    /// 辺と、その両端のノードを追加します。
+    fn add_edge(&mut self, edge: &DependencyEdge) {
+        let from = &edge.from.info.about.package;
+        self.nodes.insert(format!(
+            "  \"{}\" [label=\"{} {}\"];",
+            from.name, from.name, from.version
+        ));
+        let style = match edge.kind {
+            RelationKind::Depend => "solid",
+            RelationKind::Recommends => "dashed",
+            RelationKind::Suggests => "dotted",
+        };
+        match edge.satisfied_by {
+            Some((alternative, provider)) => {
+                let to = &provider.info.about.package;
+                self.nodes.insert(format!(
+                    "  \"{}\" [label=\"{} {}\"];",
+                    to.name, to.name, to.version
+                ));
+                self.edges.insert(format!(
+                    "  \"{}\" -> \"{}\" [label=\"{}\", style={}];",
+                    from.name,
+                    to.name,
+                    escape(&alternative.to_string()),
+                    style
+                ));
+            }
+            None => {
+                let group =
+                    depend::incompatibility::format_group(edge.group);
+                self.nodes.insert(format!(
+                    "  \"{}\" [shape=box, color=red];",
+                    escape(&group)
+                ));
+                self.edges.insert(format!(
+                    "  \"{}\" -> \"{}\" [style={}, color=red];",
+                    from.name,
+                    escape(&group),
+                    style
+                ));
+            }
+        }
+    }
+}
+
+impl Display for DotGraph {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "digraph dependencies {{")?;
+        for line in self.nodes.iter().chain(self.edges.iter()) {
+            writeln!(f, "{}", line)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// DOT 形式の文字列リテラル内で使用できるように文字列をエスケープします。
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// 指定されたモードのインストール済みパッケージから依存関係グラフを作成します。
+///
+/// パッケージリストは共有ロックを取得した状態で読み込まれます。
+fn installed_graph(mode: ExecMode) -> Result<DependencyGraph, io::Error> {
+    let lock_manager = LockManager::new(matches!(mode, ExecMode::Global));
+    let _lock = lock_manager.acquire_shared_lock()?;
+    let installed_packages = list::database(mode).load_all()?;
+    Ok(DependencyGraph::from_installed_packages(&installed_packages))
+}
+
+/// インストール済みのパッケージを名前で探します。
+fn find_installed<'a>(
+    graph: &'a DependencyGraph,
+    package_name: &str,
+) -> Result<&'a InstalledPackageData, io::Error> {
+    graph
+        .installed_package_data
+        .iter()
+        .find(|installed| {
+            installed.info.about.package.name == package_name
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Package '{}' is not installed", package_name),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pkg::list::PackageListData;
+
+    fn graph(packages: Vec<InstalledPackageData>) -> DependencyGraph {
+        DependencyGraph::from_installed_packages(&PackageListData {
+            installed_packages: packages,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_dependency_edges_show_satisfying_alternative() {
+        let graph = graph(vec![
+            InstalledPackageData::for_test("app", "1.0")
+                .depending_on_any(&[
+                    ("missing", ">= 1.0"),
+                    ("lib", ">= 1.0"),
+                ])
+                .depending_on_any(&[("editor", ">= 1.0")]),
+            InstalledPackageData::for_test("lib", "1.0")
+                .installed_as_dependency(),
+            InstalledPackageData::for_test("vim", "1.0")
+                .providing(&["editor"])
+                .installed_as_dependency(),
+        ]);
+        let app = &graph.installed_package_data[0];
+
+        let edges = dependency_edges(&graph, app);
+        assert_eq!(edges.len(), 2);
+        let (alternative, provider) = edges[0].satisfied_by.unwrap();
+        assert_eq!(alternative.name, "lib");
+        assert_eq!(provider.info.about.package.name, "lib");
+        assert!(edges[0].via_virtual().is_none());
+        assert_eq!(edges[1].target_name(), Some("vim"));
+        assert_eq!(edges[1].via_virtual().unwrap().name, "editor");
+    }
+
+    #[test]
+    fn test_find_reasons_follows_auto_packages_to_manual_root() {
+        let graph = graph(vec![
+            InstalledPackageData::for_test("app", "1.0")
+                .depending_on(&["lib"]),
+            InstalledPackageData::for_test("lib", "1.0")
+                .depending_on(&["base"])
+                .installed_as_dependency(),
+            InstalledPackageData::for_test("base", "1.0")
+                .installed_as_dependency(),
+        ]);
+
+        let paths = find_reasons(&graph, "base");
+        assert_eq!(paths.len(), 1);
+        let chain = paths[0]
+            .iter()
+            .map(|edge| edge.from.info.about.package.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(chain, vec!["app", "lib"]);
+    }
+}
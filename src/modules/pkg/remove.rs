@@ -5,73 +5,260 @@ use super::super::pkg;
 use super::super::project;
 use super::super::project::ExecMode;
 use super::depend;
+use crate::modules::pkg::list::{
+    PackageListData, RECORD_FILEPATH, REMOVED_MARKER_FILEPATH,
+};
 use crate::modules::pkg::lock::LockManager;
-use crate::modules::system::path;
+use crate::modules::pkg::manifest::FileManifest;
+use crate::modules::pkg::transaction::Transaction;
 use crate::utils::error::Error;
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 /// 指定されたパッケージをシステムから削除します。
 ///
 /// アンインストールモード（ローカルまたはグローバル）に基づいて、パッケージの場所を特定し、
 /// アンインストールプロセスを実行し、パッケージリストからエントリを削除します。
+/// 削除するパッケージ同士に依存関係がある場合は、依存元から順に削除します。
 ///
 /// # Arguments
-/// * `target_pkg_name` - 削除するパッケージの名前。
+/// * `target_pkg_names` - 削除するパッケージの名前。
 /// * `uninstall_mode` - アンインストールモード（`ExecMode::Local`または`ExecMode::Global`）。
 ///
 /// # Returns
 /// `Ok(())` パッケージが正常に削除された場合。
-/// `Err(Error)` パッケージが見つからない、またはアンインストール中にエラーが発生した場合。
+/// `Err(Error)` パッケージが見つからない、他のパッケージが依存している、またはアンインストール中にエラーが発生した場合。
 pub fn remove(
-    target_pkg_names: &Vec<String>,
+    target_pkg_names: &[String],
+    uninstall_mode: ExecMode,
+) -> Result<(), Error> {
+    uninstall_packages(target_pkg_names, uninstall_mode, false)
+}
+
+/// 指定されたパッケージを1つのトランザクションで削除またはパージします。
+///
+/// 他のパッケージが依存しているパッケージが含まれている場合は何も削除しません。
+/// 削除するパッケージ同士に依存関係がある場合は、依存元から順に削除します。
+/// 途中で失敗した場合は、トランザクションをロールバックします。
+///
+/// # Arguments
+/// * `target_pkg_names` - 削除するパッケージの名前。
+/// * `uninstall_mode` - アンインストールモード。
+/// * `purge` - パッケージディレクトリと設定ファイルも削除する場合は`true`。
+///
+/// # Returns
+/// `Ok(())` すべてのパッケージが正常に削除された場合。
+/// `Err(Error)` パッケージが見つからない、他のパッケージが依存している、またはアンインストール中にエラーが発生した場合。
+pub(super) fn uninstall_packages(
+    target_pkg_names: &[String],
     uninstall_mode: ExecMode,
+    purge: bool,
 ) -> Result<(), Error> {
     let lock_manager =
         LockManager::new(matches!(uninstall_mode, ExecMode::Global));
-    lock_manager.acquire_lock()?;
+    let _lock = lock_manager.acquire_lock()?;
 
-    for target_pkg_name in target_pkg_names {
-        let final_pkg_destination_path = match uninstall_mode {
-            ExecMode::Local => {
-                path::local::packages_dirpath().join(target_pkg_name)
+    let installed_packages =
+        pkg::list::database(uninstall_mode).load_all()?;
+    uninstall_loaded_packages(
+        target_pkg_names,
+        uninstall_mode,
+        purge,
+        installed_packages,
+    )
+}
+
+/// 読み込み済みのインストール済みパッケージを使って、指定されたパッケージを削除またはパージします。
+///
+/// 呼び出し元は排他ロックを取得したうえで、ロック中に`installed_packages`を読み込んでいる必要があります。
+///
+/// # Arguments
+/// * `target_pkg_names` - 削除するパッケージの名前。
+/// * `uninstall_mode` - アンインストールモード。
+/// * `purge` - パッケージディレクトリと設定ファイルも削除する場合は`true`。
+/// * `installed_packages` - ロック中に読み込んだインストール済みパッケージ。
+///
+/// # Returns
+/// `Ok(())` すべてのパッケージが正常に削除された場合。
+/// `Err(Error)` パッケージが見つからない、他のパッケージが依存している、またはアンインストール中にエラーが発生した場合。
+pub(super) fn uninstall_loaded_packages(
+    target_pkg_names: &[String],
+    uninstall_mode: ExecMode,
+    purge: bool,
+    mut installed_packages: PackageListData,
+) -> Result<(), Error> {
+    let depend_graph = depend::DependencyGraph::from_installed_packages(
+        &installed_packages,
+    );
+    let target_names =
+        target_pkg_names.iter().map(String::as_str).collect::<Vec<_>>();
+    if let Err(e) = depend_graph.is_packages_removable(&target_names) {
+        log::error!("You cannot uninstall these packages.\n{}", e);
+        return Err(e.into());
+    }
+    let ordered_pkg_names =
+        depend_graph.get_removal_order(target_pkg_names);
+
+    let mut transaction =
+        Transaction::begin(uninstall_mode, &installed_packages)?;
+    let result =
+        ordered_pkg_names.iter().try_for_each(|target_pkg_name| {
+            remove_package(
+                target_pkg_name,
+                uninstall_mode,
+                purge,
+                &mut installed_packages,
+                &mut transaction,
+            )
+        });
+    match result {
+        Ok(()) => transaction.commit()?,
+        Err(e) => {
+            if purge {
+                log::error!("Purge failed: {}", e);
+            } else {
+                log::error!("Removal failed: {}", e);
             }
-            ExecMode::Global => {
-                let list_file_path = path::global::packageslist_filepath();
-                list_file_path
-                    .parent()
-                    .ok_or_else(|| {
-                        std::io::Error::new(
-                            std::io::ErrorKind::InvalidInput,
-                            format!(
-                                "Global packages list file path '{}' does not have a parent directory.",
-                                list_file_path.display()
-                            ),
-                        )
-                    })?
-                    .join(target_pkg_name)
+            if let Err(rollback_error) = transaction.rollback() {
+                log::error!("Rollback incomplete: {}", rollback_error);
             }
-        };
-
-        if !final_pkg_destination_path.exists() {
-            log::error!(
-                "Package not found at: {}",
-                final_pkg_destination_path.display()
-            );
-            return Err(std::io::ErrorKind::NotFound.into());
+            return Err(e);
         }
+    }
 
-        uninstall_package(
-            target_pkg_name,
-            uninstall_mode,
-            &final_pkg_destination_path,
-        )?;
+    Ok(())
+}
 
-        remove_package_from_list(target_pkg_name, uninstall_mode)?;
+/// 指定されたパッケージと、それらに推移的に依存しているパッケージをすべて削除します。
+///
+/// 一緒に削除されるパッケージを表示してから、依存元から順に1つのトランザクションで削除します。
+///
+/// # Arguments
+/// * `target_pkg_names` - 削除するパッケージの名前。
+/// * `uninstall_mode` - アンインストールモード。
+///
+/// # Returns
+/// `Ok(())` すべてのパッケージが正常に削除された場合。
+/// `Err(Error)` パッケージが見つからない、またはアンインストール中にエラーが発生した場合。
+pub fn remove_cascade(
+    target_pkg_names: &[String],
+    uninstall_mode: ExecMode,
+) -> Result<(), Error> {
+    let depend_graph = installed_graph(uninstall_mode)?;
+    let target_names =
+        target_pkg_names.iter().map(String::as_str).collect::<Vec<_>>();
+    let dependent_names = depend_graph
+        .get_reverse_dependencies(&target_names)
+        .into_iter()
+        .map(|pkg| pkg.about.package.name)
+        .collect::<Vec<_>>();
+    if !dependent_names.is_empty() {
+        log::warn!(
+            "The following dependent packages will also be removed: {}",
+            dependent_names.join(", ")
+        );
     }
 
-    lock_manager.release_lock()?;
+    let all_pkg_names = dependent_names
+        .into_iter()
+        .chain(target_pkg_names.iter().cloned())
+        .collect::<Vec<_>>();
+    remove(&all_pkg_names, uninstall_mode)
+}
 
+/// 指定されたモードのインストール済みパッケージから依存関係グラフを作成します。
+fn installed_graph(
+    uninstall_mode: ExecMode,
+) -> Result<depend::DependencyGraph, std::io::Error> {
+    let installed_packages =
+        pkg::list::database(uninstall_mode).load_all()?;
+    Ok(depend::DependencyGraph::from_installed_packages(
+        &installed_packages,
+    ))
+}
+
+/// 1つのパッケージをトランザクション内で削除またはパージします。
+///
+/// 削除する場合は、パージできるようにパッケージディレクトリを残して削除済みであることを記録します。
+/// パージする場合は、パッケージディレクトリも削除します。
+///
+/// # Arguments
+/// * `target_pkg_name` - 削除するパッケージの名前。
+/// * `uninstall_mode` - アンインストールモード。
+/// * `purge` - パッケージディレクトリと設定ファイルも削除する場合は`true`。
+/// * `installed_packages` - インストール済みパッケージ。削除したパッケージは取り除かれます。
+/// * `transaction` - 変更を記録するトランザクション。
+///
+/// # Returns
+/// `Ok(())` パッケージが正常に削除された場合。
+/// `Err(Error)` パッケージが見つからない、またはアンインストール中にエラーが発生した場合。
+fn remove_package(
+    target_pkg_name: &str,
+    uninstall_mode: ExecMode,
+    purge: bool,
+    installed_packages: &mut PackageListData,
+    transaction: &mut Transaction,
+) -> Result<(), Error> {
+    let final_pkg_destination_path =
+        pkg::list::packages_dirpath(uninstall_mode).join(target_pkg_name);
+
+    if !final_pkg_destination_path.exists() {
+        log::error!(
+            "Package not found at: {}",
+            final_pkg_destination_path.display()
+        );
+        return Err(std::io::ErrorKind::NotFound.into());
+    }
+
+    // 削除済みのパッケージはパージのみ可能
+    let is_installed = installed_packages
+        .installed_packages
+        .iter()
+        .any(|pkg| pkg.info.about.package.name == target_pkg_name)
+        && !final_pkg_destination_path
+            .join(REMOVED_MARKER_FILEPATH)
+            .exists();
+    if !purge && !is_installed {
+        return Err(Error::from(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Package '{}' is not installed", target_pkg_name),
+        )));
+    }
+
+    transaction
+        .start_remove(target_pkg_name, &final_pkg_destination_path)?;
+    uninstall_package(
+        target_pkg_name,
+        uninstall_mode,
+        purge,
+        &final_pkg_destination_path,
+        installed_packages,
+        transaction,
+    )?;
+    transaction.finish_step()?;
+
+    transaction
+        .preserve(&final_pkg_destination_path.join(RECORD_FILEPATH))?;
+    remove_package_from_list(target_pkg_name, uninstall_mode)?;
+    installed_packages
+        .installed_packages
+        .retain(|pkg| pkg.info.about.package.name != target_pkg_name);
+
+    if purge {
+        transaction.backup(&final_pkg_destination_path)?;
+        log::debug!(
+            "Removed package directory {}",
+            final_pkg_destination_path.display()
+        );
+        return Ok(());
+    }
+
+    // パージできるようにディレクトリは残し、削除済みであることを記録する
+    let marker_path =
+        final_pkg_destination_path.join(REMOVED_MARKER_FILEPATH);
+    transaction.preserve(&marker_path)?;
+    fs::write(&marker_path, "")?;
     Ok(())
 }
 
@@ -83,7 +270,10 @@ pub fn remove(
 /// # Arguments
 /// * `pkg_name` - アンインストールするパッケージの名前。
 /// * `uninstall_mode` - アンインストールモード。
+/// * `purge` - パージスクリプトを実行し、設定ファイルも削除する場合は`true`。
 /// * `final_pkg_destination_path` - パッケージがインストールされているパス。
+/// * `installed_packages` - インストール済みパッケージ。
+/// * `transaction` - 削除したファイルのバックアップを記録するトランザクション。
 ///
 /// # Returns
 /// `Ok(())` アンインストールプロセスが正常に完了した場合。
@@ -91,7 +281,10 @@ pub fn remove(
 fn uninstall_package(
     pkg_name: &str,
     uninstall_mode: ExecMode,
+    purge: bool,
     final_pkg_destination_path: &PathBuf,
+    installed_packages: &PackageListData,
+    transaction: &mut Transaction,
 ) -> Result<(), std::io::Error> {
     let original_cwd = env::current_dir()?;
 
@@ -112,10 +305,20 @@ fn uninstall_package(
         final_pkg_destination_path.display()
     );
 
-    let result = uninstall_process(pkg_name, uninstall_mode);
+    let result = uninstall_process(
+        pkg_name,
+        uninstall_mode,
+        purge,
+        final_pkg_destination_path,
+        installed_packages,
+        transaction,
+    );
 
     env::set_current_dir(&original_cwd)?;
-    log::debug!("Restored current directory to {}", original_cwd.display());
+    log::debug!(
+        "Restored current directory to {}",
+        original_cwd.display()
+    );
 
     result
 }
@@ -133,27 +336,30 @@ fn remove_package_from_list(
     pkg_name: &str,
     uninstall_mode: ExecMode,
 ) -> Result<(), std::io::Error> {
-    match uninstall_mode {
-        ExecMode::Local => {
-            pkg::list::del_pkg_local(pkg_name)?;
-            log::debug!("Removed package '{}' from local list.", pkg_name);
-        }
-        ExecMode::Global => {
-            pkg::list::del_pkg_global(pkg_name)?;
-            log::debug!("Removed package '{}' from global list.", pkg_name);
-        }
-    }
+    pkg::list::database(uninstall_mode).delete(pkg_name)?;
+    log::debug!(
+        "Removed package '{}' from {} database.",
+        pkg_name,
+        uninstall_mode
+    );
     Ok(())
 }
 
 /// パッケージのアンインストールプロセスを実行します。
 ///
 /// 依存関係グラフをチェックし、パッケージが削除可能であれば、
-/// プロジェクトの削除スクリプトを実行します。
+/// プロジェクトの削除スクリプト（パージする場合はパージスクリプト）を実行します。
+/// その後、インストール時に記録されたファイルのうちスクリプトが残したものを削除します。
+/// 削除する場合、記録時から変更されたファイルは設定ファイルとみなし、残します。
+/// 削除したファイルはトランザクションのバックアップへ移動し、失敗時に元に戻せるようにします。
 ///
 /// # Arguments
 /// * `pkg_name` - アンインストールするパッケージの名前。
 /// * `uninstall_mode` - アンインストールモード。
+/// * `purge` - パージスクリプトを実行し、設定ファイルも削除する場合は`true`。
+/// * `pkg_dir` - パッケージがインストールされているパス。
+/// * `installed_packages` - インストール済みパッケージ。
+/// * `transaction` - 削除したファイルのバックアップを記録するトランザクション。
 ///
 /// # Returns
 /// `Ok(())` アンインストールプロセスが正常に完了した場合。
@@ -161,24 +367,31 @@ fn remove_package_from_list(
 fn uninstall_process(
     pkg_name: &str,
     uninstall_mode: ExecMode,
+    purge: bool,
+    pkg_dir: &Path,
+    installed_packages: &PackageListData,
+    transaction: &mut Transaction,
 ) -> Result<(), std::io::Error> {
-    let installed_packages = match uninstall_mode {
-        ExecMode::Local => pkg::list::get_local()?,
-        ExecMode::Global => pkg::list::get_global()?,
-    };
-
     let depend_graph = depend::DependencyGraph::from_installed_packages(
-        &installed_packages,
+        installed_packages,
     );
 
     match depend_graph.is_packages_removable(&[pkg_name]) {
         Ok(()) => {
-            let opts = project::remove::RemoveOptions {
-                remove_mode: uninstall_mode,
-                remove_shell: project::ExecShell::default(),
-            };
-            project::remove::remove(opts)
-                .map_err(std::io::Error::other)?;
+            if purge {
+                run_purge_script(pkg_name, uninstall_mode, pkg_dir)?;
+            } else {
+                run_remove_script(pkg_name, uninstall_mode, pkg_dir)?;
+            }
+            let kept_files = FileManifest::load(pkg_dir)?
+                .remove_files(!purge, |path| transaction.backup(path))?;
+            if !kept_files.is_empty() {
+                log::info!(
+                    "Kept {} modified file(s) of '{}'. Use purge to remove them.",
+                    kept_files.len(),
+                    pkg_name
+                );
+            }
             Ok(())
         }
         Err(e) => {
@@ -187,3 +400,43 @@ fn uninstall_process(
         }
     }
 }
+
+/// パッケージの削除スクリプトがあれば実行します。
+fn run_remove_script(
+    pkg_name: &str,
+    uninstall_mode: ExecMode,
+    pkg_dir: &Path,
+) -> Result<(), std::io::Error> {
+    if !pkg_dir.join("ipak/scripts/remove.sh").is_file() {
+        log::info!(
+            "Package '{}' has no remove script. Removing recorded files only.",
+            pkg_name
+        );
+        return Ok(());
+    }
+    let opts = project::remove::RemoveOptions {
+        remove_mode: uninstall_mode,
+        remove_shell: project::ExecShell::default(),
+    };
+    project::remove::remove(opts).map_err(std::io::Error::other)
+}
+
+/// パッケージのパージスクリプトがあれば実行します。
+fn run_purge_script(
+    pkg_name: &str,
+    uninstall_mode: ExecMode,
+    pkg_dir: &Path,
+) -> Result<(), std::io::Error> {
+    if !pkg_dir.join("ipak/scripts/purge.sh").is_file() {
+        log::info!(
+            "Package '{}' has no purge script. Removing recorded files only.",
+            pkg_name
+        );
+        return Ok(());
+    }
+    let opts = project::purge::PurgeOptions {
+        purge_mode: uninstall_mode,
+        purge_shell: project::ExecShell::default(),
+    };
+    project::purge::purge(opts).map_err(std::io::Error::other)
+}
@@ -0,0 +1,459 @@
+//! このモジュールは、パッケージアーカイブの署名と署名の検証を提供します。
+//!
+//! パッケージ化の際に、アーカイブに含まれるすべてのファイルのSHA-256ハッシュを並べたマニフェストを作成し、
+//! ed25519の秘密鍵で署名して`ipak/signature.yaml`としてアーカイブに埋め込みます。
+//! インストールの際には、展開したファイルがマニフェストと一致すること、署名が正しいこと、
+//! そして署名した鍵が信頼済みの鍵のディレクトリにあることを確認します。
+//!
+//! 鍵は16進数で書かれたテキストファイルとして保存されます。
+//! 秘密鍵のファイルには32バイトのシードが、公開鍵のファイル（`.pub`）には32バイトの公開鍵が含まれます。
+
+use super::manifest::hash_file;
+use crate::modules::project::ExecMode;
+use crate::modules::system::path;
+use ed25519_dalek::{
+    Signature, Signer, SigningKey, Verifier, VerifyingKey,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// パッケージ内で署名を保存する相対パスです。
+pub const SIGNATURE_FILEPATH: &str = "ipak/signature.yaml";
+/// 公開鍵ファイルの拡張子です。
+pub const PUBLIC_KEY_EXTENSION: &str = "pub";
+
+/// パッケージに埋め込まれる署名です。
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PackageSignature {
+    /// 署名に使用した鍵の公開鍵（16進数）。
+    pub public_key: String,
+    /// 署名されたファイルとそのハッシュ（パス順）。
+    pub files: Vec<SignedFile>,
+    /// マニフェストに対するed25519署名（16進数）。
+    pub signature: String,
+}
+
+/// 署名されたファイルを表す構造体です。
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SignedFile {
+    /// パッケージのルートからの相対パス（`/`区切り）。
+    pub path: String,
+    /// ファイル内容のSHA-256ハッシュ（16進数）。
+    pub sha256: String,
+}
+
+/// 署名の検証結果を表す列挙型です。
+#[derive(Debug, PartialEq)]
+pub enum Verification {
+    /// パッケージは署名されていない。
+    Unsigned,
+    /// 署名は正しいが、署名した鍵が信頼されていない。
+    Untrusted {
+        /// 署名した鍵の公開鍵（16進数）。
+        public_key: String,
+    },
+    /// 署名が正しくない、またはファイルがマニフェストと一致しない。
+    Invalid {
+        /// 検証に失敗した理由。
+        reason: String,
+    },
+    /// 信頼済みの鍵による正しい署名がある。
+    Trusted {
+        /// 署名した鍵の名前（公開鍵ファイルの名前）。
+        key_name: String,
+    },
+}
+
+/// ディレクトリ内のファイルに署名し、署名を`ipak/signature.yaml`に書き込みます。
+///
+/// # Arguments
+/// * `dir` - 署名するパッケージのルートディレクトリ。
+/// * `signing_key` - 署名に使用する秘密鍵。
+///
+/// # Returns
+/// `Ok(())` 署名に成功した場合。
+/// `Err(io::Error)` ファイルの読み込み、または署名の書き込みに失敗した場合。
+pub fn sign_dir(
+    dir: &Path,
+    signing_key: &SigningKey,
+) -> Result<(), io::Error> {
+    let files = hash_files(dir)?;
+    let signature = signing_key.sign(&manifest_bytes(&files));
+    let package_signature = PackageSignature {
+        public_key: to_hex(signing_key.verifying_key().as_bytes()),
+        files,
+        signature: to_hex(&signature.to_bytes()),
+    };
+    let yaml_string =
+        serde_yaml::to_string(&package_signature).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed to serialize the signature: {}", e),
+            )
+        })?;
+    let signature_path = dir.join(SIGNATURE_FILEPATH);
+    if let Some(parent_dir) = signature_path.parent() {
+        fs::create_dir_all(parent_dir)?;
+    }
+    fs::write(signature_path, yaml_string)
+}
+
+/// 展開されたパッケージの署名を検証します。
+///
+/// # Arguments
+/// * `dir` - 展開されたパッケージのルートディレクトリ。
+/// * `trusted_keys_dirs` - 信頼済みの公開鍵を探すディレクトリ。
+///
+/// # Returns
+/// `Ok(Verification)` 検証結果。
+/// `Err(io::Error)` ファイルの読み込みに失敗した場合。
+pub fn verify_dir(
+    dir: &Path,
+    trusted_keys_dirs: &[PathBuf],
+) -> Result<Verification, io::Error> {
+    let signature_path = dir.join(SIGNATURE_FILEPATH);
+    let signature_str = match fs::read_to_string(&signature_path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Verification::Unsigned);
+        }
+        Err(e) => return Err(e),
+    };
+    let invalid = |reason: String| Ok(Verification::Invalid { reason });
+    let package_signature: PackageSignature =
+        match serde_yaml::from_str(&signature_str) {
+            Ok(package_signature) => package_signature,
+            Err(e) => {
+                return invalid(format!(
+                    "failed to parse {}: {}",
+                    SIGNATURE_FILEPATH, e
+                ));
+            }
+        };
+    let Some(public_key) =
+        parse_verifying_key(&package_signature.public_key)
+    else {
+        return invalid("malformed public key".to_string());
+    };
+    let Some(signature) = from_hex(&package_signature.signature)
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+    else {
+        return invalid("malformed signature".to_string());
+    };
+    if public_key
+        .verify(&manifest_bytes(&package_signature.files), &signature)
+        .is_err()
+    {
+        return invalid(
+            "the signature does not match the file manifest".to_string(),
+        );
+    }
+
+    let actual_files = hash_files(dir)?;
+    for expected in &package_signature.files {
+        match actual_files.iter().find(|file| file.path == expected.path) {
+            None => {
+                return invalid(format!("{} is missing", expected.path));
+            }
+            Some(actual) if actual.sha256 != expected.sha256 => {
+                return invalid(format!("{} was modified", expected.path));
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some(extra) = actual_files
+        .iter()
+        .find(|file| !package_signature.files.contains(file))
+    {
+        return invalid(format!("{} is not signed", extra.path));
+    }
+
+    let key_name = trusted_keys(trusted_keys_dirs)
+        .into_iter()
+        .find(|(_, trusted_key)| *trusted_key == public_key)
+        .map(|(key_name, _)| key_name);
+    Ok(match key_name {
+        Some(key_name) => Verification::Trusted { key_name },
+        None => Verification::Untrusted {
+            public_key: package_signature.public_key,
+        },
+    })
+}
+
+/// 新しい鍵のペアを作成し、秘密鍵と公開鍵をファイルに書き込みます。
+///
+/// 秘密鍵は`path`に、公開鍵は`path`に`.pub`を付けたファイルに書き込まれます。
+/// 既存のファイルは上書きされません。
+///
+/// # Arguments
+/// * `path` - 秘密鍵を書き込むファイルのパス。
+///
+/// # Returns
+/// `Ok(PathBuf)` 公開鍵を書き込んだファイルのパス。
+/// `Err(io::Error)` 乱数の生成、またはファイルの書き込みに失敗した場合。
+pub fn generate_key(path: &Path) -> Result<PathBuf, io::Error> {
+    let mut seed = [0u8; 32];
+    getrandom::fill(&mut seed)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    let signing_key = SigningKey::from_bytes(&seed);
+    let public_key_path = public_key_filepath(path);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    io::Write::write_all(
+        &mut options.open(path)?,
+        format!("{}\n", to_hex(&seed)).as_bytes(),
+    )?;
+    fs::write(
+        &public_key_path,
+        format!("{}\n", to_hex(signing_key.verifying_key().as_bytes())),
+    )?;
+    Ok(public_key_path)
+}
+
+/// 秘密鍵のファイルに対応する公開鍵のファイルのパスを返します。
+pub fn public_key_filepath(secret_key_path: &Path) -> PathBuf {
+    let mut file_name = secret_key_path.as_os_str().to_os_string();
+    file_name.push(format!(".{}", PUBLIC_KEY_EXTENSION));
+    PathBuf::from(file_name)
+}
+
+/// 秘密鍵をファイルから読み込みます。
+///
+/// # Arguments
+/// * `path` - 秘密鍵のファイルのパス。
+///
+/// # Returns
+/// `Ok(SigningKey)` 読み込まれた秘密鍵。
+/// `Err(io::Error)` ファイルの読み込みに失敗した場合、または鍵の形式が正しくない場合。
+pub fn read_signing_key(path: &Path) -> Result<SigningKey, io::Error> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to read key '{}': {}", path.display(), e),
+        )
+    })?;
+    from_hex(content.trim())
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .map(|seed| SigningKey::from_bytes(&seed))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("'{}' is not a valid secret key", path.display()),
+            )
+        })
+}
+
+/// 公開鍵をファイルから読み込みます。
+///
+/// # Arguments
+/// * `path` - 公開鍵のファイルのパス。
+///
+/// # Returns
+/// `Ok(VerifyingKey)` 読み込まれた公開鍵。
+/// `Err(io::Error)` ファイルの読み込みに失敗した場合、または鍵の形式が正しくない場合。
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey, io::Error> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to read key '{}': {}", path.display(), e),
+        )
+    })?;
+    parse_verifying_key(content.trim()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("'{}' is not a valid public key", path.display()),
+        )
+    })
+}
+
+/// 指定されたモードで信頼する公開鍵のディレクトリを返します。
+///
+/// ローカルモードではローカルとグローバルの両方の鍵を信頼し、
+/// グローバルモードではグローバルの鍵のみを信頼します。
+pub fn trusted_keys_dirs(mode: ExecMode) -> Vec<PathBuf> {
+    match mode {
+        ExecMode::Local => vec![
+            path::local::trusted_keys_dirpath(),
+            path::global::trusted_keys_dirpath(),
+        ],
+        ExecMode::Global => vec![path::global::trusted_keys_dirpath()],
+    }
+}
+
+/// 信頼済みの鍵のディレクトリにある公開鍵を、その名前と共に返します。
+///
+/// 読み込めない鍵は警告を表示して無視します。
+///
+/// # Arguments
+/// * `dirs` - 公開鍵を探すディレクトリ。存在しないディレクトリは無視されます。
+pub fn trusted_keys(dirs: &[PathBuf]) -> Vec<(String, VerifyingKey)> {
+    let mut keys = Vec::new();
+    for dir in dirs {
+        let Ok(read_dir) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut paths = read_dir
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == PUBLIC_KEY_EXTENSION)
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            match read_verifying_key(&path) {
+                Ok(key) => keys.push((
+                    path.file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned(),
+                    key,
+                )),
+                Err(e) => log::warn!("Ignoring trusted key: {}", e),
+            }
+        }
+    }
+    keys
+}
+
+/// 公開鍵を16進数の文字列で返します。
+pub fn key_to_hex(key: &VerifyingKey) -> String {
+    to_hex(key.as_bytes())
+}
+
+/// ディレクトリ内のすべてのファイルのハッシュを、パス順に返します。署名ファイル自身は除外されます。
+fn hash_files(dir: &Path) -> Result<Vec<SignedFile>, io::Error> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir).follow_links(false).sort_by_file_name()
+    {
+        let entry = entry.map_err(io::Error::from)?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let relative_path = entry
+            .path()
+            .strip_prefix(dir)
+            .map_err(io::Error::other)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if relative_path == SIGNATURE_FILEPATH {
+            continue;
+        }
+        files.push(SignedFile {
+            sha256: hash_file(entry.path())?,
+            path: relative_path,
+        });
+    }
+    Ok(files)
+}
+
+/// 署名の対象となるマニフェストのバイト列を作成します。
+///
+/// `sha256sum`と同じ`<ハッシュ>  <パス>`形式の行を並べたものです。
+fn manifest_bytes(files: &[SignedFile]) -> Vec<u8> {
+    let mut manifest = String::new();
+    for file in files {
+        let _ = writeln!(manifest, "{}  {}", file.sha256, file.path);
+    }
+    manifest.into_bytes()
+}
+
+/// 16進数の文字列から公開鍵を作成します。
+fn parse_verifying_key(hex: &str) -> Option<VerifyingKey> {
+    let bytes = <[u8; 32]>::try_from(from_hex(hex)?).ok()?;
+    VerifyingKey::from_bytes(&bytes).ok()
+}
+
+/// バイト列を16進数の文字列に変換します。
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// 16進数の文字列をバイト列に変換します。
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("ipak/scripts")).unwrap();
+        fs::write(dir.path().join("ipak/project.yaml"), b"metadata")
+            .unwrap();
+        fs::write(dir.path().join("ipak/scripts/install.sh"), b"echo")
+            .unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_signed_package_is_trusted_only_with_its_key() {
+        let keys_dir = tempfile::tempdir().unwrap();
+        let secret_key_path = keys_dir.path().join("release");
+        let public_key_path = generate_key(&secret_key_path).unwrap();
+        assert!(generate_key(&secret_key_path).is_err());
+        let signing_key = read_signing_key(&secret_key_path).unwrap();
+
+        let dir = package_dir();
+        assert_eq!(
+            verify_dir(dir.path(), &[]).unwrap(),
+            Verification::Unsigned
+        );
+        sign_dir(dir.path(), &signing_key).unwrap();
+        assert_eq!(
+            verify_dir(dir.path(), &[]).unwrap(),
+            Verification::Untrusted {
+                public_key: key_to_hex(
+                    &read_verifying_key(&public_key_path).unwrap()
+                )
+            }
+        );
+        assert_eq!(
+            verify_dir(dir.path(), &[keys_dir.path().to_path_buf()])
+                .unwrap(),
+            Verification::Trusted { key_name: "release".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_tampered_package_is_invalid() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let dir = package_dir();
+        sign_dir(dir.path(), &signing_key).unwrap();
+
+        fs::write(dir.path().join("ipak/scripts/install.sh"), b"rm -rf")
+            .unwrap();
+        assert!(matches!(
+            verify_dir(dir.path(), &[]).unwrap(),
+            Verification::Invalid { reason } if reason.contains("install.sh was modified")
+        ));
+
+        fs::write(dir.path().join("ipak/scripts/install.sh"), b"echo")
+            .unwrap();
+        fs::write(dir.path().join("payload"), b"extra").unwrap();
+        assert!(matches!(
+            verify_dir(dir.path(), &[]).unwrap(),
+            Verification::Invalid { reason } if reason.contains("payload is not signed")
+        ));
+    }
+}
@@ -0,0 +1,664 @@
+//! このモジュールは、パッケージ操作のトランザクションを提供します。
+//!
+//! インストール・削除・パージの一連の処理で行った変更を記録し、
+//! 途中で失敗した場合にはすべての変更を取り消して元の状態に戻します。
+//! 確定したトランザクションで行われたインストールと削除は、履歴ファイルに記録されます。
+
+use super::list::{self, FileDatabase, PackageListData};
+use super::lock::LockManager;
+use super::manifest::FileManifest;
+use crate::modules::project::{self, ExecMode, ExecShell};
+use crate::modules::system::path;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// トランザクション内で行われた個々の変更です。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+enum Step {
+    /// 既存のパスをバックアップディレクトリへ移動、またはコピーしました。
+    BackedUp { original: PathBuf, backup: PathBuf },
+    /// 新しいディレクトリを作成しました。
+    Created { path: PathBuf },
+    /// パッケージのインストールスクリプトを実行しました。
+    Installed { name: String, pkg_dir: PathBuf },
+    /// パッケージの削除（またはパージ）スクリプトを実行しました。
+    Removed { name: String, pkg_dir: PathBuf },
+}
+
+/// タスクファイルに書き込まれるジャーナルの1項目です。
+///
+/// 各変更は実行前に`Step`として記録され、完了後に`Done`が記録されます。
+/// ロールバックで変更を取り消すたびに`Undone`が記録されるため、
+/// ロールバックが中断された場合も続きから再開できます。
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type")]
+enum JournalEntry {
+    /// トランザクションを開始しました。
+    Begin { backup_dir: PathBuf },
+    /// これから変更を行います。
+    Step { step: Step },
+    /// 直前の変更が完了しました。
+    Done,
+    /// 最後に記録された、まだ取り消されていない変更を取り消しました。
+    Undone,
+    /// トランザクションを確定しました。
+    Commit,
+}
+
+impl JournalEntry {
+    /// タスクファイルに追記する形式に変換します。
+    fn to_task(&self) -> Result<String, io::Error> {
+        let yaml =
+            serde_yaml::to_string(self).map_err(io::Error::other)?;
+        Ok(format!("---\n{}", yaml.trim_end()))
+    }
+}
+
+/// ジャーナルから復元したトランザクションの状態です。
+#[derive(Debug, Default, PartialEq)]
+struct JournalState {
+    backup_dir: Option<PathBuf>,
+    steps: Vec<Step>,
+    last_done: bool,
+    committed: bool,
+}
+
+impl JournalState {
+    /// タスクファイルの内容を読み込みます。
+    ///
+    /// クラッシュによって途中までしか書き込まれなかった末尾の項目は無視されます。
+    fn parse(tasks: &str) -> Self {
+        let mut state = Self::default();
+        for document in serde_yaml::Deserializer::from_str(tasks) {
+            let entry = match JournalEntry::deserialize(document) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    log::warn!("Ignoring unreadable journal entry: {}", e);
+                    break;
+                }
+            };
+            match entry {
+                JournalEntry::Begin { backup_dir } => {
+                    state.backup_dir = Some(backup_dir)
+                }
+                JournalEntry::Step { step } => {
+                    state.steps.push(step);
+                    state.last_done = false;
+                }
+                JournalEntry::Done => state.last_done = true,
+                JournalEntry::Undone => {
+                    state.steps.pop();
+                    state.last_done = true;
+                }
+                JournalEntry::Commit => state.committed = true,
+            }
+        }
+        state
+    }
+}
+
+/// パッケージ操作をまとめて適用、または取り消すためのトランザクションです。
+///
+/// 変更は実行前に`Step`としてタスクファイル（ジャーナル）に記録され、`rollback`では逆順に取り消されます。
+/// データベースはインデックスと変更するパッケージのレコードだけを変更前に保存しておき、ロールバック時に書き戻します。
+/// プロセスが途中で終了した場合は、次に排他ロックを取得した時点で`recover`によって取り消されます。
+pub struct Transaction {
+    mode: ExecMode,
+    journal: LockManager,
+    backup_dir: PathBuf,
+    steps: Vec<Step>,
+    last_done: bool,
+}
+
+impl Transaction {
+    /// 新しいトランザクションを開始します。
+    ///
+    /// パッケージディレクトリ内にバックアップ用のディレクトリを作成し、ジャーナルに開始を記録してから、
+    /// データベースのインデックスを保存します。パッケージのレコードは、変更する前に個別に保存してください。
+    /// 開始時点のパッケージリストは`ipak system db restore`で戻せるスナップショットとしても保存されます。
+    ///
+    /// # Arguments
+    /// * `mode` - 操作対象のモード（ローカルまたはグローバル）。
+    /// * `installed_packages` - 操作の開始時に読み込んだインストール済みパッケージ。
+    ///
+    /// # Returns
+    /// `Ok(Transaction)` トランザクションの開始に成功した場合。
+    /// `Err(io::Error)` バックアップディレクトリの作成、スナップショットの保存、またはジャーナルへの書き込みに失敗した場合。
+    pub fn begin(
+        mode: ExecMode,
+        installed_packages: &PackageListData,
+    ) -> Result<Self, io::Error> {
+        let packages_dir = list::packages_dirpath(mode);
+        fs::create_dir_all(&packages_dir)?;
+        let backup_dir = tempfile::Builder::new()
+            .prefix(".transaction-")
+            .tempdir_in(&packages_dir)?
+            .keep();
+        list::database(mode).snapshot(installed_packages)?;
+        let journal = LockManager::new(matches!(mode, ExecMode::Global));
+        journal.add_task(
+            &JournalEntry::Begin { backup_dir: backup_dir.clone() }
+                .to_task()?,
+        )?;
+        log::debug!(
+            "Began transaction with backup directory {}",
+            backup_dir.display()
+        );
+        let mut transaction = Self {
+            mode,
+            journal,
+            backup_dir,
+            steps: Vec::new(),
+            last_done: true,
+        };
+        transaction
+            .preserve(&FileDatabase::new(packages_dir).index_filepath())?;
+        Ok(transaction)
+    }
+
+    /// これから行う変更をジャーナルに記録します。
+    fn start_step(&mut self, step: Step) -> Result<(), io::Error> {
+        self.journal.add_task(
+            &JournalEntry::Step { step: step.clone() }.to_task()?,
+        )?;
+        self.steps.push(step);
+        self.last_done = false;
+        Ok(())
+    }
+
+    /// 直前に記録した変更が完了したことをジャーナルに記録します。
+    ///
+    /// # Returns
+    /// `Ok(())` 記録に成功した場合。
+    /// `Err(io::Error)` ジャーナルへの書き込みに失敗した場合。
+    pub fn finish_step(&mut self) -> Result<(), io::Error> {
+        self.journal.add_task(&JournalEntry::Done.to_task()?)?;
+        self.last_done = true;
+        Ok(())
+    }
+
+    /// 既存のパスをバックアップディレクトリへ移動します。
+    ///
+    /// パスが存在しない場合は何もしません。
+    ///
+    /// # Arguments
+    /// * `original` - バックアップするファイルまたはディレクトリのパス。
+    ///
+    /// # Returns
+    /// `Ok(())` 移動に成功した場合、またはパスが存在しない場合。
+    /// `Err(io::Error)` 移動に失敗した場合。
+    pub fn backup(&mut self, original: &Path) -> Result<(), io::Error> {
+        if original.symlink_metadata().is_err() {
+            return Ok(());
+        }
+        let backup = self.backup_dir.join(self.steps.len().to_string());
+        self.start_step(Step::BackedUp {
+            original: original.to_path_buf(),
+            backup: backup.clone(),
+        })?;
+        path::move_path(original, &backup)?;
+        log::debug!(
+            "Backed up {} to {}",
+            original.display(),
+            backup.display()
+        );
+        self.finish_step()
+    }
+
+    /// ファイルの現在の状態を、ロールバック時に戻せるように保存します。
+    ///
+    /// ファイルを書き換えたり削除したりする前に呼び出すと、
+    /// ロールバック時にファイルが保存した時点の内容に戻されます。
+    /// パスが存在しない場合は、ロールバック時にそのパスが削除されるように記録します。
+    ///
+    /// # Arguments
+    /// * `original` - 保存するファイルのパス。
+    ///
+    /// # Returns
+    /// `Ok(())` 保存に成功した場合。
+    /// `Err(io::Error)` コピー、またはジャーナルへの書き込みに失敗した場合。
+    pub fn preserve(&mut self, original: &Path) -> Result<(), io::Error> {
+        if original.symlink_metadata().is_err() {
+            self.start_step(Step::Created {
+                path: original.to_path_buf(),
+            })?;
+            return self.finish_step();
+        }
+        let backup = self.backup_dir.join(self.steps.len().to_string());
+        self.start_step(Step::BackedUp {
+            original: original.to_path_buf(),
+            backup: backup.clone(),
+        })?;
+        path::copy_path(original, &backup)?;
+        log::debug!(
+            "Preserved {} as {}",
+            original.display(),
+            backup.display()
+        );
+        self.finish_step()
+    }
+
+    /// 新しいディレクトリを作成し、トランザクションに記録します。
+    ///
+    /// # Arguments
+    /// * `path` - 作成するディレクトリのパス。
+    ///
+    /// # Returns
+    /// `Ok(())` 作成に成功した場合。
+    /// `Err(io::Error)` 作成に失敗した場合。
+    pub fn create_dir(&mut self, path: &Path) -> Result<(), io::Error> {
+        self.start_step(Step::Created { path: path.to_path_buf() })?;
+        fs::create_dir_all(path)?;
+        self.finish_step()
+    }
+
+    /// パッケージのインストールスクリプトをこれから実行することを記録します。
+    ///
+    /// スクリプトが完了したら`finish_step`を呼び出してください。
+    ///
+    /// # Arguments
+    /// * `name` - パッケージ名。
+    /// * `pkg_dir` - パッケージがインストールされるディレクトリ。
+    ///
+    /// # Returns
+    /// `Ok(())` 記録に成功した場合。
+    /// `Err(io::Error)` ジャーナルへの書き込みに失敗した場合。
+    pub fn start_install(
+        &mut self,
+        name: &str,
+        pkg_dir: &Path,
+    ) -> Result<(), io::Error> {
+        self.start_step(Step::Installed {
+            name: name.to_string(),
+            pkg_dir: pkg_dir.to_path_buf(),
+        })
+    }
+
+    /// パッケージの削除スクリプトをこれから実行することを記録します。
+    ///
+    /// スクリプトが完了したら`finish_step`を呼び出してください。
+    ///
+    /// # Arguments
+    /// * `name` - パッケージ名。
+    /// * `pkg_dir` - パッケージがインストールされているディレクトリ。
+    ///
+    /// # Returns
+    /// `Ok(())` 記録に成功した場合。
+    /// `Err(io::Error)` ジャーナルへの書き込みに失敗した場合。
+    pub fn start_remove(
+        &mut self,
+        name: &str,
+        pkg_dir: &Path,
+    ) -> Result<(), io::Error> {
+        self.start_step(Step::Removed {
+            name: name.to_string(),
+            pkg_dir: pkg_dir.to_path_buf(),
+        })
+    }
+
+    /// トランザクションを確定します。
+    ///
+    /// ジャーナルに確定を記録してから、バックアップディレクトリとジャーナルを削除します。
+    /// インストールまたは削除したパッケージは履歴ファイルに記録されます。
+    ///
+    /// # Returns
+    /// `Ok(())` 確定に成功した場合。
+    /// `Err(io::Error)` ジャーナルへの書き込みに失敗した場合。
+    pub fn commit(self) -> Result<(), io::Error> {
+        self.journal.add_task(&JournalEntry::Commit.to_task()?)?;
+        log::debug!(
+            "Committed transaction with {} step(s)",
+            self.steps.len()
+        );
+        self.append_history();
+        finish(&self.journal, &self.backup_dir)
+    }
+
+    /// トランザクションでインストールまたは削除したパッケージを、履歴ファイルに追記します。
+    ///
+    /// 履歴の書き込みに失敗しても、トランザクションの結果には影響しません。
+    fn append_history(&self) {
+        let actions = self
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Installed { name, .. } => {
+                    Some(format!("install {}", name))
+                }
+                Step::Removed { name, .. } => {
+                    Some(format!("remove {}", name))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if actions.is_empty() {
+            return;
+        }
+        let history_file = history_filepath(self.mode);
+        if let Err(e) = write_history(&history_file, &actions) {
+            log::warn!(
+                "Failed to write history to {}: {}",
+                history_file.display(),
+                e
+            );
+        }
+    }
+
+    /// トランザクション内で行われたすべての変更を逆順に取り消します。
+    ///
+    /// 個々の取り消しに失敗しても残りの取り消しは続行されます。
+    /// 取り消しに失敗した場合はジャーナルとバックアップが残され、
+    /// `ipak system recover`で再試行できます。
+    ///
+    /// # Returns
+    /// `Ok(())` すべての変更が取り消された場合。
+    /// `Err(io::Error)` いずれかの取り消しに失敗した場合（最初のエラー）。
+    pub fn rollback(self) -> Result<(), io::Error> {
+        let result =
+            undo_all(self.mode, &self.journal, self.steps, self.last_done);
+        match result {
+            Ok(()) => finish(&self.journal, &self.backup_dir),
+            Err(e) => {
+                log::error!(
+                    "Run `ipak system recover` to retry the rollback."
+                );
+                Err(e)
+            }
+        }
+    }
+}
+
+/// 前回のプロセスが途中で終了したトランザクションを、ジャーナルを元に回復します。
+///
+/// 確定済みのトランザクションは後片付けのみを行い、
+/// 確定していないトランザクションはすべての変更を取り消します。
+///
+/// # Arguments
+/// * `mode` - 操作対象のモード（ローカルまたはグローバル）。
+/// * `journal` - ジャーナルを管理する`LockManager`。
+/// * `tasks` - タスクファイルの内容。
+///
+/// # Returns
+/// `Ok(())` 回復に成功した場合。
+/// `Err(io::Error)` 変更の取り消しに失敗した場合。
+pub fn recover(
+    mode: ExecMode,
+    journal: &LockManager,
+    tasks: &str,
+) -> Result<(), io::Error> {
+    let state = JournalState::parse(tasks);
+    let Some(backup_dir) = state.backup_dir else {
+        log::warn!("The journal has no transaction to recover");
+        return journal.clear_tasks();
+    };
+    if state.committed {
+        log::info!("Completing a transaction that was already committed");
+        return finish(journal, &backup_dir);
+    }
+
+    undo_all(mode, journal, state.steps, state.last_done)?;
+    finish(journal, &backup_dir)
+}
+
+/// 記録されたすべての変更を逆順に取り消します。
+fn undo_all(
+    mode: ExecMode,
+    journal: &LockManager,
+    mut steps: Vec<Step>,
+    mut last_done: bool,
+) -> Result<(), io::Error> {
+    log::warn!("Rolling back {} step(s)...", steps.len());
+    let mut first_error = None;
+    while let Some(step) = steps.pop() {
+        let result = undo(mode, step, last_done).and_then(|()| {
+            journal.add_task(&JournalEntry::Undone.to_task()?)
+        });
+        if let Err(e) = result {
+            log::error!("Rollback step failed: {}", e);
+            first_error.get_or_insert(e);
+        }
+        last_done = true;
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// 記録された1つの変更を取り消します。
+///
+/// ファイルシステムの変更は実際の状態を確認してから取り消すため、
+/// 完了したかどうか分からない変更にも安全に適用できます。
+/// 完了していないスクリプトの実行は取り消さず、警告のみを表示します。
+///
+/// # Arguments
+/// * `mode` - 操作対象のモード。
+/// * `step` - 取り消す変更。
+/// * `completed` - 変更が完了したことがジャーナルに記録されているかどうか。
+fn undo(
+    mode: ExecMode,
+    step: Step,
+    completed: bool,
+) -> Result<(), io::Error> {
+    match step {
+        Step::BackedUp { original, backup } => {
+            if backup.symlink_metadata().is_err() {
+                return Ok(());
+            }
+            if !completed && original.symlink_metadata().is_ok() {
+                // コピー中に中断された場合は、元のパスが残っている
+                remove_path(&backup)?;
+                return Ok(());
+            }
+            if original.symlink_metadata().is_ok() {
+                remove_path(&original)?;
+            }
+            path::move_path(&backup, &original)?;
+            log::debug!("Restored {}", original.display());
+        }
+        Step::Created { path } => {
+            if path.symlink_metadata().is_ok() {
+                remove_path(&path)?;
+            }
+            log::debug!("Removed {}", path.display());
+        }
+        Step::Installed { name, .. } | Step::Removed { name, .. }
+            if !completed =>
+        {
+            log::warn!(
+                "The script of '{}' was interrupted and may have left partial changes",
+                name
+            );
+        }
+        Step::Installed { name, pkg_dir } => {
+            log::info!("Reverting installation of '{}'", name);
+            if pkg_dir.join("ipak/scripts/remove.sh").is_file() {
+                let opts = project::remove::RemoveOptions {
+                    remove_mode: mode,
+                    remove_shell: ExecShell::default(),
+                };
+                run_in_dir(&pkg_dir, || project::remove::remove(opts))?;
+            }
+            let mut manifest = FileManifest::load(&pkg_dir)?;
+            // 旧バージョンから引き継いだ設定ファイルは、このインストールで作成されたものではないため残す
+            manifest.files.retain(|entry| !entry.config);
+            manifest.remove_files(false, |path| fs::remove_file(path))?;
+        }
+        Step::Removed { name, pkg_dir } => {
+            log::info!("Reverting removal of '{}'", name);
+            if pkg_dir.join("ipak/scripts/install.sh").is_file() {
+                let opts = project::install::InstallOptions {
+                    install_mode: mode,
+                    install_shell: ExecShell::default(),
+                };
+                run_in_dir(&pkg_dir, || project::install::install(opts))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// バックアップディレクトリとジャーナルを削除します。
+fn finish(
+    journal: &LockManager,
+    backup_dir: &Path,
+) -> Result<(), io::Error> {
+    if backup_dir.exists() {
+        fs::remove_dir_all(backup_dir)?;
+    }
+    journal.clear_tasks()
+}
+
+/// 指定されたモードの履歴ファイルへのパスを返します。
+fn history_filepath(mode: ExecMode) -> PathBuf {
+    match mode {
+        ExecMode::Local => path::local::history_filepath(),
+        ExecMode::Global => path::global::history_filepath(),
+    }
+}
+
+/// 現在の時刻を付けて、操作を履歴ファイルに追記します。
+fn write_history(
+    history_file: &Path,
+    actions: &[String],
+) -> Result<(), io::Error> {
+    if let Some(parent) = history_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file =
+        OpenOptions::new().create(true).append(true).open(history_file)?;
+    let timestamp = chrono::Utc::now()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    for action in actions {
+        writeln!(file, "{} {}", timestamp, action)?;
+    }
+    Ok(())
+}
+
+/// ファイルまたはディレクトリを削除します。
+fn remove_path(path: &Path) -> Result<(), io::Error> {
+    if path.is_dir() && !path.is_symlink() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// 作業ディレクトリを一時的に変更してスクリプトを実行します。
+///
+/// # Arguments
+/// * `dir` - スクリプトを実行するディレクトリ。
+/// * `f` - 実行するスクリプト。
+///
+/// # Returns
+/// `Ok(())` スクリプトが正常に完了した場合。
+/// `Err(io::Error)` ディレクトリの変更、またはスクリプトの実行に失敗した場合。
+pub fn run_in_dir(
+    dir: &Path,
+    f: impl FnOnce() -> Result<(), String>,
+) -> Result<(), io::Error> {
+    let original_cwd = env::current_dir()?;
+    env::set_current_dir(dir)?;
+    let result = f().map_err(io::Error::other);
+    env::set_current_dir(&original_cwd)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal(entries: &[JournalEntry]) -> String {
+        entries
+            .iter()
+            .map(|entry| entry.to_task().unwrap() + "\n")
+            .collect()
+    }
+
+    #[test]
+    fn test_journal_state_tracks_steps_and_ignores_truncated_tail() {
+        let created = Step::Created { path: PathBuf::from("/tmp/a") };
+        let installed = Step::Installed {
+            name: "a".to_string(),
+            pkg_dir: PathBuf::from("/tmp/a"),
+        };
+        let mut tasks = journal(&[
+            JournalEntry::Begin { backup_dir: PathBuf::from("/tmp/b") },
+            JournalEntry::Step { step: created.clone() },
+            JournalEntry::Done,
+            JournalEntry::Step { step: installed.clone() },
+        ]);
+        tasks.push_str("---\ntype: Ste");
+
+        let state = JournalState::parse(&tasks);
+        assert_eq!(state.backup_dir, Some(PathBuf::from("/tmp/b")));
+        assert_eq!(state.steps, vec![created, installed]);
+        assert!(!state.last_done);
+        assert!(!state.committed);
+
+        tasks = journal(&[
+            JournalEntry::Begin { backup_dir: PathBuf::from("/tmp/b") },
+            JournalEntry::Step {
+                step: Step::Created { path: PathBuf::from("/tmp/a") },
+            },
+            JournalEntry::Undone,
+        ]);
+        let state = JournalState::parse(&tasks);
+        assert!(state.steps.is_empty());
+        assert!(state.last_done);
+    }
+
+    #[test]
+    fn test_undo_restores_backup_only_if_it_was_moved() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("pkg");
+        let backup = dir.path().join("0");
+        fs::create_dir(&original).unwrap();
+        fs::write(original.join("file"), "new").unwrap();
+
+        // 移動される前に中断された場合は何もしない
+        let step = Step::BackedUp {
+            original: original.clone(),
+            backup: backup.clone(),
+        };
+        undo(ExecMode::Local, step.clone(), false).unwrap();
+        assert_eq!(
+            fs::read_to_string(original.join("file")).unwrap(),
+            "new"
+        );
+
+        fs::create_dir(&backup).unwrap();
+        fs::write(backup.join("file"), "old").unwrap();
+        undo(ExecMode::Local, step, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(original.join("file")).unwrap(),
+            "old"
+        );
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn test_undo_install_keeps_inherited_config_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg_dir = dir.path().join("pkg");
+        fs::create_dir(&pkg_dir).unwrap();
+        let binary = dir.path().join("tool");
+        let config = dir.path().join("tool.conf");
+        fs::write(&binary, "binary").unwrap();
+        fs::write(&config, "edited by user").unwrap();
+        let mut manifest =
+            FileManifest::from_paths(&[binary.clone(), config.clone()])
+                .unwrap();
+        manifest.files[1].config = true;
+        manifest.save(&pkg_dir).unwrap();
+
+        let step = Step::Installed { name: "pkg".to_string(), pkg_dir };
+        undo(ExecMode::Local, step, true).unwrap();
+        assert!(!binary.exists());
+        assert_eq!(fs::read_to_string(&config).unwrap(), "edited by user");
+    }
+}
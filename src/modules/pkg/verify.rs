@@ -0,0 +1,85 @@
+//! このモジュールは、インストール済みパッケージのファイルを記録されたチェックサムと照合する機能を提供します。
+//!
+//! インストール時にマニフェストに記録したSHA-256ハッシュとパーミッションを現在のファイルと比較し、
+//! 変更されたファイル、存在しないファイル、パッケージディレクトリ内の余分なファイルを報告します。
+
+use super::list;
+use super::lock::LockManager;
+use super::manifest::FileManifest;
+use crate::modules::project::ExecMode;
+use crate::utils::error::Error;
+use std::io;
+
+/// インストール済みパッケージのファイルを検証します。
+///
+/// パッケージ名を省略した場合は、すべてのインストール済みパッケージを検証します。
+///
+/// # Arguments
+/// * `package_name` - 検証するパッケージの名前。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 問題のあるファイルが見つからなかった場合。
+/// `Err(Error)` 問題のあるファイルが見つかった場合、パッケージがインストールされていない場合、
+/// またはファイルの読み込みに失敗した場合。
+pub fn verify(
+    package_name: Option<&str>,
+    mode: ExecMode,
+) -> Result<(), Error> {
+    let lock_manager = LockManager::new(matches!(mode, ExecMode::Global));
+    let _lock = lock_manager.acquire_shared_lock()?;
+    let installed_packages = list::database(mode).load_all()?;
+    let packages = installed_packages
+        .installed_packages
+        .iter()
+        .filter(|pkg| {
+            package_name
+                .is_none_or(|name| pkg.info.about.package.name == name)
+        })
+        .collect::<Vec<_>>();
+    if let Some(package_name) = package_name
+        && packages.is_empty()
+    {
+        return Err(Error::from(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Package '{}' is not installed", package_name),
+        )));
+    }
+
+    let packages_dir = list::packages_dirpath(mode);
+    let mut failed_packages = 0;
+    let mut issue_count = 0;
+    for package in packages {
+        let name = &package.info.about.package.name;
+        let pkg_dir = packages_dir.join(name);
+        let manifest = FileManifest::load(&pkg_dir)?;
+        if manifest.package_files.is_empty() {
+            log::warn!(
+                "{}: no checksums were recorded for its package directory; reinstall it to verify them",
+                name
+            );
+        }
+        let issues = manifest.verify(&pkg_dir)?;
+        if issues.is_empty() {
+            log::info!(
+                "{} {}: OK",
+                name,
+                package.info.about.package.version
+            );
+            continue;
+        }
+        for (file_path, status) in &issues {
+            log::warn!("{}: {} {}", name, status, file_path.display());
+        }
+        failed_packages += 1;
+        issue_count += issues.len();
+    }
+
+    if issue_count > 0 {
+        return Err(Error::from(format!(
+            "Found {} problem(s) in {} package(s)",
+            issue_count, failed_packages
+        )));
+    }
+    Ok(())
+}
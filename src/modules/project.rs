@@ -4,6 +4,7 @@
 
 use crate::modules::project::build::BuildMode;
 use crate::modules::project::package::PackageTarget;
+use crate::modules::system::config;
 use crate::utils::error::Error;
 use crate::utils::shell::is_superuser;
 use crate::utils::{
@@ -15,6 +16,7 @@ use build::BuildOptions;
 use install::InstallOptions;
 use purge::PurgeOptions;
 use remove::RemoveOptions;
+use std::path::PathBuf;
 use std::{env, fs, str::FromStr};
 pub mod build;
 pub mod configure;
@@ -110,11 +112,10 @@ impl Default for ExecMode {
 }
 
 /// 実行に使用するシェルを定義する列挙型です。
-#[derive(Default, clap::ValueEnum, Clone, Copy, Debug)]
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
 pub enum ExecShell {
     /// 制限付きBashシェル。
     RBash,
-    #[default]
     /// Bashシェル。
     Bash,
     /// Zshシェル。
@@ -137,6 +138,13 @@ impl FromStr for ExecShell {
     }
 }
 
+impl Default for ExecShell {
+    /// 設定の`shell`で指定されたシェルを返します（既定は`Bash`）。
+    fn default() -> Self {
+        config::get().shell()
+    }
+}
+
 impl ExecShell {
     /// 実行可能な`Command`オブジェクトを生成します。
     ///
@@ -206,7 +214,9 @@ pub fn project(args: ProjectCommands) -> Result<(), Error> {
         ProjectCommands::Purge { local, global, shell } => {
             project_purge((local, global).into(), shell)
         }
-        ProjectCommands::Package { target } => project_package(target),
+        ProjectCommands::Package { target, sign } => {
+            project_package(target, sign)
+        }
         ProjectCommands::Init => project_init(),
         ProjectCommands::Run { shell, command, args } => {
             project_run(shell, command, args)
@@ -240,15 +250,19 @@ pub fn project_init() -> Result<(), Error> {
 ///
 /// # Arguments
 /// * `target` - パッケージ化のターゲット（オプション）。
+/// * `sign_key` - パッケージの署名に使用する秘密鍵のファイル（オプション）。
 ///
 /// # Returns
 /// `Ok(())` パッケージ化が正常に完了した場合。
 /// `Err(Error)` パッケージ化中にエラーが発生した場合。
 pub fn project_package(
     target: Option<PackageTarget>,
+    sign_key: Option<PathBuf>,
 ) -> Result<(), Error> {
-    let package_options =
-        package::PackageOptions { target: target.unwrap_or_default() };
+    let package_options = package::PackageOptions {
+        target: target.unwrap_or_default(),
+        sign_key,
+    };
 
     package::package(package_options).map_err(Error::from)
 }
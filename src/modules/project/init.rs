@@ -1,6 +1,8 @@
 //! このモジュールは、既存のプロジェクトを`ipak`プロジェクトとして初期化する機能を提供します。
 //! プロジェクトの言語を検出し、それに応じた`ipak`スクリプトと設定ファイルを生成します。
 
+#![allow(clippy::collapsible_if)]
+
 use super::metadata;
 use crate::utils::files::file_creation;
 use crate::utils::version::Version;
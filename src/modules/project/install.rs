@@ -4,6 +4,7 @@
 use super::ExecMode;
 use super::ExecShell;
 use super::metadata::{self, metadata};
+use crate::modules::system::path;
 use crate::utils::color::colorize::*;
 use crate::utils::version::Version;
 use std::fmt::{self, Display};
@@ -56,33 +57,7 @@ impl Display for InstallOptions {
 /// `Err(String)` インストール中にエラーが発生した場合。
 pub fn install(opts: InstallOptions) -> Result<(), String> {
     log::debug!("{}", &opts);
-    let target_dir =
-        metadata::get_dir().map_err(|e| format!("Error: {}", e))?;
-    let project_metadata =
-        metadata().map_err(|e| format!("Error: {}", e))?;
-
-    fn setup_execshell(
-        cmd: &mut Command,
-        target_dir: &std::path::Path,
-        project_name: &str,
-        project_version: &Version,
-        install_mode: &ExecMode,
-    ) {
-        cmd.current_dir(target_dir)
-            .env("IPAK_PROJECT_NAME", project_name)
-            .env("IPAK_PROJECT_VERSION", project_version.to_string())
-            .env("IPAK_INSTALL_MODE", install_mode.to_string())
-            .arg("ipak/scripts/install.sh");
-    }
-
-    let mut install_process = opts.install_shell.generate();
-    setup_execshell(
-        &mut install_process,
-        &target_dir,
-        &project_metadata.about.package.name,
-        &project_metadata.about.package.version,
-        &opts.install_mode,
-    );
+    let mut install_process = setup_execshell(&opts, "install.sh")?;
 
     let status = install_process.status().map_err(|e| {
         format!("Failed to execute install process: {}", e)
@@ -94,3 +69,67 @@ pub fn install(opts: InstallOptions) -> Result<(), String> {
         Err(format!("Install process failed with status: {}", status))
     }
 }
+
+/// プロジェクトのアップグレードフックを実行します。
+///
+/// 既にインストールされているバージョンから移行する際に、
+/// `ipak/scripts/upgrade.sh`スクリプトを実行します。
+/// 以前のバージョンは環境変数`IPAK_PREVIOUS_VERSION`として渡されます。
+///
+/// # Arguments
+/// * `opts` - インストールオプションを含む`InstallOptions`構造体。
+/// * `previous_version` - アップグレード前にインストールされていたバージョン。
+///
+/// # Returns
+/// `Ok(())` アップグレードフックが正常に完了した場合。
+/// `Err(String)` アップグレードフックの実行中にエラーが発生した場合。
+pub fn upgrade(
+    opts: InstallOptions,
+    previous_version: &Version,
+) -> Result<(), String> {
+    log::debug!("{}", &opts);
+    let mut upgrade_process = setup_execshell(&opts, "upgrade.sh")?;
+    upgrade_process
+        .env("IPAK_PREVIOUS_VERSION", previous_version.to_string());
+
+    let status = upgrade_process.status().map_err(|e| {
+        format!("Failed to execute upgrade process: {}", e)
+    })?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("Upgrade process failed with status: {}", status))
+    }
+}
+
+/// `ipak/scripts`内のスクリプトを実行する`Command`を準備します。
+///
+/// # Arguments
+/// * `opts` - インストールオプション。
+/// * `script_name` - 実行するスクリプトのファイル名。
+///
+/// # Returns
+/// `Ok(Command)` 環境変数と作業ディレクトリが設定されたコマンド。
+/// `Err(String)` プロジェクトのメタデータの取得に失敗した場合。
+fn setup_execshell(
+    opts: &InstallOptions,
+    script_name: &str,
+) -> Result<Command, String> {
+    let target_dir =
+        metadata::get_dir().map_err(|e| format!("Error: {}", e))?;
+    let project_metadata =
+        metadata().map_err(|e| format!("Error: {}", e))?;
+
+    let mut cmd = opts.install_shell.generate();
+    cmd.current_dir(&target_dir)
+        .env("IPAK_PROJECT_NAME", &project_metadata.about.package.name)
+        .env(
+            "IPAK_PROJECT_VERSION",
+            project_metadata.about.package.version.to_string(),
+        )
+        .env("IPAK_INSTALL_MODE", opts.install_mode.to_string())
+        .envs(path::script_envs())
+        .arg(format!("ipak/scripts/{}", script_name));
+    Ok(cmd)
+}
@@ -3,7 +3,9 @@
 //! 指定されたターゲット（ソースビルド、通常、最小）に応じたアーカイブを作成します。
 
 use super::metadata;
-use crate::utils::archive::{ArchiveType, create_archive};
+use crate::modules::pkg::signature;
+use crate::modules::system::config;
+use crate::utils::archive::create_archive;
 use crate::utils::color::colorize::*;
 use ignore::gitignore::GitignoreBuilder;
 use serde_yaml;
@@ -17,15 +19,16 @@ use std::str::FromStr;
 pub struct PackageOptions {
     /// パッケージ化のターゲット（例: SourceBuild, Normal, Min）。
     pub target: PackageTarget,
+    /// パッケージの署名に使用する秘密鍵のファイル。`None`の場合は署名しません。
+    pub sign_key: Option<PathBuf>,
 }
 
 /// パッケージ化のターゲットタイプを定義する列挙型です。
-#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
 pub enum PackageTarget {
     /// ソースビルド用のパッケージ。
     SourceBuild,
 
-    #[default]
     /// 通常のパッケージ。
     Normal,
 
@@ -33,6 +36,13 @@ pub enum PackageTarget {
     Min,
 }
 
+impl Default for PackageTarget {
+    /// 設定の`package.target`で指定されたターゲットを返します（既定は`Normal`）。
+    fn default() -> Self {
+        config::get().package_target()
+    }
+}
+
 impl Display for PackageTarget {
     /// `PackageTarget`を整形して表示します。
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -63,6 +73,14 @@ impl Display for PackageOptions {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "{}:", "Package Options".cyan().bold())?;
         writeln!(f, "  {}: {}", "target".green().bold(), self.target)?;
+        if let Some(sign_key) = &self.sign_key {
+            writeln!(
+                f,
+                "  {}: {}",
+                "sign".green().bold(),
+                sign_key.display()
+            )?;
+        }
         Ok(())
     }
 }
@@ -162,6 +180,7 @@ fn walk_and_copy(
 ///
 /// 指定されたパッケージオプションに基づいて、プロジェクトのファイルを収集し、
 /// `.gitignore`設定を尊重してアーカイブを作成します。
+/// 秘密鍵が指定された場合は、収集したファイルのハッシュに署名して`ipak/signature.yaml`として埋め込みます。
 ///
 /// # Arguments
 /// * `opts` - パッケージ化オプションを含む`PackageOptions`構造体。
@@ -250,6 +269,14 @@ pub fn package(opts: PackageOptions) -> Result<(), String> {
 
     walk_and_copy(source_base, &dest_base, &gitignore, &skip_prefix)?;
 
+    if let Some(sign_key) = &opts.sign_key {
+        let signing_key = signature::read_signing_key(sign_key)
+            .map_err(|e| format!("Failed to load signing key: {}", e))?;
+        signature::sign_dir(&dest_base, &signing_key)
+            .map_err(|e| format!("Failed to sign the package: {}", e))?;
+        log::debug!("Signed the package with {}", sign_key.display());
+    }
+
     let archive_path: PathBuf = source_base
         .join("ipak")
         .join("package")
@@ -261,8 +288,13 @@ pub fn package(opts: PackageOptions) -> Result<(), String> {
         })?;
     }
 
-    log::debug!("Creating zip archive at {}", archive_path.display());
-    create_archive(&dest_base, &archive_path, ArchiveType::Zip)
+    let archive_type = config::get().package_archive_type();
+    log::debug!(
+        "Creating {} archive at {}",
+        archive_type,
+        archive_path.display()
+    );
+    create_archive(&dest_base, &archive_path, archive_type)
         .map_err(|e| format!("Failed to create archive: {}", e))?;
 
     fs::remove_dir_all(&dest_base).map_err(|e| {
@@ -4,6 +4,7 @@
 use super::ExecMode;
 use super::ExecShell;
 use super::metadata;
+use crate::modules::system::path;
 use crate::utils::color::colorize::*;
 use std::fmt::{self, Display};
 
@@ -67,6 +68,7 @@ pub fn purge(opts: PurgeOptions) -> Result<(), String> {
             project_metadata.about.package.version.to_string(),
         )
         .env("IPAK_PURGE_MODE", opts.purge_mode.to_string())
+        .envs(path::script_envs())
         .arg("ipak/scripts/purge.sh");
 
     let status = purge_process
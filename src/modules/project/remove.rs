@@ -4,6 +4,7 @@
 use super::ExecMode;
 use super::ExecShell;
 use super::metadata;
+use crate::modules::system::path;
 use crate::utils::color::colorize::*;
 use std::fmt::{self, Display};
 
@@ -69,6 +70,7 @@ pub fn remove(opts: RemoveOptions) -> Result<(), String> {
             project_metadata.about.package.version.to_string(),
         )
         .env("IPAK_REMOVE_MODE", opts.remove_mode.to_string())
+        .envs(path::script_envs())
         .arg("ipak/scripts/remove.sh");
 
     let status = remove_process
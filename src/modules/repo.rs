@@ -0,0 +1,594 @@
+//! このモジュールは、パッケージリポジトリの管理に関連する機能を提供します。
+//!
+//! リポジトリは`.ipak`アーカイブを格納したディレクトリ（または`file://`で指定されるツリー）で、
+//! 各パッケージの`PackageData`を記録した`index.yaml`を持ちます。
+//! 設定されたリポジトリのインデックスを使って、パッケージを名前とバージョン範囲から解決します。
+
+use crate::modules::pkg::{self, PackageData};
+use crate::modules::project::ExecMode;
+use crate::modules::system::path;
+use crate::utils::args::RepoCommands;
+use crate::utils::color::colorize::*;
+use crate::utils::error::Error;
+use crate::utils::version::{Version, VersionRange};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use walkdir::WalkDir;
+
+/// リポジトリディレクトリ内のインデックスファイル名です。
+const INDEX_FILENAME: &str = "index.yaml";
+
+/// リポジトリのインデックスを表す構造体です。
+#[derive(Serialize, Deserialize)]
+pub struct RepositoryIndex {
+    /// インデックスの生成日時。
+    pub last_modified: DateTime<Local>,
+    /// リポジトリに含まれるパッケージのリスト。
+    pub packages: Vec<IndexEntry>,
+}
+
+/// インデックスに記録される個々のパッケージです。
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexEntry {
+    /// リポジトリディレクトリからの相対パス。
+    pub filename: PathBuf,
+    /// パッケージのメタデータ。
+    pub info: PackageData,
+}
+
+/// 設定されたリポジトリのリストを表す構造体です。
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct RepositoryListData {
+    /// 登録されているリポジトリ。
+    pub repositories: Vec<RepositoryData>,
+}
+
+/// 個々のリポジトリの設定です。
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RepositoryData {
+    /// リポジトリの名前。
+    pub name: String,
+    /// リポジトリの場所（ディレクトリのパス、または`file://`のURL）。
+    pub url: String,
+}
+
+impl Display for RepositoryData {
+    /// `RepositoryData`を整形して表示します。
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name.cyan().bold(), self.url)
+    }
+}
+
+impl RepositoryData {
+    /// リポジトリのURLをローカルのディレクトリパスに変換します。
+    ///
+    /// # Returns
+    /// `Ok(PathBuf)` リポジトリのディレクトリ。
+    /// `Err(io::Error)` サポートされていないURLスキームの場合。
+    pub fn dirpath(&self) -> Result<PathBuf, io::Error> {
+        if let Some(path) = self.url.strip_prefix("file://") {
+            Ok(PathBuf::from(path))
+        } else if self.url.contains("://") {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "Unsupported repository URL '{}'. Only directories and file:// URLs are supported.",
+                    self.url
+                ),
+            ))
+        } else {
+            Ok(PathBuf::from(&self.url))
+        }
+    }
+
+    /// リポジトリのインデックスを読み込みます。
+    ///
+    /// `index.yaml`が存在しない場合は、ディレクトリ内の`.ipak`アーカイブを直接読み込みます。
+    ///
+    /// # Returns
+    /// `Ok(RepositoryIndex)` 読み込まれたインデックス。
+    /// `Err(io::Error)` インデックスの読み込みまたはパースに失敗した場合。
+    pub fn index(&self) -> Result<RepositoryIndex, io::Error> {
+        let repository_dir = self.dirpath()?;
+        let index_path = repository_dir.join(INDEX_FILENAME);
+        if !index_path.exists() && repository_dir.is_dir() {
+            log::debug!(
+                "Repository '{}' has no index. Scanning {}",
+                self.name,
+                repository_dir.display()
+            );
+            let packages = scan_archives(&repository_dir)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            return Ok(RepositoryIndex {
+                last_modified: Local::now(),
+                packages,
+            });
+        }
+        let index_str = fs::read_to_string(&index_path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "Failed to read repository index '{}': {}",
+                    index_path.display(),
+                    e
+                ),
+            )
+        })?;
+        serde_yaml::from_str(&index_str).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse repository index '{}': {}",
+                    index_path.display(),
+                    e
+                ),
+            )
+        })
+    }
+}
+
+impl RepositoryListData {
+    /// 指定されたモードのリポジトリリストを読み込みます。
+    ///
+    /// ファイルが存在しない場合は、空のリストを返します。
+    ///
+    /// # Arguments
+    /// * `mode` - 実行モード（ローカルまたはグローバル）。
+    ///
+    /// # Returns
+    /// `Ok(RepositoryListData)` 読み込まれたリポジトリリスト。
+    /// `Err(io::Error)` ファイルの読み込みまたはパースに失敗した場合。
+    pub fn get(mode: ExecMode) -> Result<Self, io::Error> {
+        let list_filepath = repositories_filepath(mode);
+        let list_str = match fs::read_to_string(&list_filepath) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(e),
+        };
+        serde_yaml::from_str(&list_str).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Failed to parse repositories list file '{}': {}",
+                    list_filepath.display(),
+                    e
+                ),
+            )
+        })
+    }
+
+    /// リポジトリリストを指定されたモードのファイルに書き込みます。
+    ///
+    /// 一時ファイルに書き込んでから置き換えるため、書き込みの途中でクラッシュしてもリストは壊れません。
+    ///
+    /// # Arguments
+    /// * `mode` - 実行モード（ローカルまたはグローバル）。
+    ///
+    /// # Returns
+    /// `Ok(())` 書き込みに成功した場合。
+    /// `Err(io::Error)` シリアライズまたは書き込みに失敗した場合。
+    pub fn apply(&self, mode: ExecMode) -> Result<(), io::Error> {
+        pkg::list::write_yaml(&repositories_filepath(mode), self)
+    }
+}
+
+/// リポジトリ関連のコマンドを処理します。
+///
+/// # Arguments
+/// * `args` - 処理するリポジトリコマンド。
+///
+/// # Returns
+/// `Ok(())` 成功した場合。
+/// `Err(Error)` エラーが発生した場合。
+pub fn repo(args: RepoCommands) -> Result<(), Error> {
+    match args {
+        RepoCommands::Index { dir } => index(&dir),
+        RepoCommands::Add { name, url, local, global } => {
+            add(name, url, (local, global).into())
+        }
+        RepoCommands::Remove { name, local, global } => {
+            remove(&name, (local, global).into())
+        }
+        RepoCommands::List { local, global } => {
+            list((local, global).into())
+        }
+    }
+}
+
+/// 指定されたディレクトリ内の`.ipak`アーカイブから`index.yaml`を生成します。
+///
+/// # Arguments
+/// * `dir` - リポジトリのディレクトリ。
+///
+/// # Returns
+/// `Ok(())` インデックスが正常に生成された場合。
+/// `Err(Error)` アーカイブの読み込み、またはインデックスの書き込みに失敗した場合。
+pub fn index(dir: &Path) -> Result<(), Error> {
+    let packages = scan_archives(dir)?;
+    for entry in &packages {
+        log::info!(
+            "Indexed {} {} ({})",
+            entry.info.about.package.name,
+            entry.info.about.package.version,
+            entry.filename.display()
+        );
+    }
+
+    let repository_index =
+        RepositoryIndex { last_modified: Local::now(), packages };
+    pkg::list::write_yaml(&dir.join(INDEX_FILENAME), &repository_index)?;
+    log::info!(
+        "Wrote {} package(s) to {}",
+        repository_index.packages.len(),
+        dir.join(INDEX_FILENAME).display()
+    );
+    Ok(())
+}
+
+/// リポジトリをリポジトリリストに追加します。
+///
+/// 同じ名前のリポジトリが既に存在する場合は、URLを更新します。
+/// ディレクトリのパスは絶対パスに変換して保存されます。
+///
+/// # Arguments
+/// * `name` - リポジトリの名前。
+/// * `url` - リポジトリの場所。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 追加に成功した場合。
+/// `Err(Error)` リポジトリリストの読み込みまたは書き込みに失敗した場合。
+pub fn add(
+    name: String,
+    url: String,
+    mode: ExecMode,
+) -> Result<(), Error> {
+    let url = if url.contains("://") {
+        url
+    } else {
+        fs::canonicalize(&url)?.to_string_lossy().into_owned()
+    };
+    let repository = RepositoryData { name, url };
+    if let Err(e) = repository.index() {
+        log::warn!("{}", e);
+        log::warn!(
+            "Run `ipak repo index` in the repository to generate its index."
+        );
+    }
+
+    let mut repository_list = RepositoryListData::get(mode)?;
+    match repository_list
+        .repositories
+        .iter_mut()
+        .find(|r| r.name == repository.name)
+    {
+        Some(existing) => existing.url = repository.url.clone(),
+        None => repository_list.repositories.push(repository.clone()),
+    }
+    repository_list.apply(mode)?;
+    log::info!("Added repository {}", repository);
+    Ok(())
+}
+
+/// リポジトリをリポジトリリストから削除します。
+///
+/// # Arguments
+/// * `name` - 削除するリポジトリの名前。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 削除に成功した場合。
+/// `Err(Error)` リポジトリが見つからない、またはリポジトリリストの更新に失敗した場合。
+pub fn remove(name: &str, mode: ExecMode) -> Result<(), Error> {
+    let mut repository_list = RepositoryListData::get(mode)?;
+    let original_len = repository_list.repositories.len();
+    repository_list.repositories.retain(|r| r.name != name);
+    if repository_list.repositories.len() == original_len {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Repository '{}' is not configured ({})", name, mode),
+        )
+        .into());
+    }
+    repository_list.apply(mode)?;
+    log::info!("Removed repository '{}'", name);
+    Ok(())
+}
+
+/// 設定されているリポジトリを一覧表示します。
+///
+/// # Arguments
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 表示に成功した場合。
+/// `Err(Error)` リポジトリリストの読み込みに失敗した場合。
+pub fn list(mode: ExecMode) -> Result<(), Error> {
+    let repository_list = RepositoryListData::get(mode)?;
+    if repository_list.repositories.is_empty() {
+        log::info!("No repositories configured in this scope.");
+    }
+    for repository in &repository_list.repositories {
+        log::info!("{}", repository);
+    }
+    Ok(())
+}
+
+/// インストール対象の指定をパッケージアーカイブのパスに解決します。
+///
+/// 既存のファイルを指す指定はそのまま使用し、それ以外は`name[@range]`として
+/// 設定されたリポジトリから解決します。
+///
+/// # Arguments
+/// * `targets` - ファイルパス、または`name[@range]`のリスト。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(Vec<PathBuf>)` 解決されたパッケージアーカイブのパス。
+/// `Err(Error)` いずれかの指定を解決できなかった場合。
+pub fn resolve_targets(
+    targets: &[String],
+    mode: ExecMode,
+) -> Result<Vec<PathBuf>, Error> {
+    targets
+        .iter()
+        .map(|target| {
+            let target_path = PathBuf::from(target);
+            if target_path.is_file() {
+                Ok(target_path)
+            } else {
+                let (name, range) = parse_package_spec(target)?;
+                find_package(&name, &range, mode)
+            }
+        })
+        .collect()
+}
+
+/// 設定されたリポジトリから、名前とバージョン範囲に一致する最新のパッケージを検索します。
+///
+/// ローカルモードではローカルとグローバルの両方のリポジトリを、
+/// グローバルモードではグローバルのリポジトリのみを検索します。
+///
+/// # Arguments
+/// * `name` - パッケージ名。
+/// * `range` - 許容するバージョン範囲。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(PathBuf)` 見つかったパッケージアーカイブのパス。
+/// `Err(Error)` 一致するパッケージが見つからない場合。
+pub fn find_package(
+    name: &str,
+    range: &VersionRange,
+    mode: ExecMode,
+) -> Result<PathBuf, Error> {
+    let mut best: Option<(Version, PathBuf)> = None;
+    for (archive_path, info) in available_packages(mode)? {
+        let version = &info.about.package.version;
+        if info.about.package.name != name || !range.compare(version) {
+            continue;
+        }
+        if best.as_ref().is_none_or(|(v, _)| version > v) {
+            best = Some((version.clone(), archive_path));
+        }
+    }
+
+    match best {
+        Some((version, archive_path)) => {
+            log::info!(
+                "Resolved {} ({}) to {} {}",
+                name,
+                range,
+                version,
+                archive_path.display()
+            );
+            Ok(archive_path)
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "No package matching {} ({}) was found in the configured repositories",
+                name, range
+            ),
+        )
+        .into()),
+    }
+}
+
+/// 設定されたリポジトリから利用可能なすべてのパッケージを取得します。
+///
+/// ローカルモードではローカルとグローバルの両方のリポジトリを、
+/// グローバルモードではグローバルのリポジトリのみを対象とします。
+/// 読み込めないリポジトリは警告を出して無視されます。
+///
+/// # Arguments
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(Vec<(PathBuf, PackageData)>)` パッケージアーカイブのパスとメタデータの組。
+/// `Err(Error)` リポジトリリストの読み込みに失敗した場合。
+pub fn available_packages(
+    mode: ExecMode,
+) -> Result<Vec<(PathBuf, PackageData)>, Error> {
+    let mut repositories = RepositoryListData::get(mode)?.repositories;
+    if mode == ExecMode::Local {
+        repositories.extend(
+            RepositoryListData::get(ExecMode::Global)?.repositories,
+        );
+    }
+
+    let mut packages = Vec::new();
+    for repository in &repositories {
+        let (repository_dir, repository_index) = match repository
+            .dirpath()
+            .and_then(|dir| repository.index().map(|index| (dir, index)))
+        {
+            Ok(found) => found,
+            Err(e) => {
+                log::warn!(
+                    "Skipping repository '{}': {}",
+                    repository.name,
+                    e
+                );
+                continue;
+            }
+        };
+        for entry in repository_index.packages {
+            if !stays_inside_repository(&entry.filename) {
+                log::warn!(
+                    "Skipping '{}' in repository '{}': the path leaves the repository directory",
+                    entry.filename.display(),
+                    repository.name
+                );
+                continue;
+            }
+            packages
+                .push((repository_dir.join(&entry.filename), entry.info));
+        }
+    }
+    Ok(packages)
+}
+
+/// インデックスに記録されたパスが、リポジトリディレクトリ内を指しているかどうかを返します。
+///
+/// `repo index`はサブディレクトリ内のアーカイブも記録するため、
+/// 通常の名前だけからなる相対パスを許可し、絶対パスや`..`、`.`を含むパスは拒否します。
+fn stays_inside_repository(filename: &Path) -> bool {
+    filename.components().next().is_some()
+        && filename
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// ディレクトリ内の`.ipak`アーカイブを再帰的に検索し、メタデータを読み込みます。
+///
+/// # Arguments
+/// * `dir` - 検索するディレクトリ。
+///
+/// # Returns
+/// `Ok(Vec<IndexEntry>)` パス順に並べられたインデックスのエントリ。
+/// `Err(Error)` アーカイブの読み込みに失敗した場合。
+fn scan_archives(dir: &Path) -> Result<Vec<IndexEntry>, Error> {
+    let mut archive_paths = WalkDir::new(dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ipak"))
+        .collect::<Vec<_>>();
+    archive_paths.sort();
+
+    archive_paths
+        .into_iter()
+        .map(|archive_path| {
+            let info = pkg::metadata::get(&archive_path)?;
+            let filename = archive_path
+                .strip_prefix(dir)
+                .unwrap_or(&archive_path)
+                .to_path_buf();
+            Ok(IndexEntry { filename, info })
+        })
+        .collect()
+}
+
+/// `name[@range]`形式のパッケージ指定を解析します。
+///
+/// 範囲が省略された場合は、すべてのバージョンに一致します。
+/// `name@>=1.0`のように演算子とバージョンの間の空白は省略できます。
+///
+/// # Arguments
+/// * `spec` - パッケージ指定文字列。
+///
+/// # Returns
+/// `Ok((String, VersionRange))` パッケージ名とバージョン範囲。
+/// `Err(Error)` バージョン範囲の解析に失敗した場合。
+pub fn parse_package_spec(
+    spec: &str,
+) -> Result<(String, VersionRange), Error> {
+    let Some((name, range_str)) = spec.split_once('@') else {
+        return Ok((spec.to_string(), VersionRange::default()));
+    };
+    let range_str = range_str
+        .split(',')
+        .map(|part| {
+            let part = part.trim();
+            let version_start = part
+                .find(|c: char| !matches!(c, '<' | '>' | '=' | ' '))
+                .unwrap_or(part.len());
+            let (symbol, version) = part.split_at(version_start);
+            format!("{} {}", symbol.trim(), version).trim().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let range = VersionRange::from_str(&range_str)?;
+    Ok((name.to_string(), range))
+}
+
+/// 指定されたモードのリポジトリリストファイルへのパスを返します。
+fn repositories_filepath(mode: ExecMode) -> PathBuf {
+    match mode {
+        ExecMode::Local => path::local::repositories_filepath(),
+        ExecMode::Global => path::global::repositories_filepath(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_package_spec() {
+        let (name, range) = parse_package_spec("tool").unwrap();
+        assert_eq!(name, "tool");
+        assert!(range.compare(&Version::from_str("0.1").unwrap()));
+
+        let (name, range) = parse_package_spec("tool@>=1.0,<2.0").unwrap();
+        assert_eq!(name, "tool");
+        assert!(range.compare(&Version::from_str("1.5").unwrap()));
+        assert!(!range.compare(&Version::from_str("2.0").unwrap()));
+
+        let (_, range) = parse_package_spec("tool@1.2").unwrap();
+        assert!(range.compare(&Version::from_str("1.2").unwrap()));
+        assert!(!range.compare(&Version::from_str("1.3").unwrap()));
+
+        assert!(parse_package_spec("tool@=>1.0").is_err());
+    }
+
+    #[test]
+    fn test_repository_dirpath() {
+        let repository = RepositoryData {
+            name: "team".to_string(),
+            url: "file:///srv/ipak".to_string(),
+        };
+        assert_eq!(
+            repository.dirpath().unwrap(),
+            PathBuf::from("/srv/ipak")
+        );
+
+        let repository = RepositoryData {
+            name: "web".to_string(),
+            url: "https://example.com/ipak".to_string(),
+        };
+        assert!(repository.dirpath().is_err());
+    }
+
+    #[test]
+    fn test_index_paths_must_stay_inside_repository() {
+        assert!(stays_inside_repository(Path::new("tool-1.0.ipak")));
+        assert!(stays_inside_repository(Path::new("tools/tool-1.0.ipak")));
+        assert!(!stays_inside_repository(Path::new("")));
+        assert!(!stays_inside_repository(Path::new("/etc/passwd")));
+        assert!(!stays_inside_repository(Path::new("../tool-1.0.ipak")));
+        assert!(!stays_inside_repository(Path::new("tools/../../x.ipak")));
+        assert!(!stays_inside_repository(Path::new("./tool-1.0.ipak")));
+    }
+}
@@ -1,14 +1,19 @@
 //! このモジュールは、システム関連のコマンドを処理します。
 //! 主に`ipak`の設定（ローカルおよびグローバル）を管理します。
 
+mod check;
+pub mod config;
 mod configure;
+mod db;
+pub mod env;
+mod key;
 pub mod path;
+mod recover;
 use crate::utils::{args::SystemCommands, error::Error};
 
 /// システム関連のコマンドを処理します。
 ///
 /// `SystemCommands`列挙型に基づいて、適切なシステム関数にディスパッチします。
-/// 現在は設定コマンドのみをサポートしています。
 ///
 /// # Arguments
 /// * `args` - 処理するシステムコマンド。
@@ -30,6 +35,18 @@ pub fn system(args: SystemCommands) -> Result<(), Error> {
             })
             .map_err(Error::from)?
         }
+        SystemCommands::Recover { local, global } => {
+            recover::recover((local, global).into())?
+        }
+        SystemCommands::Db(args) => db::db(args)?,
+        SystemCommands::Check { fix, local, global } => {
+            check::check(fix, (local, global).into())?
+        }
+        SystemCommands::Key(args) => key::key(args)?,
+        SystemCommands::Config(args) => config::config(args)?,
+        SystemCommands::Env { shell, local, global } => {
+            env::env(shell, (local, global).into())?
+        }
     }
     Ok(())
 }
@@ -0,0 +1,495 @@
+//! このモジュールは、インストール済みパッケージのデータベースの整合性を検査し、修復します。
+//!
+//! データベースのエントリとパッケージディレクトリの対応、重複したエントリ、
+//! インストール済みパッケージ間の依存関係と競合、不足している依存コマンドを検査します。
+//! `--fix`を指定すると、データベースとパッケージディレクトリの不一致を修復します。
+
+use crate::modules::pkg::depend::{self, DependencyGraph};
+use crate::modules::pkg::list::{
+    self, FileDatabase, InstalledPackageData, PackageDatabase,
+    PackageListData, REMOVED_MARKER_FILEPATH,
+};
+use crate::modules::pkg::lock::LockManager;
+use crate::modules::pkg::{PackageData, PackageRange};
+use crate::modules::project::ExecMode;
+use crate::utils::error::Error;
+use chrono::Local;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// 検査で見つかった問題を表す列挙型です。
+#[derive(Debug)]
+pub enum Problem {
+    /// データベースにエントリがあるが、パッケージディレクトリが存在しない。
+    MissingDirectory { name: String },
+    /// データベースにエントリがあるが、レコードを読み込めない。
+    BrokenRecord { name: String, reason: String },
+    /// パッケージディレクトリがあるが、データベースにエントリがない。
+    /// `adoptable`は、ディレクトリ内の`ipak/project.yaml`からレコードを作成できるかどうかを表します。
+    UntrackedDirectory { name: String, adoptable: bool },
+    /// 同じ名前のエントリが複数ある。
+    DuplicateEntry { name: String, count: usize },
+    /// 満たされていない依存関係がある。
+    UnsatisfiedDependency { name: String, groups: Vec<Vec<PackageRange>> },
+    /// インストール済みのパッケージと競合している。
+    Conflict { name: String, conflicts: Vec<PackageRange> },
+    /// 依存コマンドが見つからない。
+    MissingCommands { name: String, commands: Vec<String> },
+}
+
+impl Problem {
+    /// `--fix`で修復できる問題かどうかを返します。
+    ///
+    /// データベースとパッケージディレクトリの不一致だけが修復の対象です。
+    /// 依存関係や競合の問題は、パッケージのインストールまたは削除で解決する必要があります。
+    pub fn is_fixable(&self) -> bool {
+        matches!(
+            self,
+            Problem::MissingDirectory { .. }
+                | Problem::BrokenRecord { .. }
+                | Problem::UntrackedDirectory { adoptable: true, .. }
+                | Problem::DuplicateEntry { .. }
+        )
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Problem::MissingDirectory { name } => write!(
+                f,
+                "{}: listed in the database, but its package directory does not exist",
+                name
+            ),
+            Problem::BrokenRecord { name, reason } => write!(
+                f,
+                "{}: listed in the database, but its record cannot be read ({})",
+                name, reason
+            ),
+            Problem::UntrackedDirectory { name, adoptable } => {
+                write!(
+                    f,
+                    "{}: a package directory exists, but it is not in the database",
+                    name
+                )?;
+                if !adoptable {
+                    write!(
+                        f,
+                        " (it has no valid ipak/project.yaml; remove it manually if it is not needed)"
+                    )?;
+                }
+                Ok(())
+            }
+            Problem::DuplicateEntry { name, count } => {
+                write!(
+                    f,
+                    "{}: listed {} times in the database",
+                    name, count
+                )
+            }
+            Problem::UnsatisfiedDependency { name, groups } => write!(
+                f,
+                "{}: unsatisfied dependencies: {}",
+                name,
+                groups
+                    .iter()
+                    .map(|group| depend::incompatibility::format_group(
+                        group
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Problem::Conflict { name, conflicts } => write!(
+                f,
+                "{}: conflicts with installed packages: {}",
+                name,
+                conflicts
+                    .iter()
+                    .map(|conflict| conflict.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Problem::MissingCommands { name, commands } => write!(
+                f,
+                "{}: missing commands: {}",
+                name,
+                commands.join(", ")
+            ),
+        }
+    }
+}
+
+/// インストール済みパッケージのデータベースを検査します。
+///
+/// 見つかった問題を表示し、`fix`が指定された場合は修復できる問題を修復します。
+///
+/// # Arguments
+/// * `fix` - 修復できる問題を修復するかどうか。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 問題が見つからなかった場合、またはすべての問題を修復した場合。
+/// `Err(Error)` 問題が残っている場合、またはデータベースの読み込みや書き込みに失敗した場合。
+pub fn check(fix: bool, mode: ExecMode) -> Result<(), Error> {
+    let lock_manager = LockManager::new(matches!(mode, ExecMode::Global));
+    let _lock = if fix {
+        lock_manager.acquire_lock()?
+    } else {
+        lock_manager.acquire_shared_lock()?
+    };
+    let packages_dir = list::packages_dirpath(mode);
+    let database = FileDatabase::new(packages_dir.clone());
+
+    let mut problems = inspect(&database, &packages_dir)?;
+    if fix && problems.iter().any(Problem::is_fixable) {
+        let fixed = repair(&database, &packages_dir, &problems)?;
+        log::warn!("Fixed {} problem(s) in the {} database.", fixed, mode);
+        problems = inspect(&database, &packages_dir)?;
+    }
+
+    if problems.is_empty() {
+        log::info!("No problems found in the {} database.", mode);
+        return Ok(());
+    }
+    for problem in &problems {
+        log::warn!("{}", problem);
+    }
+    let hint = if !fix && problems.iter().any(Problem::is_fixable) {
+        " Run `ipak system check --fix` to repair the database."
+    } else {
+        ""
+    };
+    Err(Error::from(format!(
+        "Found {} problem(s) in the {} database.{}",
+        problems.len(),
+        mode,
+        hint
+    )))
+}
+
+/// データベースとパッケージディレクトリを検査し、見つかった問題を返します。
+///
+/// # Arguments
+/// * `database` - 検査するデータベース。
+/// * `packages_dir` - パッケージがインストールされるディレクトリ。
+///
+/// # Returns
+/// `Ok(Vec<Problem>)` 見つかった問題のリスト。
+/// `Err(io::Error)` インデックスまたはパッケージディレクトリの読み込みに失敗した場合。
+fn inspect(
+    database: &FileDatabase,
+    packages_dir: &Path,
+) -> Result<Vec<Problem>, io::Error> {
+    let mut problems = Vec::new();
+    let mut counts = BTreeMap::<String, usize>::new();
+    let mut installed = PackageListData::default();
+
+    for entry in database.entries()? {
+        let name = entry.entry.name;
+        let count = counts.entry(name.clone()).or_default();
+        *count += 1;
+        if *count > 1 {
+            continue;
+        }
+        if !packages_dir.join(&name).is_dir() {
+            problems.push(Problem::MissingDirectory { name });
+            continue;
+        }
+        match entry.record {
+            Ok(package) => installed.installed_packages.push(package),
+            Err(e) => problems.push(Problem::BrokenRecord {
+                name,
+                reason: e.to_string(),
+            }),
+        }
+    }
+    for (name, count) in &counts {
+        if *count > 1 {
+            problems.push(Problem::DuplicateEntry {
+                name: name.clone(),
+                count: *count,
+            });
+        }
+    }
+    for name in package_dir_names(packages_dir)? {
+        if !counts.contains_key(&name) {
+            let adoptable =
+                read_package_metadata(&packages_dir.join(&name))
+                    .is_ok_and(|info| info.about.package.name == name);
+            problems.push(Problem::UntrackedDirectory { name, adoptable });
+        }
+    }
+
+    let graph = DependencyGraph::from_installed_packages(&installed);
+    for package in &installed.installed_packages {
+        let info = &package.info;
+        let name = info.about.package.name.clone();
+        if !graph.are_dependencies_satisfied(info) {
+            problems.push(Problem::UnsatisfiedDependency {
+                name: name.clone(),
+                groups: graph.get_missing_dependencies(info),
+            });
+        }
+        if let Some(conflicts) = graph.has_conflicts(info) {
+            problems
+                .push(Problem::Conflict { name: name.clone(), conflicts });
+        }
+        let commands =
+            depend::utils::get_missing_depend_cmds(&info.relation);
+        if !commands.is_empty() {
+            problems.push(Problem::MissingCommands { name, commands });
+        }
+    }
+    Ok(problems)
+}
+
+/// データベースとパッケージディレクトリの不一致を修復します。
+///
+/// 修復の前にデータベースのスナップショットを保存するため、`ipak system db restore`で元に戻せます。
+/// - パッケージディレクトリが存在しないエントリはデータベースから削除します。
+/// - 重複したエントリは1つにまとめます。
+/// - レコードを読み込めないエントリと、データベースにないパッケージディレクトリは、
+///   ディレクトリ内の`ipak/project.yaml`から手動でインストールされたパッケージとしてレコードを作り直します。
+///   `project.yaml`を読み込めない場合、エントリはデータベースから削除され、ディレクトリはそのまま残されます。
+///
+/// # Arguments
+/// * `database` - 修復するデータベース。
+/// * `packages_dir` - パッケージがインストールされるディレクトリ。
+/// * `problems` - `inspect`で見つかった問題。
+///
+/// # Returns
+/// `Ok(usize)` 修復した問題の数。
+/// `Err(io::Error)` データベースの読み込みまたは書き込みに失敗した場合。
+fn repair(
+    database: &FileDatabase,
+    packages_dir: &Path,
+    problems: &[Problem],
+) -> Result<usize, io::Error> {
+    let mut installed_packages: Vec<InstalledPackageData> = Vec::new();
+    for entry in database.entries()? {
+        let Ok(package) = entry.record else {
+            continue;
+        };
+        if !packages_dir.join(&entry.entry.name).is_dir() {
+            continue;
+        }
+        // 重複したエントリは、後に記録されたものを優先する
+        match installed_packages.iter_mut().find(|installed| {
+            installed.info.about.package.name == entry.entry.name
+        }) {
+            Some(installed) => *installed = package,
+            None => installed_packages.push(package),
+        }
+    }
+
+    let mut fixed = 0;
+    for problem in problems {
+        match problem {
+            Problem::MissingDirectory { name } => {
+                log::warn!("Removed '{}' from the database", name);
+                fixed += 1;
+            }
+            Problem::DuplicateEntry { name, .. } => {
+                log::warn!("Merged the duplicate entries of '{}'", name);
+                fixed += 1;
+            }
+            Problem::BrokenRecord { name, .. }
+            | Problem::UntrackedDirectory { name, adoptable: true } => {
+                match read_package_metadata(&packages_dir.join(name)) {
+                    Ok(info) if info.about.package.name == *name => {
+                        installed_packages.push(InstalledPackageData {
+                            info,
+                            last_modified: Local::now(),
+                            ..Default::default()
+                        });
+                        log::warn!(
+                            "Rebuilt the record of '{}' from its project.yaml",
+                            name
+                        );
+                        fixed += 1;
+                    }
+                    Ok(info) => log::warn!(
+                        "Cannot rebuild the record of '{}': its project.yaml describes '{}'",
+                        name,
+                        info.about.package.name
+                    ),
+                    Err(e) => log::warn!(
+                        "Cannot rebuild the record of '{}': {}",
+                        name,
+                        e
+                    ),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    database.backup()?;
+    database.replace_all(PackageListData {
+        last_modified: Local::now(),
+        installed_packages,
+    })?;
+    Ok(fixed)
+}
+
+/// パッケージディレクトリ内のパッケージのディレクトリ名を返します。
+///
+/// `.`で始まるディレクトリ（トランザクションのバックアップなど）と、
+/// `ipak pkg remove`がパージのために残したディレクトリは除外されます。
+fn package_dir_names(
+    packages_dir: &Path,
+) -> Result<Vec<String>, io::Error> {
+    let read_dir = match fs::read_dir(packages_dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(e),
+    };
+    let mut names = Vec::new();
+    for entry in read_dir {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir()
+            && !name.starts_with('.')
+            && !entry.path().join(REMOVED_MARKER_FILEPATH).exists()
+        {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// パッケージディレクトリ内の`ipak/project.yaml`を読み込みます。
+fn read_package_metadata(
+    package_dir: &Path,
+) -> Result<PackageData, io::Error> {
+    let metadata_path = package_dir.join("ipak/project.yaml");
+    let content = fs::read_to_string(&metadata_path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to read {}: {}", metadata_path.display(), e),
+        )
+    })?;
+    serde_yaml::from_str(&content).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Failed to parse {}: {}", metadata_path.display(), e),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::pkg::list::{IndexEntry, PackageIndex};
+    use crate::utils::version::Version;
+    use std::str::FromStr;
+
+    fn install(database: &FileDatabase, dir: &Path, name: &str) {
+        let package = InstalledPackageData::for_test(name, "1.0");
+        let metadata_dir = dir.join(name).join("ipak");
+        fs::create_dir_all(&metadata_dir).unwrap();
+        fs::write(
+            metadata_dir.join("project.yaml"),
+            serde_yaml::to_string(&package.info).unwrap(),
+        )
+        .unwrap();
+        database.put(package).unwrap();
+    }
+
+    #[test]
+    fn test_inspect_reports_database_and_relation_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = FileDatabase::new(dir.path().to_path_buf());
+        install(&database, dir.path(), "ok");
+        database
+            .put(InstalledPackageData::for_test("gone", "1.0"))
+            .unwrap();
+        fs::remove_dir_all(dir.path().join("gone")).unwrap();
+        database
+            .put(
+                InstalledPackageData::for_test("app", "1.0")
+                    .depending_on(&["missing"]),
+            )
+            .unwrap();
+        fs::create_dir_all(dir.path().join("stray")).unwrap();
+        fs::create_dir_all(dir.path().join(".transaction-x")).unwrap();
+
+        let problems = inspect(&database, dir.path()).unwrap();
+        assert_eq!(problems.len(), 3);
+        assert!(matches!(
+            &problems[0],
+            Problem::MissingDirectory { name } if name == "gone"
+        ));
+        assert!(matches!(
+            &problems[1],
+            Problem::UntrackedDirectory { name, adoptable: false }
+                if name == "stray"
+        ));
+        assert!(matches!(
+            &problems[2],
+            Problem::UnsatisfiedDependency { name, .. } if name == "app"
+        ));
+    }
+
+    #[test]
+    fn test_inspect_ignores_removed_package_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = FileDatabase::new(dir.path().to_path_buf());
+        install(&database, dir.path(), "kept");
+        // `ipak pkg remove`と同様に、レコードを削除して目印を残す
+        database.delete("kept").unwrap();
+        fs::write(
+            dir.path().join("kept").join(REMOVED_MARKER_FILEPATH),
+            "",
+        )
+        .unwrap();
+
+        assert!(inspect(&database, dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_repair_reconciles_database_with_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = FileDatabase::new(dir.path().to_path_buf());
+        install(&database, dir.path(), "a");
+        install(&database, dir.path(), "b");
+        database
+            .put(InstalledPackageData::for_test("gone", "1.0"))
+            .unwrap();
+        fs::remove_dir_all(dir.path().join("gone")).unwrap();
+        fs::remove_file(database.record_filepath("b").unwrap()).unwrap();
+        let mut index: PackageIndex = serde_yaml::from_str(
+            &fs::read_to_string(database.index_filepath()).unwrap(),
+        )
+        .unwrap();
+        index.packages.push(IndexEntry {
+            name: "a".to_string(),
+            version: Version::from_str("1.0").unwrap(),
+        });
+        fs::write(
+            database.index_filepath(),
+            serde_yaml::to_string(&index).unwrap(),
+        )
+        .unwrap();
+
+        let problems = inspect(&database, dir.path()).unwrap();
+        assert_eq!(problems.len(), 3);
+        assert_eq!(repair(&database, dir.path(), &problems).unwrap(), 3);
+        assert!(inspect(&database, dir.path()).unwrap().is_empty());
+        let names = database
+            .load_all()
+            .unwrap()
+            .installed_packages
+            .into_iter()
+            .map(|pkg| pkg.info.about.package.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(database.backups().len(), 1);
+    }
+}
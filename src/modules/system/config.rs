@@ -0,0 +1,535 @@
+//! このモジュールは、`ipak`自身の設定を管理します。
+//!
+//! 設定は次の順に読み込まれ、後のものほど優先されます。
+//!
+//! 1. 組み込みの既定値
+//! 2. グローバルな設定ファイル（`/etc/ipak/config.toml`）
+//! 3. ローカルな設定ファイル（`~/.ipak/config.toml`、`XDG_CONFIG_HOME`が設定されている場合は`$XDG_CONFIG_HOME/ipak/config.toml`）
+//! 4. 環境変数（`IPAK_LOCK_TIMEOUT`のように、キーを大文字にして`.`と`-`を`_`に置き換えたもの）
+//! 5. コマンドラインの`--config KEY=VALUE`
+//!
+//! 設定ファイルはTOML形式で、`lock.timeout`のようなキーは`[lock]`テーブルの`timeout`として書きます。
+
+use crate::modules::project::ExecMode;
+use crate::modules::project::ExecShell;
+use crate::modules::project::package::PackageTarget;
+use crate::modules::system::path;
+use crate::utils::archive::ArchiveType;
+use crate::utils::args::ConfigCommands;
+use crate::utils::error::Error;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// 設定項目を表す構造体です。
+pub struct Setting {
+    /// 設定のキー（例: `lock.timeout`）。
+    pub key: &'static str,
+    /// 既定値。
+    pub default: &'static str,
+    /// 設定の説明。
+    pub description: &'static str,
+    /// 値が正しいかどうかを検証する関数。
+    validate: fn(&str) -> Result<(), String>,
+}
+
+/// `ipak`が認識するすべての設定項目です。
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "color",
+        default: "auto",
+        description: "Colour output: auto, always or never",
+        validate: |value| parse_color(value).map(|_| ()),
+    },
+    Setting {
+        key: "local.bin-dir",
+        default: "",
+        description: "Directory for local package executables (empty uses the data directory)",
+        validate: |value| {
+            if value.is_empty()
+                || value.starts_with('/')
+                || value.starts_with("~/")
+            {
+                Ok(())
+            } else {
+                Err("expected an absolute path or a path starting with ~/"
+                    .to_string())
+            }
+        },
+    },
+    Setting {
+        key: "lock.timeout",
+        default: "0",
+        description: "Seconds to wait for another ipak process (0 waits forever)",
+        validate: |value| {
+            value.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())
+        },
+    },
+    Setting {
+        key: "package.archive-type",
+        default: "zip",
+        description: "Archive format written by `project package`",
+        validate: |value| ArchiveType::from_str(value).map(|_| ()),
+    },
+    Setting {
+        key: "package.target",
+        default: "normal",
+        description: "Default target of `project package`",
+        validate: |value| PackageTarget::from_str(value).map(|_| ()),
+    },
+    Setting {
+        key: "shell",
+        default: "bash",
+        description: "Shell used to run project and package scripts",
+        validate: |value| ExecShell::from_str(value).map(|_| ()),
+    },
+];
+
+/// 設定値がどこから来たかを表す列挙型です。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    /// 組み込みの既定値。
+    Default,
+    /// グローバルな設定ファイル。
+    Global,
+    /// ローカルな設定ファイル。
+    Local,
+    /// 環境変数。
+    Env,
+    /// コマンドラインの`--config`。
+    CommandLine,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::Global => write!(f, "global"),
+            Self::Local => write!(f, "local"),
+            Self::Env => write!(f, "env"),
+            Self::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// すべての層を重ね合わせた設定です。
+pub struct Config {
+    values: BTreeMap<&'static str, (String, Source)>,
+    warnings: Vec<String>,
+}
+
+/// 読み込まれた設定です。最初に参照されたときに確定します。
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// 設定を読み込み、コマンドラインの上書きを適用します。
+///
+/// 設定が参照される前に、一度だけ呼び出す必要があります。
+///
+/// # Arguments
+/// * `overrides` - コマンドラインで指定された`KEY=VALUE`のリスト。
+///
+/// # Returns
+/// `Ok(())` 読み込みに成功した場合。
+/// `Err(Error)` `KEY=VALUE`の形式やキー、値が正しくない場合。
+pub fn init(overrides: &[String]) -> Result<(), Error> {
+    let mut config = Config::load();
+    for assignment in overrides {
+        let (key, value) =
+            assignment.split_once('=').ok_or_else(|| {
+                Error::from(format!(
+                    "Invalid --config '{}': expected KEY=VALUE",
+                    assignment
+                ))
+            })?;
+        config.set(key.trim(), value.trim(), Source::CommandLine)?;
+    }
+    if CONFIG.set(config).is_err() {
+        log::debug!("Configuration was already loaded");
+    }
+    Ok(())
+}
+
+/// 現在の設定を返します。
+///
+/// `init`が呼ばれていない場合は、コマンドラインの上書きなしで読み込みます。
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::load)
+}
+
+impl Config {
+    /// 既定値、設定ファイル、環境変数から設定を読み込みます。
+    ///
+    /// 読み込めない設定ファイルや正しくない値は無視し、警告として記録します。
+    fn load() -> Self {
+        let mut config = Self {
+            values: SETTINGS
+                .iter()
+                .map(|setting| {
+                    (
+                        setting.key,
+                        (setting.default.to_string(), Source::Default),
+                    )
+                })
+                .collect(),
+            warnings: Vec::new(),
+        };
+        for (file, source) in [
+            (path::global::config_filepath(), Source::Global),
+            (path::local::config_filepath(), Source::Local),
+        ] {
+            let values = match read_file(&file) {
+                Ok(values) => values,
+                Err(e) => {
+                    config.warnings.push(format!(
+                        "Ignoring {}: {}",
+                        file.display(),
+                        e
+                    ));
+                    continue;
+                }
+            };
+            for (key, value) in values {
+                if let Err(e) = config.set(&key, &value, source) {
+                    config.warnings.push(format!(
+                        "Ignoring {}: {}",
+                        file.display(),
+                        e
+                    ));
+                }
+            }
+        }
+        for setting in SETTINGS {
+            if let Ok(value) = std::env::var(env_name(setting.key))
+                && let Err(e) =
+                    config.set(setting.key, &value, Source::Env)
+            {
+                config.warnings.push(format!(
+                    "Ignoring {}: {}",
+                    env_name(setting.key),
+                    e
+                ));
+            }
+        }
+        config
+    }
+
+    /// 設定値を検証してから上書きします。
+    fn set(
+        &mut self,
+        key: &str,
+        value: &str,
+        source: Source,
+    ) -> Result<(), Error> {
+        let setting = find_setting(key)?;
+        (setting.validate)(value).map_err(|e| {
+            Error::from(format!(
+                "Invalid value '{}' for {}: {}",
+                value, key, e
+            ))
+        })?;
+        self.values.insert(setting.key, (value.to_string(), source));
+        Ok(())
+    }
+
+    /// 設定値とその出どころを返します。
+    ///
+    /// # Arguments
+    /// * `key` - 設定のキー。
+    pub fn value(&self, key: &str) -> Option<(&str, Source)> {
+        self.values
+            .get(key)
+            .map(|(value, source)| (value.as_str(), *source))
+    }
+
+    /// 読み込み時に無視された設定についての警告を返します。
+    ///
+    /// 設定はロガーの初期化前に読み込まれるため、警告は呼び出し側で表示します。
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// 設定値を文字列で返します。
+    fn raw(&self, key: &str) -> &str {
+        self.value(key).map_or("", |(value, _)| value)
+    }
+
+    /// スクリプトの実行に使用するシェルを返します。
+    pub fn shell(&self) -> ExecShell {
+        ExecShell::from_str(self.raw("shell")).unwrap_or(ExecShell::Bash)
+    }
+
+    /// `project package`の既定のターゲットを返します。
+    pub fn package_target(&self) -> PackageTarget {
+        PackageTarget::from_str(self.raw("package.target"))
+            .unwrap_or(PackageTarget::Normal)
+    }
+
+    /// `project package`が作成するアーカイブの形式を返します。
+    pub fn package_archive_type(&self) -> ArchiveType {
+        ArchiveType::from_str(self.raw("package.archive-type"))
+            .unwrap_or(ArchiveType::Zip)
+    }
+
+    /// ローカルパッケージの実行ファイルを置くディレクトリを返します。
+    ///
+    /// 指定されていない場合は`None`を返します。
+    pub fn local_bin_dir(&self) -> Option<&str> {
+        Some(self.raw("local.bin-dir")).filter(|dir| !dir.is_empty())
+    }
+
+    /// ロックの待機時間の上限を返します。`None`の場合は無期限に待機します。
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        match self.raw("lock.timeout").parse::<u64>() {
+            Ok(0) | Err(_) => None,
+            Ok(secs) => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// 色付きの出力を使用するかどうかを返します。
+    ///
+    /// `auto`の場合は、標準エラー出力が端末で、`NO_COLOR`が設定されていないときに色を付けます。
+    pub fn color(&self) -> bool {
+        parse_color(self.raw("color")).unwrap_or(None).unwrap_or_else(
+            || {
+                std::env::var_os("NO_COLOR").is_none()
+                    && io::stderr().is_terminal()
+            },
+        )
+    }
+}
+
+/// 設定関連のコマンドを処理します。
+///
+/// # Arguments
+/// * `args` - 処理する設定コマンド。
+///
+/// # Returns
+/// `Ok(())` 成功した場合。
+/// `Err(Error)` エラーが発生した場合。
+pub fn config(args: ConfigCommands) -> Result<(), Error> {
+    match args {
+        ConfigCommands::Get { key } => {
+            find_setting(&key)?;
+            if let Some((value, _)) = get().value(&key) {
+                log::info!("{}", value);
+            }
+            Ok(())
+        }
+        ConfigCommands::Set { key, value, local, global } => {
+            set(&key, &value, (local, global).into())
+        }
+        ConfigCommands::List => {
+            for setting in SETTINGS {
+                if let Some((value, source)) = get().value(setting.key) {
+                    log::info!(
+                        "{} = {} ({}) - {}",
+                        setting.key,
+                        value,
+                        source,
+                        setting.description
+                    );
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// 設定ファイルに値を書き込みます。
+///
+/// # Arguments
+/// * `key` - 設定のキー。
+/// * `value` - 設定する値。
+/// * `mode` - 書き込む設定ファイル（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 書き込みに成功した場合。
+/// `Err(Error)` キーや値が正しくない、または設定ファイルの読み書きに失敗した場合。
+fn set(key: &str, value: &str, mode: ExecMode) -> Result<(), Error> {
+    let setting = find_setting(key)?;
+    (setting.validate)(value).map_err(|e| {
+        Error::from(format!(
+            "Invalid value '{}' for {}: {}",
+            value, key, e
+        ))
+    })?;
+    let file = match mode {
+        ExecMode::Local => path::local::config_filepath(),
+        ExecMode::Global => path::global::config_filepath(),
+    };
+    let mut table = match fs::read_to_string(&file) {
+        Ok(content) => content.parse::<toml::Table>().map_err(|e| {
+            Error::from(format!(
+                "Failed to parse {}: {}",
+                file.display(),
+                e
+            ))
+        })?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            toml::Table::new()
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut parts = setting.key.split('.').collect::<Vec<_>>();
+    let name = parts.pop().unwrap_or(setting.key);
+    let mut current = &mut table;
+    for part in parts {
+        current = current
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| {
+                Error::from(format!(
+                    "'{}' in {} is not a table",
+                    part,
+                    file.display()
+                ))
+            })?;
+    }
+    let toml_value = match value.parse::<i64>() {
+        Ok(number) => toml::Value::Integer(number),
+        Err(_) => toml::Value::String(value.to_string()),
+    };
+    current.insert(name.to_string(), toml_value);
+
+    let content = toml::to_string(&table).map_err(|e| {
+        Error::from(format!(
+            "Failed to serialize {}: {}",
+            file.display(),
+            e
+        ))
+    })?;
+    write_file(&file, &content)?;
+    log::warn!("Set {} = {} in {}", setting.key, value, file.display());
+    Ok(())
+}
+
+/// キーに対応する設定項目を探します。
+fn find_setting(key: &str) -> Result<&'static Setting, Error> {
+    SETTINGS.iter().find(|setting| setting.key == key).ok_or_else(|| {
+        Error::from(format!(
+            "Unknown config key '{}'. Known keys: {}",
+            key,
+            SETTINGS
+                .iter()
+                .map(|setting| setting.key)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    })
+}
+
+/// 設定のキーに対応する環境変数の名前を返します（例: `lock.timeout` → `IPAK_LOCK_TIMEOUT`）。
+fn env_name(key: &str) -> String {
+    format!("IPAK_{}", key.to_ascii_uppercase().replace(['.', '-'], "_"))
+}
+
+/// `color`の値をパースします。`auto`の場合は`None`を返します。
+fn parse_color(value: &str) -> Result<Option<bool>, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "auto" => Ok(None),
+        "always" => Ok(Some(true)),
+        "never" => Ok(Some(false)),
+        _ => Err("expected auto, always or never".to_string()),
+    }
+}
+
+/// 設定ファイルを読み込み、`lock.timeout`のようなキーと値の組に平坦化します。
+///
+/// ファイルが存在しない場合は空のリストを返します。
+fn read_file(file: &Path) -> Result<Vec<(String, String)>, String> {
+    let content = match fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    let table =
+        content.parse::<toml::Table>().map_err(|e| e.to_string())?;
+    let mut values = Vec::new();
+    flatten("", &table, &mut values);
+    Ok(values)
+}
+
+/// TOMLのテーブルを`.`区切りのキーと値の組に平坦化します。
+fn flatten(
+    prefix: &str,
+    table: &toml::Table,
+    values: &mut Vec<(String, String)>,
+) {
+    for (name, value) in table {
+        let key = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, values),
+            toml::Value::String(value) => {
+                values.push((key, value.clone()))
+            }
+            value => values.push((key, value.to_string())),
+        }
+    }
+}
+
+/// 設定ファイルを一時ファイル経由で書き込みます。
+fn write_file(file: &Path, content: &str) -> Result<(), io::Error> {
+    let parent_dir = file.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent_dir)?;
+    let mut temp_file = tempfile::Builder::new()
+        .prefix(".ipak-")
+        .suffix(".tmp")
+        .tempfile_in(parent_dir)?;
+    temp_file.write_all(content.as_bytes())?;
+    // Unixでは一時ファイルは所有者のみ読み取り可能なため、他のユーザーも設定を読めるようにする
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        temp_file
+            .as_file()
+            .set_permissions(fs::Permissions::from_mode(0o644))?;
+    }
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(file).map_err(|e| e.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_file_is_flattened_and_validated() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config.toml");
+        fs::write(
+            &file,
+            "shell = \"zsh\"\n[lock]\ntimeout = 30\n[package]\ntarget = \"bogus\"\n",
+        )
+        .unwrap();
+        let values = read_file(&file).unwrap();
+        assert!(values.contains(&("lock.timeout".into(), "30".into())));
+
+        let mut config =
+            Config { values: BTreeMap::new(), warnings: Vec::new() };
+        for (key, value) in &values {
+            let _ = config.set(key, value, Source::Local);
+        }
+        assert_eq!(config.value("shell"), Some(("zsh", Source::Local)));
+        assert_eq!(config.lock_timeout(), Some(Duration::from_secs(30)));
+        assert!(config.value("package.target").is_none());
+        assert!(config.set("no.such.key", "1", Source::Env).is_err());
+        assert_eq!(
+            env_name("package.archive-type"),
+            "IPAK_PACKAGE_ARCHIVE_TYPE"
+        );
+    }
+}
@@ -1,36 +1,40 @@
 //! このモジュールは、グローバルシステムにおける`ipak`の初期設定とファイル構造のセットアップを管理します。
 //! システムのルートディレクトリ内に必要なディレクトリと設定ファイルを生成します。
 
+use crate::modules::pkg::lock::LockManager;
+use crate::modules::system::path;
 use crate::utils::files::file_creation;
-use std::io::{Error, ErrorKind};
-use std::path::Path;
+use std::io::Error;
 
 /// グローバルシステムに`ipak`の初期設定を行います。
 ///
-/// `/etc/ipak/`ディレクトリ内に`README.md`ファイルを作成します。
+/// `/etc/ipak/`ディレクトリ（ルートディレクトリが変更されている場合はその下）内に`README.md`ファイルと設定ファイル`config.toml`を作成し、
+/// 状態を置く`/var/lib/ipak`とログを置く`/var/log/ipak`を作成します。
+/// 以前のバージョンが`/etc/ipak`に保存したパッケージなどの状態は、作成の前に`/var/lib/ipak`へ移動されます。
 /// 既存のファイルやディレクトリがある場合はスキップされます。
 ///
 /// # Returns
 /// `Ok(())` 成功した場合。
 /// `Err(Error)` ファイル操作に失敗した場合。
 pub fn configure() -> Result<(), Error> {
-    let configure_list =
-        [["/etc/ipak/README.md", include_str!("data/global/README.md")]];
+    let configure_list = [
+        ["README.md", include_str!("data/global/README.md")],
+        ["config.toml", include_str!("data/config.toml")],
+    ];
+    LockManager::new(true).migrate()?;
+    for dir in [path::global::state_dirpath(), path::global::log_dirpath()]
+    {
+        std::fs::create_dir_all(&dir)?;
+    }
+
+    let ipak_dir = path::global::config_dirpath();
     for configure_data in configure_list {
-        let creation_result = file_creation(
-            Path::new(configure_data[0]).to_str().unwrap(),
-            configure_data[1],
-        );
-        match creation_result {
-            Ok(()) => {
-                continue;
-            }
-            Err(e) => {
-                if e.kind() != ErrorKind::AlreadyExists {
-                    return Err(e);
-                }
-            }
+        let file_path = ipak_dir.join(configure_data[0]);
+        // file_creationは既存のファイルを上書きするため、設定を消さないようにスキップする
+        if file_path.symlink_metadata().is_ok() {
+            continue;
         }
+        file_creation(file_path.to_str().unwrap(), configure_data[1])?;
     }
     Ok(())
 }
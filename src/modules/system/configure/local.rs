@@ -2,69 +2,74 @@
 //! ユーザーのホームディレクトリ内に必要なディレクトリと設定ファイルを生成します。
 
 use crate::modules::pkg::list;
+use crate::modules::pkg::lock::LockManager;
+use crate::modules::system::path;
 use crate::utils::files::file_creation;
 use serde_yaml;
-use std::env;
-use std::io::{Error, ErrorKind};
-use std::path::Path;
+use std::io::Error;
 
 /// ローカルシステムに`ipak`の初期設定を行います。
 ///
-/// ユーザーのホームディレクトリ内に`.ipak`ディレクトリ、`bin`ディレクトリ、
-/// `packages`ディレクトリ、`list.yaml`ファイル、`README.md`ファイル、
-/// およびキャッシュディレクトリ`.cache/ipak`を作成します。
+/// データディレクトリ（`~/.ipak`、または`$XDG_DATA_HOME/ipak`）内に`bin`ディレクトリ、
+/// `packages`ディレクトリ、インデックスファイル`index.yaml`、`README.md`ファイルを、
+/// 設定ディレクトリに設定ファイル`config.toml`を、状態ディレクトリにタスクファイルを作成し、
+/// キャッシュディレクトリ（`~/.cache/ipak`、または`$XDG_CACHE_HOME/ipak`）を作成します。
+/// 実行ファイルのディレクトリが`~/.ipak/bin`でない場合は、`~/.ipak/bin`からのシンボリックリンクも作成します。
+/// `~/.ipak`に残っているデータは、作成の前に新しい場所へ移動されます。
 /// 既存のファイルやディレクトリがある場合はスキップされます。
 ///
 /// # Returns
 /// `Ok(())` 成功した場合。
-/// `Err(Error)` ファイル操作または環境変数の取得に失敗した場合。
+/// `Err(Error)` ファイル操作に失敗した場合。
 pub fn configure() -> Result<(), Error> {
-    let package_list_data = {
-        let package_list_data =
-            serde_yaml::to_string(&list::PackageListData::default());
-        package_list_data.unwrap()
+    LockManager::new(false).migrate()?;
+    let package_index = {
+        let package_index =
+            serde_yaml::to_string(&list::PackageIndex::default());
+        package_index.unwrap()
     };
-    let configure_list = [
-        [".ipak/README.md", include_str!("data/local/README.md")],
-        [".ipak/bin/ipak-local", include_str!("data/local/ipak-local")],
-        [".ipak/packages/list.yaml", package_list_data.as_str()],
-        [".ipak/lock", ""],
-        [".ipak/tasks", ""],
+    let bin_dir = path::local::bin_dirpath();
+    let mut configure_list = vec![
+        (
+            path::local::data_dirpath().join("README.md"),
+            include_str!("data/local/README.md"),
+        ),
+        (path::local::config_filepath(), include_str!("data/config.toml")),
+        (
+            bin_dir.join("ipak-local"),
+            include_str!("data/local/ipak-local"),
+        ),
+        (path::local::tasks_filepath(), ""),
     ];
-    let home_dir = env::var("HOME").map_err(|e| {
-        Error::new(
-            ErrorKind::NotFound,
-            format!("HOME environment variable not found: {}", e),
-        )
-    })?;
-    let home_dir = Path::new(&home_dir);
+    // 以前の形式のパッケージリストがある場合は、最初の書き込み時に移行されるため空のインデックスを作成しない
+    if !path::local::packageslist_filepath().exists() {
+        configure_list.push((
+            path::local::packages_dirpath().join("index.yaml"),
+            package_index.as_str(),
+        ));
+    }
 
-    let ipak_bin_dir = home_dir.join(".ipak/bin");
-    std::fs::create_dir_all(&ipak_bin_dir).map_err(|e| {
+    std::fs::create_dir_all(&bin_dir).map_err(|e| {
         Error::other(format!(
-            "Failed to create .ipak/bin directory: {}",
+            "Failed to create {} directory: {}",
+            bin_dir.display(),
             e
         ))
     })?;
+    path::local::link_legacy_bin_dir()?;
 
-    for configure_data in configure_list {
-        let creation_result = file_creation(
-            home_dir.join(configure_data[0]).to_str().unwrap(),
-            configure_data[1],
-        );
-        match creation_result {
-            Ok(()) => continue,
-            Err(e) => {
-                if e.kind() != ErrorKind::AlreadyExists {
-                    return Err(e);
-                }
-            }
+    for (file_path, content) in configure_list {
+        // file_creationは既存のファイルを上書きするため、設定やジャーナルを消さないようにスキップする
+        if file_path.symlink_metadata().is_ok() {
+            continue;
         }
+        file_creation(file_path.to_str().unwrap(), content)?;
     }
-    let ipak_cache_dir = home_dir.join(".cache/ipak");
+    let ipak_cache_dir = path::local::cache_path();
     std::fs::create_dir_all(&ipak_cache_dir).map_err(|e| {
         Error::other(format!(
-            "Failed to create .cache/ipak directory: {}",
+            "Failed to create {} directory: {}",
+            ipak_cache_dir.display(),
             e
         ))
     })?;
@@ -0,0 +1,88 @@
+//! このモジュールは、インストール済みパッケージのデータベースを管理します。
+//! データベースのスナップショットの一覧表示と、スナップショットからの復元を提供します。
+
+use crate::modules::pkg::list;
+use crate::modules::pkg::lock::LockManager;
+use crate::modules::project::ExecMode;
+use crate::utils::args::DbCommands;
+use crate::utils::error::Error;
+
+/// データベース関連のコマンドを処理します。
+///
+/// # Arguments
+/// * `args` - 処理するデータベースコマンド。
+///
+/// # Returns
+/// `Ok(())` 成功した場合。
+/// `Err(Error)` エラーが発生した場合。
+pub fn db(args: DbCommands) -> Result<(), Error> {
+    match args {
+        DbCommands::List { local, global } => {
+            list_backups((local, global).into())
+        }
+        DbCommands::Restore { generation, local, global } => {
+            restore(generation, (local, global).into())
+        }
+    }
+}
+
+/// パッケージリストのバックアップを一覧表示します。
+///
+/// # Arguments
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 表示に成功した場合。
+/// `Err(Error)` ロックの取得に失敗した場合。
+fn list_backups(mode: ExecMode) -> Result<(), Error> {
+    let lock_manager = LockManager::new(matches!(mode, ExecMode::Global));
+    let _lock = lock_manager.acquire_shared_lock()?;
+    let backups = list::database(mode).backups();
+    if backups.is_empty() {
+        log::info!("No backups of the {} packages list.", mode);
+        return Ok(());
+    }
+    for backup in backups {
+        match backup.data {
+            Ok(data) => log::info!(
+                "{}: {} ({} package(s)) {}",
+                backup.generation,
+                data.last_modified.format("%Y-%m-%d %H:%M:%S"),
+                data.installed_packages.len(),
+                backup.path.display()
+            ),
+            Err(e) => log::info!(
+                "{}: unreadable ({}) {}",
+                backup.generation,
+                e,
+                backup.path.display()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// パッケージリストを指定された世代のバックアップから復元します。
+///
+/// 復元されるのはパッケージリストのみで、パッケージのファイルは変更されません。
+///
+/// # Arguments
+/// * `generation` - 復元するバックアップの世代（`1`が最新）。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 復元に成功した場合。
+/// `Err(Error)` バックアップが存在しない、読み込めない、または書き込みに失敗した場合。
+fn restore(generation: usize, mode: ExecMode) -> Result<(), Error> {
+    let lock_manager = LockManager::new(matches!(mode, ExecMode::Global));
+    let _lock = lock_manager.acquire_lock()?;
+    let data = list::database(mode).restore_backup(generation)?;
+    log::warn!(
+        "Restored the {} packages list from backup {} ({}, {} package(s))",
+        mode,
+        generation,
+        data.last_modified.format("%Y-%m-%d %H:%M:%S"),
+        data.installed_packages.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,392 @@
+//! このモジュールは、シェルの環境を設定するスニペットを生成します。
+//!
+//! `ipak system env`は、ローカルパッケージの実行ファイルのディレクトリと、
+//! インストール済みパッケージが`project.yaml`の`env`で宣言した環境変数を、
+//! シェルの設定ファイルで`eval`できる形式で出力します。
+//! 宣言はパッケージの記録と一緒にパッケージリストに保存されるため、
+//! パッケージを削除すると出力からも取り除かれます。
+
+use crate::modules::pkg::list::{self, InstalledPackageData};
+use crate::modules::pkg::lock::LockManager;
+use crate::modules::project::ExecMode;
+use crate::modules::system::path;
+use crate::utils::error::Error;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// 空の場合に既定値を使うため、末尾に`:`を残す必要がある環境変数です。
+///
+/// 例えば`MANPATH`が`dir`だけになると、`man`はシステムのマニュアルを探さなくなります。
+const KEEP_DEFAULT_VARS: &[&str] = &["MANPATH", "INFOPATH"];
+
+/// スニペットを出力するシェルの種類を表す列挙型です。
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum EnvShell {
+    /// Bash。
+    Bash,
+    /// Zsh。
+    Zsh,
+    /// Fish。
+    Fish,
+}
+
+impl EnvShell {
+    /// 環境変数`SHELL`から使用中のシェルを推測します。
+    ///
+    /// 推測できない場合は`Bash`を返します。
+    fn detect() -> Self {
+        let shell = env::var_os("SHELL").map(PathBuf::from);
+        match shell
+            .as_deref()
+            .and_then(Path::file_name)
+            .and_then(|name| name.to_str())
+        {
+            Some("zsh") => Self::Zsh,
+            Some("fish") => Self::Fish,
+            _ => Self::Bash,
+        }
+    }
+}
+
+/// シェルの環境に加える変更を表す構造体です。
+#[derive(Debug, Default)]
+struct Environment {
+    /// 環境変数ごとに先頭へ追加するディレクトリ（優先度の高い順）。
+    prepend: BTreeMap<String, Vec<String>>,
+    /// 設定する環境変数の値。
+    set: BTreeMap<String, String>,
+}
+
+impl Environment {
+    /// 環境変数の先頭に追加するディレクトリを登録します。
+    ///
+    /// 既に登録されているディレクトリは、優先度の高い方だけが残ります。
+    fn prepend(&mut self, name: &str, dir: String) {
+        let dirs = self.prepend.entry(name.to_string()).or_default();
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+
+    /// パッケージが宣言した環境変数を追加します。
+    ///
+    /// 先に追加されたパッケージの宣言ほど優先されます。
+    /// 名前が環境変数として正しくない宣言と、`resolve_dir`で解決できないディレクトリは、警告を表示して無視します。
+    ///
+    /// # Arguments
+    /// * `package` - インストール済みのパッケージ。
+    /// * `package_dir` - パッケージがインストールされているディレクトリ。
+    /// * `mode` - パッケージがインストールされているモード。
+    fn add_package(
+        &mut self,
+        package: &InstalledPackageData,
+        package_dir: &Path,
+        mode: ExecMode,
+    ) {
+        let env = &package.info.env;
+        let package_name = &package.info.about.package.name;
+        for (name, dirs) in &env.prepend {
+            if !is_valid_name(name) {
+                log::warn!(
+                    "Ignoring invalid environment variable '{}' from {}",
+                    name,
+                    package_name
+                );
+                continue;
+            }
+            for dir in dirs {
+                match resolve_dir(dir, package_dir, mode) {
+                    Ok(resolved) => self.prepend(name, resolved),
+                    Err(reason) => log::warn!(
+                        "Ignoring directory '{}' for {} from {}: {}",
+                        dir,
+                        name,
+                        package_name,
+                        reason
+                    ),
+                }
+            }
+        }
+        for (name, value) in &env.set {
+            if !is_valid_name(name) {
+                log::warn!(
+                    "Ignoring invalid environment variable '{}' from {}",
+                    name,
+                    package_name
+                );
+                continue;
+            }
+            self.set.entry(name.clone()).or_insert_with(|| value.clone());
+        }
+    }
+
+    /// 指定されたシェルで評価できるスニペットを生成します。
+    ///
+    /// ディレクトリは既に含まれている場合は追加されないため、何度評価しても重複しません。
+    fn render(&self, shell: EnvShell) -> String {
+        let mut snippet = String::new();
+        for (name, dirs) in &self.prepend {
+            let keep_default = KEEP_DEFAULT_VARS.contains(&name.as_str());
+            if shell == EnvShell::Fish && keep_default {
+                let _ = writeln!(
+                    snippet,
+                    "set -q {name}; or set -gx --path {name} ''"
+                );
+            }
+            // 先頭に追加していくため、優先度の低いものから出力する
+            for dir in dirs.iter().rev() {
+                let _ = match shell {
+                    EnvShell::Bash | EnvShell::Zsh => {
+                        let rest = if keep_default {
+                            format!(":${{{name}}}")
+                        } else {
+                            format!("${{{name}:+:${{{name}}}}}")
+                        };
+                        writeln!(
+                            snippet,
+                            "case \":${{{name}}}:\" in *:{dir}:*) ;; *) export {name}={dir}\"{rest}\" ;; esac",
+                            dir = quote_sh(dir),
+                        )
+                    }
+                    EnvShell::Fish => writeln!(
+                        snippet,
+                        "contains -- {dir} ${name}; or set -gx --path {name} {dir} ${name}",
+                        dir = quote_fish(dir),
+                    ),
+                };
+            }
+        }
+        for (name, value) in &self.set {
+            let _ = match shell {
+                EnvShell::Bash | EnvShell::Zsh => {
+                    writeln!(
+                        snippet,
+                        "export {}={}",
+                        name,
+                        quote_sh(value)
+                    )
+                }
+                EnvShell::Fish => {
+                    writeln!(
+                        snippet,
+                        "set -gx {} {}",
+                        name,
+                        quote_fish(value)
+                    )
+                }
+            };
+        }
+        snippet
+    }
+}
+
+/// シェルの環境を設定するスニペットを標準出力に出力します。
+///
+/// ローカルモードでは、ローカルパッケージの実行ファイルのディレクトリ、ローカルパッケージ、
+/// グローバルパッケージの順に優先されます。グローバルモードではグローバルパッケージのみを含めます。
+///
+/// # Arguments
+/// * `shell` - スニペットの形式。`None`の場合は環境変数`SHELL`から推測します。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 出力に成功した場合。
+/// `Err(Error)` パッケージリストの読み込みに失敗した場合。
+pub fn env(shell: Option<EnvShell>, mode: ExecMode) -> Result<(), Error> {
+    let mut environment = Environment::default();
+    if let ExecMode::Local = mode {
+        environment.prepend(
+            "PATH",
+            path::local::bin_dirpath().display().to_string(),
+        );
+        for package in installed_packages(ExecMode::Local)? {
+            let package_dir = path::local::packages_dirpath()
+                .join(&package.info.about.package.name);
+            environment.add_package(
+                &package,
+                &package_dir,
+                ExecMode::Local,
+            );
+        }
+    }
+    // ローカルモードでは、グローバルパッケージを読み込めなくても続行する
+    match installed_packages(ExecMode::Global) {
+        Ok(packages) => {
+            for package in packages {
+                let package_dir = path::global::packages_dirpath()
+                    .join(&package.info.about.package.name);
+                environment.add_package(
+                    &package,
+                    &package_dir,
+                    ExecMode::Global,
+                );
+            }
+        }
+        Err(e) if matches!(mode, ExecMode::Local) => {
+            log::warn!("Skipping global packages: {}", e)
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    print!(
+        "{}",
+        environment.render(shell.unwrap_or_else(EnvShell::detect))
+    );
+    Ok(())
+}
+
+/// 指定されたモードのインストール済みパッケージを、共有ロックを取得して読み込みます。
+fn installed_packages(
+    mode: ExecMode,
+) -> Result<Vec<InstalledPackageData>, io::Error> {
+    let lock_manager = LockManager::new(matches!(mode, ExecMode::Global));
+    let _lock = lock_manager.acquire_shared_lock()?;
+    Ok(list::database(mode).load_all()?.installed_packages)
+}
+
+/// パッケージが宣言したディレクトリを絶対パスに変換します。
+///
+/// 相対パスはパッケージのディレクトリからのパスとして扱い、絶対パスはそのまま使います。
+/// `~/`で始まる場合はホームディレクトリからのパスとして扱いますが、
+/// グローバルパッケージでは`ipak system env`を実行した利用者のホームディレクトリになってしまうため、ローカルパッケージに限ります。
+/// 基準のディレクトリの外に出られないように、`..`を含むパスは拒否します。
+///
+/// # Returns
+/// `Ok(String)` 解決されたディレクトリ。
+/// `Err(&str)` ディレクトリを使えない理由。
+fn resolve_dir(
+    dir: &str,
+    package_dir: &Path,
+    mode: ExecMode,
+) -> Result<String, &'static str> {
+    if Path::new(dir).components().any(|c| c == Component::ParentDir) {
+        return Err("`..` is not allowed");
+    }
+    let resolved = match dir.strip_prefix("~/") {
+        Some(_) if mode == ExecMode::Global => {
+            return Err("`~/` is only allowed for local packages");
+        }
+        Some(relative) if Path::new(relative).is_absolute() => {
+            return Err("`~/` must be followed by a relative path");
+        }
+        Some(relative) => path::local::home_path().join(relative),
+        None => package_dir.join(dir),
+    };
+    Ok(resolved.display().to_string())
+}
+
+/// 環境変数の名前として正しいかどうかを返します。
+///
+/// スニペットは`eval`されるため、名前は英数字と`_`のみに制限します。
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Bash、Zsh用に文字列を単一引用符で囲みます。
+fn quote_sh(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Fish用に文字列を単一引用符で囲みます。
+fn quote_fish(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_quotes_values_and_skips_invalid_names() {
+        let mut package = InstalledPackageData::default();
+        package.info.about.package.name = "tool".to_string();
+        package
+            .info
+            .env
+            .prepend
+            .insert("PATH".to_string(), vec!["bin".to_string()]);
+        package
+            .info
+            .env
+            .prepend
+            .insert("MANPATH".to_string(), vec!["/opt/man".to_string()]);
+        package
+            .info
+            .env
+            .set
+            .insert("TOOL_HOME".to_string(), "it's".to_string());
+        package
+            .info
+            .env
+            .set
+            .insert("$(rm -rf ~)".to_string(), "x".to_string());
+
+        let mut environment = Environment::default();
+        environment.prepend("PATH", "/home/u/.ipak/bin".to_string());
+        environment.add_package(
+            &package,
+            Path::new("/pkgs/tool"),
+            ExecMode::Global,
+        );
+        assert_eq!(
+            environment.prepend["PATH"],
+            vec!["/home/u/.ipak/bin", "/pkgs/tool/bin"]
+        );
+
+        let bash = environment.render(EnvShell::Bash);
+        assert!(!bash.contains("rm -rf"));
+        assert!(bash.contains("export TOOL_HOME='it'\\''s'"));
+        assert!(bash.contains("export MANPATH='/opt/man'\":${MANPATH}\""));
+        let pkg_bin = bash.find("'/pkgs/tool/bin'").unwrap();
+        let ipak_bin = bash.find("'/home/u/.ipak/bin'").unwrap();
+        assert!(pkg_bin < ipak_bin);
+
+        let fish = environment.render(EnvShell::Fish);
+        assert!(fish.contains("set -gx TOOL_HOME 'it\\'s'"));
+        assert!(
+            fish.contains("set -q MANPATH; or set -gx --path MANPATH ''")
+        );
+    }
+
+    #[test]
+    fn test_resolve_dir_stays_inside_package_and_home() {
+        let package_dir = Path::new("/pkgs/tool");
+        assert_eq!(
+            resolve_dir("bin", package_dir, ExecMode::Global).unwrap(),
+            "/pkgs/tool/bin"
+        );
+        assert_eq!(
+            resolve_dir("/opt/man", package_dir, ExecMode::Global)
+                .unwrap(),
+            "/opt/man"
+        );
+        assert!(
+            resolve_dir("../other", package_dir, ExecMode::Local).is_err()
+        );
+        assert!(
+            resolve_dir("lib/../../x", package_dir, ExecMode::Local)
+                .is_err()
+        );
+        assert!(
+            resolve_dir("~/.local/bin", package_dir, ExecMode::Global)
+                .is_err()
+        );
+        assert!(
+            resolve_dir("~/../root", package_dir, ExecMode::Local)
+                .is_err()
+        );
+        assert!(
+            resolve_dir("~//etc", package_dir, ExecMode::Local).is_err()
+        );
+        assert!(
+            resolve_dir("~/.local/bin", package_dir, ExecMode::Local)
+                .unwrap()
+                .ends_with("/.local/bin")
+        );
+    }
+}
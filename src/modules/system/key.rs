@@ -0,0 +1,120 @@
+//! このモジュールは、パッケージの署名に使用する鍵を管理します。
+//! 鍵のペアの作成、公開鍵の信頼、信頼済みの鍵の一覧表示を提供します。
+
+use crate::modules::pkg::signature;
+use crate::modules::project::ExecMode;
+use crate::modules::system::path;
+use crate::utils::args::KeyCommands;
+use crate::utils::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// 鍵関連のコマンドを処理します。
+///
+/// # Arguments
+/// * `args` - 処理する鍵コマンド。
+///
+/// # Returns
+/// `Ok(())` 成功した場合。
+/// `Err(Error)` エラーが発生した場合。
+pub fn key(args: KeyCommands) -> Result<(), Error> {
+    match args {
+        KeyCommands::Generate { path } => generate(&path),
+        KeyCommands::Trust { public_key_file, local, global } => {
+            trust(&public_key_file, (local, global).into())
+        }
+        KeyCommands::List { local, global } => {
+            list_keys((local, global).into())
+        }
+    }
+}
+
+/// 新しい鍵のペアを作成します。
+///
+/// # Arguments
+/// * `path` - 秘密鍵を書き込むファイルのパス。
+///
+/// # Returns
+/// `Ok(())` 作成に成功した場合。
+/// `Err(Error)` ファイルが既に存在する、または書き込みに失敗した場合。
+fn generate(path: &Path) -> Result<(), Error> {
+    let public_key_path = signature::generate_key(path).map_err(|e| {
+        Error::from(format!(
+            "Failed to write key '{}': {}",
+            path.display(),
+            e
+        ))
+    })?;
+    log::warn!(
+        "Generated secret key {} and public key {}",
+        path.display(),
+        public_key_path.display()
+    );
+    Ok(())
+}
+
+/// 公開鍵を信頼済みの鍵のディレクトリにコピーします。
+///
+/// 鍵は公開鍵のファイル名（拡張子を除く）を名前として保存されます。
+///
+/// # Arguments
+/// * `public_key_file` - 信頼する公開鍵のファイル。
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 鍵を信頼した場合。
+/// `Err(Error)` 鍵の形式が正しくない、または書き込みに失敗した場合。
+fn trust(public_key_file: &Path, mode: ExecMode) -> Result<(), Error> {
+    let public_key = signature::read_verifying_key(public_key_file)?;
+    let key_name = public_key_file
+        .file_stem()
+        .ok_or_else(|| {
+            Error::from(format!(
+                "'{}' has no file name",
+                public_key_file.display()
+            ))
+        })?
+        .to_string_lossy()
+        .into_owned();
+    let keys_dir = match mode {
+        ExecMode::Local => path::local::trusted_keys_dirpath(),
+        ExecMode::Global => path::global::trusted_keys_dirpath(),
+    };
+    fs::create_dir_all(&keys_dir)?;
+    let destination = keys_dir.join(format!(
+        "{}.{}",
+        key_name,
+        signature::PUBLIC_KEY_EXTENSION
+    ));
+    fs::write(
+        &destination,
+        format!("{}\n", signature::key_to_hex(&public_key)),
+    )?;
+    log::warn!(
+        "Trusted key '{}' for {} packages ({})",
+        key_name,
+        mode,
+        destination.display()
+    );
+    Ok(())
+}
+
+/// 指定されたモードで信頼される公開鍵を一覧表示します。
+///
+/// # Arguments
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 表示に成功した場合。
+fn list_keys(mode: ExecMode) -> Result<(), Error> {
+    let keys =
+        signature::trusted_keys(&signature::trusted_keys_dirs(mode));
+    if keys.is_empty() {
+        log::info!("No keys are trusted for {} packages.", mode);
+        return Ok(());
+    }
+    for (key_name, public_key) in keys {
+        log::info!("{}: {}", key_name, signature::key_to_hex(&public_key));
+    }
+    Ok(())
+}
@@ -1,5 +1,243 @@
 //! このモジュールは、システムパスに関連する機能を提供します。
 //! グローバルおよびローカルのパス管理モジュールを再エクスポートします。
+//!
+//! すべてのパスは、`--root`オプションまたは環境変数`IPAK_ROOT`で指定されたルートディレクトリの下に配置されます。
+//! 指定がない場合、ルートディレクトリは`/`です。
 
 pub mod global;
 pub mod local;
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// ルートディレクトリを指定する環境変数の名前です。
+pub const ROOT_ENV: &str = "IPAK_ROOT";
+
+/// 設定されたルートディレクトリです。最初に参照されたときに確定します。
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// すべてのパスの基準となるルートディレクトリを設定します。
+///
+/// パスが参照される前に、一度だけ呼び出す必要があります。
+///
+/// # Arguments
+/// * `root` - ルートディレクトリ。相対パスは現在のディレクトリを基準に解決されます。
+///
+/// # Returns
+/// `Ok(())` 設定に成功した場合。
+/// `Err(io::Error)` パスを解決できない場合、または既に別のルートディレクトリが使われている場合。
+pub fn set_root(root: &Path) -> Result<(), io::Error> {
+    let root = std::path::absolute(root)?;
+    let current = ROOT.get_or_init(|| root.clone());
+    if *current != root {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "The root directory is already set to {}",
+                current.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// ルートディレクトリを返します。
+///
+/// `set_root`で設定されていない場合は環境変数`IPAK_ROOT`を、それもなければ`/`を使用します。
+///
+/// # Returns
+/// ルートディレクトリへのパス。
+pub fn root() -> &'static Path {
+    ROOT.get_or_init(|| {
+        std::env::var_os(ROOT_ENV)
+            .filter(|root| !root.is_empty())
+            .and_then(|root| std::path::absolute(root).ok())
+            .unwrap_or_else(|| PathBuf::from("/"))
+    })
+}
+
+/// 絶対パスをルートディレクトリの下のパスに変換します。
+///
+/// # Arguments
+/// * `path` - 変換するパス（例: `/etc/ipak`）。
+///
+/// # Returns
+/// ルートディレクトリの下のパス（例: ルートが`/srv/image`なら`/srv/image/etc/ipak`）。
+pub fn under_root(path: &Path) -> PathBuf {
+    root().join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// パッケージのスクリプトに渡す、ルートディレクトリを表す環境変数を返します。
+///
+/// `IPAK_ROOT`にはルートディレクトリが、`DESTDIR`にはルートディレクトリが`/`でない場合にのみそのパスが設定されます。
+///
+/// # Returns
+/// 環境変数の名前と値の組。
+pub fn script_envs() -> [(&'static str, OsString); 2] {
+    let root = root();
+    let destdir = if root == Path::new("/") {
+        OsString::new()
+    } else {
+        root.as_os_str().to_os_string()
+    };
+    [(ROOT_ENV, root.as_os_str().to_os_string()), ("DESTDIR", destdir)]
+}
+
+/// 従来のディレクトリに残っているファイルやディレクトリを、新しい場所へ移動します。
+///
+/// 移動元が存在しない、シンボリックリンクである、または移動先が既に存在する項目は移動しません。
+/// 従来のディレクトリのタスクファイルに中断された操作が残っている場合は、
+/// ジャーナル内のパスが無効になるため、何も移動せずにエラーを返します。
+///
+/// # Arguments
+/// * `legacy_dir` - 従来のディレクトリ。
+/// * `items` - `legacy_dir`内の名前と、その移動先の組のリスト。
+/// * `recover_hint` - 中断された操作が残っている場合に、回復の方法として表示する文。
+///
+/// # Returns
+/// `Ok(Vec<(PathBuf, PathBuf)>)` 移動した項目の移動元と移動先のリスト。
+/// `Err(io::Error)` 中断された操作が残っている、または移動に失敗した場合。
+fn migrate_dir(
+    legacy_dir: &Path,
+    items: &[(&str, PathBuf)],
+    recover_hint: &str,
+) -> Result<Vec<(PathBuf, PathBuf)>, io::Error> {
+    let pending = pending_moves(legacy_dir, items);
+
+    let legacy_tasks = legacy_dir.join("tasks");
+    if pending.iter().any(|(from, _)| *from == legacy_tasks)
+        && fs::metadata(&legacy_tasks)?.len() > 0
+    {
+        return Err(io::Error::other(format!(
+            "An interrupted operation is recorded in {}, so nothing was moved to the new directories. {}",
+            legacy_tasks.display(),
+            recover_hint
+        )));
+    }
+
+    for (from, to) in &pending {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        move_path(from, to)?;
+    }
+    Ok(pending)
+}
+
+/// 従来のディレクトリから移動する必要がある項目の、移動元と移動先のリストを返します。
+///
+/// 移動元が存在しない、シンボリックリンクである、または移動先が既に存在する項目は含まれません。
+fn pending_moves(
+    legacy_dir: &Path,
+    items: &[(&str, PathBuf)],
+) -> Vec<(PathBuf, PathBuf)> {
+    items
+        .iter()
+        .map(|(name, to)| (legacy_dir.join(name), to.clone()))
+        .filter(|(from, to)| {
+            from != to
+                && !from.is_symlink()
+                && from.exists()
+                && !to.exists()
+        })
+        .collect()
+}
+
+/// ファイルまたはディレクトリを移動します。
+///
+/// 移動先が別のファイルシステムにある場合は、コピーしてから削除します。
+pub fn move_path(from: &Path, to: &Path) -> Result<(), io::Error> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_path(from, to)?;
+            if from.is_dir() {
+                fs::remove_dir_all(from)
+            } else {
+                fs::remove_file(from)
+            }
+        }
+        result => result,
+    }
+}
+
+/// ファイル、シンボリックリンク、ディレクトリを再帰的にコピーします。
+///
+/// Unix以外のプラットフォームでは、シンボリックリンクの代わりにリンク先のファイルをコピーします。
+pub fn copy_path(from: &Path, to: &Path) -> Result<(), io::Error> {
+    let file_type = from.symlink_metadata()?.file_type();
+    if file_type.is_symlink() {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(fs::read_link(from)?, to)
+        }
+        #[cfg(not(unix))]
+        {
+            fs::copy(from, to).map(|_| ())
+        }
+    } else if file_type.is_dir() {
+        fs::create_dir(to)?;
+        fs::set_permissions(to, fs::metadata(from)?.permissions())?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_path_keeps_tree_and_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from");
+        fs::create_dir_all(from.join("bin")).unwrap();
+        fs::write(from.join("bin/tool"), "#!/bin/sh\n").unwrap();
+        std::os::unix::fs::symlink("bin/tool", from.join("link")).unwrap();
+
+        let to = dir.path().join("to");
+        copy_path(&from, &to).unwrap();
+        assert_eq!(
+            fs::read_to_string(to.join("bin/tool")).unwrap(),
+            "#!/bin/sh\n"
+        );
+        assert_eq!(
+            fs::read_link(to.join("link")).unwrap(),
+            Path::new("bin/tool")
+        );
+    }
+
+    #[test]
+    fn test_migrate_dir_keeps_existing_and_refuses_pending_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("etc");
+        let state = dir.path().join("var");
+        fs::create_dir_all(legacy.join("packages")).unwrap();
+        fs::write(legacy.join("lock"), "").unwrap();
+        fs::create_dir_all(&state).unwrap();
+        fs::write(state.join("lock"), "").unwrap();
+        let items = [
+            ("packages", state.join("packages")),
+            ("lock", state.join("lock")),
+            ("tasks", state.join("tasks")),
+        ];
+
+        fs::write(legacy.join("tasks"), "{}\n").unwrap();
+        assert!(migrate_dir(&legacy, &items, "Recover").is_err());
+        assert!(legacy.join("packages").is_dir());
+
+        fs::write(legacy.join("tasks"), "").unwrap();
+        let moved = migrate_dir(&legacy, &items, "Recover").unwrap();
+        assert_eq!(moved.len(), 2);
+        assert!(state.join("packages").is_dir());
+        assert!(legacy.join("lock").exists());
+    }
+}
@@ -1,26 +1,53 @@
 //! このモジュールは、グローバルシステムにおける`ipak`関連のパスを管理します。
 //! ルートディレクトリ、パッケージリストなどのパスを生成する関数を提供します。
+//!
+//! パスはFilesystem Hierarchy Standardに従って配置されます。
+//!
+//! | ディレクトリ | 内容 |
+//! | --- | --- |
+//! | `/etc/ipak` | 設定ファイル、リポジトリリスト、信頼済みの鍵 |
+//! | `/var/lib/ipak` | パッケージ、パッケージリスト、ロックファイル、タスクファイル |
+//! | `/var/log/ipak` | 操作の履歴 |
+//!
+//! 以前のバージョンは`/etc/ipak`に状態も保存していました。これは`migrate`によって移動されます。
 
+use std::io;
 use std::path::PathBuf;
 
 /// システムのルートディレクトリへのパスを返します。
 ///
+/// 通常は`/`ですが、`--root`オプションまたは`IPAK_ROOT`で変更できます。
+///
 /// # Returns
 /// システムのルートディレクトリへの`PathBuf`。
 fn root_path() -> PathBuf {
-    PathBuf::from("/")
+    super::root().to_path_buf()
 }
 
-/// グローバルな`ipak`のルートディレクトリへのパスを返します。
-///
-/// これは通常、`/etc/ipak/`ディレクトリです。
+/// グローバルな設定を置くディレクトリ（`/etc/ipak`）へのパスを返します。
 ///
 /// # Returns
-/// グローバルな`ipak`のルートディレクトリへの`PathBuf`。
-fn ipak_path() -> PathBuf {
+/// グローバルな設定ディレクトリへの`PathBuf`。
+pub fn config_dirpath() -> PathBuf {
     root_path().join("etc/ipak/")
 }
 
+/// グローバルな状態を置くディレクトリ（`/var/lib/ipak`）へのパスを返します。
+///
+/// # Returns
+/// グローバルな状態ディレクトリへの`PathBuf`。
+pub fn state_dirpath() -> PathBuf {
+    root_path().join("var/lib/ipak/")
+}
+
+/// グローバルなログを置くディレクトリ（`/var/log/ipak`）へのパスを返します。
+///
+/// # Returns
+/// グローバルなログディレクトリへの`PathBuf`。
+pub fn log_dirpath() -> PathBuf {
+    root_path().join("var/log/ipak/")
+}
+
 /// グローバルパッケージリストファイルへのパスを返します。
 ///
 /// # Returns
@@ -34,7 +61,7 @@ pub fn packageslist_filepath() -> PathBuf {
 /// # Returns
 /// グローバルパッケージディレクトリへの`PathBuf`。
 pub fn packages_dirpath() -> PathBuf {
-    ipak_path().join("packages")
+    state_dirpath().join("packages")
 }
 
 /// グローバルロックファイルへのパスを返します。
@@ -42,7 +69,31 @@ pub fn packages_dirpath() -> PathBuf {
 /// # Returns
 /// グローバルロックファイルへの`PathBuf`。
 pub fn lock_filepath() -> PathBuf {
-    ipak_path().join("lock")
+    state_dirpath().join("lock")
+}
+
+/// グローバルリポジトリリストファイルへのパスを返します。
+///
+/// # Returns
+/// グローバルリポジトリリストファイルへの`PathBuf`。
+pub fn repositories_filepath() -> PathBuf {
+    config_dirpath().join("repositories.yaml")
+}
+
+/// グローバルな設定ファイルへのパスを返します。
+///
+/// # Returns
+/// グローバルな設定ファイルへの`PathBuf`。
+pub fn config_filepath() -> PathBuf {
+    config_dirpath().join("config.toml")
+}
+
+/// グローバルな信頼済みの鍵のディレクトリへのパスを返します。
+///
+/// # Returns
+/// グローバルな信頼済みの鍵のディレクトリへの`PathBuf`。
+pub fn trusted_keys_dirpath() -> PathBuf {
+    config_dirpath().join("trusted-keys")
 }
 
 /// グローバルタスクファイルへのパスを返します。
@@ -50,5 +101,65 @@ pub fn lock_filepath() -> PathBuf {
 /// # Returns
 /// グローバルタスクファイルへの`PathBuf`。
 pub fn tasks_filepath() -> PathBuf {
-    ipak_path().join("tasks")
+    state_dirpath().join("tasks")
+}
+
+/// グローバルな操作の履歴ファイルへのパスを返します。
+///
+/// # Returns
+/// グローバルな履歴ファイルへの`PathBuf`。
+pub fn history_filepath() -> PathBuf {
+    log_dirpath().join("history.log")
+}
+
+/// グローバルパッケージのインストール先となるディレクトリのリストを返します。
+///
+/// インストールスクリプトが配置したファイルを追跡するために、
+/// これらのディレクトリのスナップショットが取得されます。
+///
+/// # Returns
+/// インストール先ディレクトリへの`PathBuf`のリスト。
+pub fn install_roots() -> Vec<PathBuf> {
+    ["usr/local/bin", "usr/local/sbin", "usr/local/lib", "usr/local/share"]
+        .iter()
+        .map(|dir| root_path().join(dir))
+        .collect()
+}
+
+/// 以前のバージョンがパッケージを置いていたディレクトリ（`/etc/ipak/packages`）へのパスを返します。
+///
+/// # Returns
+/// 以前のグローバルパッケージディレクトリへの`PathBuf`。
+pub fn legacy_packages_dirpath() -> PathBuf {
+    config_dirpath().join("packages")
+}
+
+/// `/etc/ipak`に残っている以前のバージョンの状態を、`/var/lib/ipak`へ移動する必要があるかどうかを返します。
+///
+/// 移動先が既に存在するかどうかに関わらず、`/etc/ipak`にパッケージまたはタスクファイルが残っていれば移動が必要です。
+///
+/// # Returns
+/// 移動が必要な場合は`true`。
+pub fn needs_migration() -> bool {
+    let legacy_packages = legacy_packages_dirpath();
+    (legacy_packages.is_dir() && !legacy_packages.is_symlink())
+        || config_dirpath().join("tasks").is_file()
+}
+
+/// `/etc/ipak`に残っているパッケージとタスクファイルを`/var/lib/ipak`へ移動します。
+///
+/// 移動先に既にパッケージディレクトリがある場合、パッケージは移動されずに残ります。
+/// その場合は、呼び出し元がデータベース単位で統合します。
+/// ロックファイルは移動中に保持されるため、移動も削除もしません。
+/// 他のプロセスと同時に移動しないように、`LockManager::migrate`から呼び出されます。
+///
+/// # Returns
+/// `Ok(Vec<(PathBuf, PathBuf)>)` 移動した項目の移動元と移動先のリスト。
+/// `Err(io::Error)` 中断された操作が残っている、または移動に失敗した場合。
+pub fn migrate() -> Result<Vec<(PathBuf, PathBuf)>, io::Error> {
+    super::migrate_dir(
+        &config_dirpath(),
+        &[("packages", packages_dirpath()), ("tasks", tasks_filepath())],
+        "Run `ipak system recover --global` to finish it.",
+    )
 }
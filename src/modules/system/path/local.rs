@@ -1,35 +1,106 @@
 //! このモジュールは、ローカルシステムにおける`ipak`関連のパスを管理します。
 //! ホームディレクトリ、パッケージリスト、キャッシュなどのパスを生成する関数を提供します。
+//!
+//! XDG Base Directoryの環境変数が設定されている場合は、それに従います。
+//!
+//! | 環境変数 | ディレクトリ | 内容 |
+//! | --- | --- | --- |
+//! | `XDG_DATA_HOME` | `$XDG_DATA_HOME/ipak` | パッケージ、リポジトリリスト、信頼済みの鍵、`bin` |
+//! | `XDG_STATE_HOME` | `$XDG_STATE_HOME/ipak` | ロックファイル、タスクファイル、操作の履歴 |
+//! | `XDG_CONFIG_HOME` | `$XDG_CONFIG_HOME/ipak` | 設定ファイル |
+//! | `XDG_CACHE_HOME` | `$XDG_CACHE_HOME/ipak` | キャッシュ |
+//!
+//! 設定されていない場合は、従来どおり`~/.ipak`（キャッシュは`~/.cache/ipak`）を使用します。
+//! 既存の`~/.ipak`は、パッケージの排他ロックを取得する際に`migrate`によって新しい場所へ移動されます。
 
+use crate::modules::system::config;
 use crate::utils::shell;
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
 /// ユーザーのホームディレクトリへのパスを返します。
 ///
 /// `HOME`環境変数が設定されていない場合は、ユーザー名に基づいてパスを推測します。
+/// ルートディレクトリが変更されている場合は、その下のホームディレクトリを返します。
 ///
 /// # Returns
 /// ユーザーのホームディレクトリへの`PathBuf`。
-fn home_path() -> PathBuf {
+pub fn home_path() -> PathBuf {
     let home_path_str = env::var("HOME").unwrap_or_else(|_| {
         log::error!("Error: HOME environment variable not set. Attempting to use username.");
         let username = shell::username();
         format!("/home/{}", username)
     });
-    PathBuf::from(home_path_str)
+    super::under_root(Path::new(&home_path_str))
 }
 
-/// `ipak`のルートディレクトリへのパスを返します。
+/// 従来の`ipak`のディレクトリ（`~/.ipak`）へのパスを返します。
 ///
-/// これは通常、ホームディレクトリ内の`.ipak`ディレクトリです。
+/// XDG Base Directoryの環境変数が設定されていない場合は、すべてのデータがここに置かれます。
 ///
 /// # Returns
-/// `ipak`のルートディレクトリへの`PathBuf`。
-fn ipak_path() -> PathBuf {
+/// `~/.ipak`への`PathBuf`。
+pub fn legacy_dirpath() -> PathBuf {
     home_path().join(".ipak")
 }
 
+/// XDG Base Directoryの環境変数で指定された、`ipak`のディレクトリへのパスを返します。
+///
+/// 仕様に従い、空または相対パスの値は無視します。
+///
+/// # Arguments
+/// * `var` - 環境変数の名前（例: `XDG_DATA_HOME`）。
+///
+/// # Returns
+/// 環境変数が設定されている場合は`$var/ipak`、そうでなければ`None`。
+fn xdg_dirpath(var: &str) -> Option<PathBuf> {
+    let base = PathBuf::from(env::var_os(var)?);
+    base.is_absolute().then(|| super::under_root(&base).join("ipak"))
+}
+
+/// パッケージなどのデータを置くディレクトリへのパスを返します。
+///
+/// # Returns
+/// `$XDG_DATA_HOME/ipak`、または`~/.ipak`への`PathBuf`。
+pub fn data_dirpath() -> PathBuf {
+    xdg_dirpath("XDG_DATA_HOME").unwrap_or_else(legacy_dirpath)
+}
+
+/// ロックファイルなどの状態を置くディレクトリへのパスを返します。
+///
+/// # Returns
+/// `$XDG_STATE_HOME/ipak`、または`~/.ipak`への`PathBuf`。
+pub fn state_dirpath() -> PathBuf {
+    xdg_dirpath("XDG_STATE_HOME").unwrap_or_else(legacy_dirpath)
+}
+
+/// 設定ファイルを置くディレクトリへのパスを返します。
+///
+/// # Returns
+/// `$XDG_CONFIG_HOME/ipak`、または`~/.ipak`への`PathBuf`。
+pub fn config_dirpath() -> PathBuf {
+    xdg_dirpath("XDG_CONFIG_HOME").unwrap_or_else(legacy_dirpath)
+}
+
+/// ローカルパッケージの実行ファイルを置くディレクトリへのパスを返します。
+///
+/// 設定の`local.bin-dir`が指定されている場合はそのディレクトリを、
+/// そうでなければデータディレクトリ内の`bin`を返します。
+///
+/// # Returns
+/// 実行ファイルのディレクトリへの`PathBuf`。
+pub fn bin_dirpath() -> PathBuf {
+    match config::get().local_bin_dir() {
+        Some(dir) => match dir.strip_prefix("~/") {
+            Some(relative) => home_path().join(relative),
+            None => super::under_root(Path::new(dir)),
+        },
+        None => data_dirpath().join("bin"),
+    }
+}
+
 /// ローカルパッケージリストファイルへのパスを返します。
 ///
 /// # Returns
@@ -43,7 +114,7 @@ pub fn packageslist_filepath() -> PathBuf {
 /// # Returns
 /// ローカルパッケージディレクトリへの`PathBuf`。
 pub fn packages_dirpath() -> PathBuf {
-    ipak_path().join("packages")
+    data_dirpath().join("packages")
 }
 
 /// `ipak`のキャッシュディレクトリへのパスを返します。
@@ -51,7 +122,8 @@ pub fn packages_dirpath() -> PathBuf {
 /// # Returns
 /// `ipak`のキャッシュディレクトリへの`PathBuf`。
 pub fn cache_path() -> PathBuf {
-    home_path().join(".cache/ipak/")
+    xdg_dirpath("XDG_CACHE_HOME")
+        .unwrap_or_else(|| home_path().join(".cache/ipak/"))
 }
 
 /// `ipak`のロックファイルへのパスを返します。
@@ -59,7 +131,47 @@ pub fn cache_path() -> PathBuf {
 /// # Returns
 /// `ipak`のロックファイルへの`PathBuf`。
 pub fn lock_filepath() -> PathBuf {
-    ipak_path().join("lock")
+    state_dirpath().join("lock")
+}
+
+/// ローカルリポジトリリストファイルへのパスを返します。
+///
+/// # Returns
+/// ローカルリポジトリリストファイルへの`PathBuf`。
+pub fn repositories_filepath() -> PathBuf {
+    data_dirpath().join("repositories.yaml")
+}
+
+/// ローカルな設定ファイルへのパスを返します。
+///
+/// 設定は`migrate`より前に読み込まれるため、新しい場所にまだ設定ファイルがなく、
+/// `~/.ipak`に残っている場合はそちらを返します。
+///
+/// # Returns
+/// ローカルな設定ファイルへの`PathBuf`。
+pub fn config_filepath() -> PathBuf {
+    let config_file = config_dirpath().join("config.toml");
+    let legacy_file = legacy_dirpath().join("config.toml");
+    if !config_file.exists() && legacy_file.is_file() {
+        return legacy_file;
+    }
+    config_file
+}
+
+/// ローカルな操作の履歴ファイルへのパスを返します。
+///
+/// # Returns
+/// ローカルな履歴ファイルへの`PathBuf`。
+pub fn history_filepath() -> PathBuf {
+    state_dirpath().join("history.log")
+}
+
+/// ローカルな信頼済みの鍵のディレクトリへのパスを返します。
+///
+/// # Returns
+/// ローカルな信頼済みの鍵のディレクトリへの`PathBuf`。
+pub fn trusted_keys_dirpath() -> PathBuf {
+    data_dirpath().join("trusted-keys")
 }
 
 /// `ipak`のタスクファイルへのパスを返します。
@@ -67,5 +179,95 @@ pub fn lock_filepath() -> PathBuf {
 /// # Returns
 /// `ipak`のタスクファイルへの`PathBuf`。
 pub fn tasks_filepath() -> PathBuf {
-    ipak_path().join("tasks")
+    state_dirpath().join("tasks")
+}
+
+/// ローカルパッケージのインストール先となるディレクトリのリストを返します。
+///
+/// インストールスクリプトが配置したファイルを追跡するために、
+/// これらのディレクトリのスナップショットが取得されます。
+///
+/// # Returns
+/// インストール先ディレクトリへの`PathBuf`のリスト。
+pub fn install_roots() -> Vec<PathBuf> {
+    let home_path = home_path();
+    vec![
+        bin_dirpath(),
+        home_path.join(".local/bin"),
+        home_path.join(".local/lib"),
+        home_path.join(".local/share"),
+    ]
+}
+
+/// `~/.ipak`から移動する項目の名前と、その移動先のリストを返します。
+///
+/// ロックファイルは移動中に保持されるため、移動の対象に含めません。
+fn migration_items() -> Vec<(&'static str, PathBuf)> {
+    vec![
+        ("packages", packages_dirpath()),
+        ("repositories.yaml", repositories_filepath()),
+        ("trusted-keys", trusted_keys_dirpath()),
+        ("README.md", data_dirpath().join("README.md")),
+        ("bin", bin_dirpath()),
+        ("config.toml", config_dirpath().join("config.toml")),
+        ("tasks", tasks_filepath()),
+    ]
+}
+
+/// `~/.ipak`に、新しい場所へ移動する必要があるデータが残っているかどうかを返します。
+///
+/// # Returns
+/// 移動が必要な場合は`true`。
+pub fn needs_migration() -> bool {
+    !super::pending_moves(&legacy_dirpath(), &migration_items()).is_empty()
+}
+
+/// `~/.ipak`に残っているデータを、XDG Base Directoryや`local.bin-dir`で指定された場所へ移動します。
+///
+/// 移動先に既にファイルがある項目は移動しません。
+/// `bin`が移動された場合は、`~/.ipak/bin`から新しい場所へのシンボリックリンクを作成します。
+/// 中断された操作のジャーナルが残っている場合は、パスが変わると回復できなくなるため移動しません。
+/// 他のプロセスと同時に移動しないように、`LockManager::migrate`から呼び出されます。
+///
+/// # Returns
+/// `Ok(Vec<(PathBuf, PathBuf)>)` 移動した項目の移動元と移動先のリスト。
+/// `Err(io::Error)` 中断された操作が残っている、または移動に失敗した場合。
+pub fn migrate() -> Result<Vec<(PathBuf, PathBuf)>, io::Error> {
+    let moved = super::migrate_dir(
+        &legacy_dirpath(),
+        &migration_items(),
+        "Run `ipak system recover --local` to finish it.",
+    )?;
+    link_legacy_bin_dir()?;
+    Ok(moved)
+}
+
+/// 実行ファイルのディレクトリが`~/.ipak/bin`でない場合に、`~/.ipak/bin`からのシンボリックリンクを作成します。
+///
+/// `~/.ipak/bin`を`PATH`に追加している環境やスクリプトがそのまま動作するようにするためのものです。
+/// 実行ファイルのディレクトリが存在しない場合、または`~/.ipak/bin`が既に存在する場合は何もしません。
+/// Unix以外のプラットフォームでも何もしません。
+///
+/// # Returns
+/// `Ok(())` 成功した場合、または何もしなかった場合。
+/// `Err(io::Error)` シンボリックリンクの作成に失敗した場合。
+pub fn link_legacy_bin_dir() -> Result<(), io::Error> {
+    let bin_dir = bin_dirpath();
+    let legacy_bin_dir = legacy_dirpath().join("bin");
+    if bin_dir == legacy_bin_dir
+        || !bin_dir.is_dir()
+        || legacy_bin_dir.symlink_metadata().is_ok()
+    {
+        return Ok(());
+    }
+    fs::create_dir_all(legacy_dirpath())?;
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&bin_dir, &legacy_bin_dir)
+    }
+    // シンボリックリンクを作成できないため、`~/.ipak/bin`は作成しない
+    #[cfg(not(unix))]
+    {
+        Ok(())
+    }
 }
@@ -0,0 +1,24 @@
+//! このモジュールは、クラッシュなどで中断されたパッケージ操作の回復を管理します。
+//! タスクファイルに残されたジャーナルを元に、中断されたトランザクションを確定またはロールバックします。
+
+use crate::modules::pkg::lock::LockManager;
+use crate::modules::project::ExecMode;
+use crate::utils::error::Error;
+
+/// 指定されたモードで中断されたパッケージ操作を回復します。
+///
+/// # Arguments
+/// * `mode` - 実行モード（ローカルまたはグローバル）。
+///
+/// # Returns
+/// `Ok(())` 回復に成功した場合、または回復する操作がなかった場合。
+/// `Err(Error)` ロックの取得、または回復に失敗した場合。
+pub fn recover(mode: ExecMode) -> Result<(), Error> {
+    let lock_manager = LockManager::new(matches!(mode, ExecMode::Global));
+    if lock_manager.recover()? {
+        log::info!("Recovered the interrupted {} operation.", mode);
+    } else {
+        log::info!("No interrupted {} operation to recover.", mode);
+    }
+    Ok(())
+}
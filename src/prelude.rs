@@ -30,7 +30,10 @@ pub mod ipak {
         use super::Error;
         /// アーカイブ関連のユーティリティとアーカイブタイプを公開します。
         pub use crate::utils::archive::{self, ArchiveType};
-        use std::{env, path::PathBuf};
+        use std::{
+            env,
+            path::{Path, PathBuf},
+        };
 
         /// 指定したパスのアーカイブタイプを判定します。
         ///
@@ -76,8 +79,8 @@ pub mod ipak {
         /// `Ok(())` - アーカイブが正常に解凍された場合。
         /// `Err(Error)` - エラーが発生した場合。
         pub fn extract_archive(
-            from: &PathBuf,
-            to: &PathBuf,
+            from: &Path,
+            to: &Path,
         ) -> Result<(), Error> {
             archive::extract_archive(from, to).map_err(Error::from)
         }
@@ -119,6 +122,7 @@ pub mod ipak {
                     Self::System(system_cmd) => system_cmd.exec(),
                     Self::Pkg(pkg_cmd) => pkg_cmd.exec(),
                     Self::Utils(utils_cmd) => utils_cmd.exec(),
+                    Self::Repo(repo_cmd) => repo_cmd.exec(),
                 }
             }
         }
@@ -144,6 +148,13 @@ pub mod ipak {
             }
         }
 
+        /// `RepoCommands`列挙型に対する`CommandExecution`トレイトの実装です。
+        impl CommandExecution for RepoCommands {
+            fn exec(self) -> Result<(), Error> {
+                crate::modules::repo::repo(self)
+            }
+        }
+
         /// `UtilsCommands`列挙型に対する`CommandExecution`トレイトの実装です。
         impl CommandExecution for UtilsCommands {
             fn exec(self) -> Result<(), Error> {
@@ -212,6 +223,10 @@ pub mod ipak {
         /// 不足している依存コマンドを取得するユーティリティを公開します。
         pub use crate::modules::pkg::depend::utils::get_missing_depend_cmds;
 
+        /// インストール済みパッケージのデータベースを公開します。
+        pub use crate::modules::pkg::list::{
+            FileDatabase, PackageDatabase,
+        };
         /// インストール済みパッケージデータとパッケージリストデータを公開します。
         pub use crate::modules::pkg::list::{
             InstalledPackageData, PackageListData,
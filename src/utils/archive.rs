@@ -3,15 +3,18 @@
 
 use ar::Archive as ArArchive;
 use ar::Builder as ArBuilder;
+use chrono::{Datelike, Timelike};
 use clap;
 use file_format::{self, FileFormat};
 use flate2::Compression;
 use flate2::write::GzEncoder;
+use std::collections::HashSet;
 use std::fmt::Display;
 use std::fs::File;
 use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tar::{Builder as TarBuilder, Header};
 use walkdir::WalkDir;
 use xz2::write::XzEncoder;
@@ -78,86 +81,708 @@ pub fn get_archive_type(path: &Path) -> Result<ArchiveType, String> {
     }
 }
 
+/// アーカイブの展開で許可する上限です。
+///
+/// 展開後のサイズを実際に書き込んだバイト数で数えるため、
+/// ヘッダーに偽のサイズが書かれたアーカイブ（zip爆弾など）も検出できます。
+#[derive(Clone, Copy, Debug)]
+pub struct ExtractLimits {
+    /// 展開するエントリの最大数。
+    pub max_entries: usize,
+    /// 展開するファイルの合計サイズの上限（バイト）。
+    pub max_total_size: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self { max_entries: 100_000, max_total_size: 4 << 30 }
+    }
+}
+
+/// アーカイブの展開中に発生するエラーです。
+#[derive(Debug)]
+pub enum ExtractError {
+    /// ファイルの読み書きに失敗した、またはアーカイブが壊れている。
+    Io(std::io::Error),
+    /// アーカイブの形式を判別できない。
+    UnknownArchiveType(String),
+    /// 絶対パスや`..`を含む、展開先の外を指すエントリ。
+    UnsafePath { path: String },
+    /// 展開先の外を指すシンボリックリンクまたはハードリンク。
+    UnsafeLink { path: String, target: String },
+    /// 展開できない種類のエントリ（デバイスファイルなど）。
+    UnsupportedEntry { path: String, kind: String },
+    /// エントリの数が上限を超えた。
+    TooManyEntries { limit: usize },
+    /// 展開後の合計サイズが上限を超えた。
+    TooLarge { limit: u64 },
+    /// 同じパスのエントリが複数ある。
+    DuplicateEntry { path: String },
+}
+
+impl Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::UnknownArchiveType(archive_type) => {
+                write!(f, "Unknown archive type: {}", archive_type)
+            }
+            Self::UnsafePath { path } => write!(
+                f,
+                "Archive entry '{}' points outside the destination",
+                path
+            ),
+            Self::UnsafeLink { path, target } => write!(
+                f,
+                "Archive entry '{}' links to '{}' outside the destination",
+                path, target
+            ),
+            Self::UnsupportedEntry { path, kind } => write!(
+                f,
+                "Archive entry '{}' has an unsupported type ({})",
+                path, kind
+            ),
+            Self::TooManyEntries { limit } => {
+                write!(f, "Archive has more than {} entries", limit)
+            }
+            Self::TooLarge { limit } => {
+                write!(f, "Archive expands to more than {} bytes", limit)
+            }
+            Self::DuplicateEntry { path } => {
+                write!(f, "Archive has more than one entry for '{}'", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ExtractError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<zip::result::ZipError> for ExtractError {
+    fn from(value: zip::result::ZipError) -> Self {
+        Self::Io(value.into())
+    }
+}
+
+/// アーカイブを既定の上限で展開します。
+///
+/// # Arguments
+/// * `from` - 展開するアーカイブのパス。
+/// * `to` - 展開先のディレクトリ。
+///
+/// # Returns
+/// `Ok(())` 展開に成功した場合。
+/// `Err(ExtractError)` アーカイブが壊れている、安全でないエントリを含む、または上限を超えた場合。
 pub fn extract_archive(
-    from: &PathBuf,
-    to: &PathBuf,
-) -> Result<(), std::io::Error> {
+    from: &Path,
+    to: &Path,
+) -> Result<(), ExtractError> {
+    extract_archive_with_limits(from, to, ExtractLimits::default())
+}
+
+/// アーカイブを指定された上限で展開します。
+///
+/// すべての`ArchiveType`のエントリは同じ検査を通ります。
+/// 絶対パスや`..`を含むエントリ、展開先の外を指すリンク、
+/// 展開先の外へ抜けるシンボリックリンクを経由した書き込みは拒否されます。
+///
+/// # Arguments
+/// * `from` - 展開するアーカイブのパス。
+/// * `to` - 展開先のディレクトリ。
+/// * `limits` - エントリの数と合計サイズの上限。
+///
+/// # Returns
+/// `Ok(())` 展開に成功した場合。
+/// `Err(ExtractError)` アーカイブが壊れている、安全でないエントリを含む、または上限を超えた場合。
+pub fn extract_archive_with_limits(
+    from: &Path,
+    to: &Path,
+    limits: ExtractLimits,
+) -> Result<(), ExtractError> {
     log::debug!(
         "Extracting archive from: {} to: {}",
         from.display(),
         to.display()
     );
-    let archive_type = get_archive_type(from).map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("Unknown archive type: {}", e),
-        )
-    })?;
+    let archive_type = get_archive_type(from)
+        .map_err(ExtractError::UnknownArchiveType)?;
     let file = File::open(from)?;
+    let mut extractor = Extractor::new(to, limits)?;
     match archive_type {
         ArchiveType::Zip => {
             let mut archive = zip::ZipArchive::new(file)?;
             for i in 0..archive.len() {
-                let mut file = archive.by_index(i)?;
-                let outpath = to.join(file.mangled_name());
-                if file.name().ends_with('/') {
-                    std::fs::create_dir_all(&outpath)?;
+                let mut entry = archive.by_index(i)?;
+                let name = entry.name().to_string();
+                let metadata = EntryMetadata {
+                    mode: entry.unix_mode(),
+                    mtime: entry
+                        .last_modified()
+                        .and_then(from_zip_datetime),
+                };
+                if entry.is_dir() {
+                    extractor.create_dir(&name, metadata)?;
+                } else if entry.is_symlink() {
+                    let mut target = String::new();
+                    (&mut entry).take(4096).read_to_string(&mut target)?;
+                    extractor.create_symlink(&name, &target)?;
                 } else {
-                    if let Some(p) = outpath.parent() {
-                        if !p.exists() {
-                            std::fs::create_dir_all(p)?;
-                        }
-                    }
-                    let mut outfile = File::create(&outpath)?;
-                    std::io::copy(&mut file, &mut outfile)?;
+                    extractor.write_file(&name, &mut entry, metadata)?;
                 }
             }
-            Ok(())
         }
         ArchiveType::UnixAr => {
             let mut archive = ArArchive::new(file);
             while let Some(entry) = archive.next_entry() {
                 let mut entry = entry?;
                 let header = entry.header();
-                let entry_name_bytes = header.identifier();
-                let entry_name =
-                    String::from_utf8_lossy(entry_name_bytes).into_owned();
-                let outpath = to.join(entry_name);
-
-                if let Some(p) = outpath.parent() {
-                    if !p.exists() {
-                        std::fs::create_dir_all(p)?;
+                let name = String::from_utf8_lossy(header.identifier())
+                    .into_owned();
+                let metadata = EntryMetadata {
+                    mode: Some(header.mode()),
+                    mtime: Some(from_unix_time(header.mtime())),
+                };
+                if header.mode() & S_IFMT == S_IFLNK {
+                    let mut target = String::new();
+                    (&mut entry).take(4096).read_to_string(&mut target)?;
+                    extractor.create_symlink(&name, &target)?;
+                } else {
+                    extractor.write_file(&name, &mut entry, metadata)?;
+                }
+            }
+        }
+        _ => {
+            let mut archive =
+                tar::Archive::new(tar_reader(archive_type, file)?);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let name = String::from_utf8_lossy(&entry.path_bytes())
+                    .into_owned();
+                let link_name = entry.link_name_bytes().map(|link_name| {
+                    String::from_utf8_lossy(&link_name).into_owned()
+                });
+                let entry_type = entry.header().entry_type();
+                let metadata = EntryMetadata {
+                    mode: entry.header().mode().ok(),
+                    mtime: entry.header().mtime().ok().map(from_unix_time),
+                };
+                match entry_type {
+                    tar::EntryType::Regular
+                    | tar::EntryType::Continuous => {
+                        extractor
+                            .write_file(&name, &mut entry, metadata)?;
+                    }
+                    tar::EntryType::Directory => {
+                        extractor.create_dir(&name, metadata)?;
+                    }
+                    tar::EntryType::Symlink => extractor.create_symlink(
+                        &name,
+                        &link_name.unwrap_or_default(),
+                    )?,
+                    tar::EntryType::Link => extractor.create_hard_link(
+                        &name,
+                        &link_name.unwrap_or_default(),
+                    )?,
+                    tar::EntryType::XGlobalHeader
+                    | tar::EntryType::XHeader => {}
+                    _ => {
+                        return Err(ExtractError::UnsupportedEntry {
+                            path: name,
+                            kind: format!("{:?}", entry_type),
+                        });
                     }
                 }
-                let mut outfile = File::create(&outpath)?;
-                std::io::copy(&mut entry, &mut outfile)?;
             }
-            Ok(())
+        }
+    }
+    extractor.finish()
+}
+
+/// アーカイブから1つのメンバーだけを読み込みます。
+///
+/// アーカイブ全体は展開しません。
+/// zipでは中央ディレクトリからメンバーを探し、tarとarではヘッダーを順に走査します。
+/// メンバー名の先頭の`./`は無視されます。
+/// 展開時と異なるメンバーを読まないように、同じ名前のメンバーが複数ある場合はエラーを返します。
+///
+/// # Arguments
+/// * `from` - 読み込むアーカイブのパス。
+/// * `member` - 読み込むメンバーのパス（例: `ipak/project.yaml`）。
+/// * `max_size` - メンバーの内容のサイズの上限（バイト）。
+///
+/// # Returns
+/// `Ok(Some(Vec<u8>))` メンバーの内容。
+/// `Ok(None)` メンバーがアーカイブに含まれていない場合。
+/// `Err(ExtractError)` アーカイブが壊れている、メンバーが上限より大きい、またはメンバーが重複している場合。
+pub fn read_archive_member(
+    from: &Path,
+    member: &str,
+    max_size: u64,
+) -> Result<Option<Vec<u8>>, ExtractError> {
+    let archive_type = get_archive_type(from)
+        .map_err(ExtractError::UnknownArchiveType)?;
+    let file = File::open(from)?;
+    let member = relative_entry_path(member);
+    let is_member = |name: &str| {
+        member.is_some() && relative_entry_path(name) == member
+    };
+    let read_limited = |reader: &mut dyn Read| {
+        let mut content = Vec::new();
+        reader.take(max_size + 1).read_to_end(&mut content)?;
+        if content.len() as u64 > max_size {
+            return Err(ExtractError::TooLarge { limit: max_size });
+        }
+        Ok(Some(content))
+    };
+    let duplicate = |name: &str| ExtractError::DuplicateEntry {
+        path: name.to_string(),
+    };
+    match archive_type {
+        ArchiveType::Zip => {
+            let mut archive = zip::ZipArchive::new(file)?;
+            let mut names = archive
+                .file_names()
+                .filter(|name| is_member(name))
+                .map(str::to_string);
+            let Some(name) = names.next() else {
+                return Ok(None);
+            };
+            if let Some(other) = names.next() {
+                return Err(duplicate(&other));
+            }
+            drop(names);
+            let mut entry = archive.by_name(&name)?;
+            if entry.is_dir() || entry.is_symlink() {
+                return Ok(None);
+            }
+            read_limited(&mut entry)
+        }
+        ArchiveType::UnixAr => {
+            let mut archive = ArArchive::new(file);
+            let mut found = None;
+            while let Some(entry) = archive.next_entry() {
+                let mut entry = entry?;
+                let header = entry.header();
+                let name = String::from_utf8_lossy(header.identifier())
+                    .into_owned();
+                if !is_member(&name) {
+                    continue;
+                }
+                if found.is_some() {
+                    return Err(duplicate(&name));
+                }
+                found = Some(if header.mode() & S_IFMT != S_IFLNK {
+                    read_limited(&mut entry)?
+                } else {
+                    None
+                });
+            }
+            Ok(found.flatten())
         }
         _ => {
-            let reader = match archive_type {
-                ArchiveType::Tar => Box::new(file) as Box<dyn Read>,
-                ArchiveType::TarGz => {
-                    Box::new(flate2::read::GzDecoder::new(file))
-                        as Box<dyn Read>
+            let mut archive =
+                tar::Archive::new(tar_reader(archive_type, file)?);
+            let mut found = None;
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let entry_type = entry.header().entry_type();
+                let name = String::from_utf8_lossy(&entry.path_bytes())
+                    .into_owned();
+                if !is_member(&name) {
+                    continue;
                 }
-                ArchiveType::TarXz => {
-                    Box::new(xz2::read::XzDecoder::new(file))
-                        as Box<dyn Read>
+                if found.is_some() {
+                    return Err(duplicate(&name));
+                }
+                found = Some(
+                    if matches!(
+                        entry_type,
+                        tar::EntryType::Regular
+                            | tar::EntryType::Continuous
+                    ) {
+                        read_limited(&mut entry)?
+                    } else {
+                        None
+                    },
+                );
+            }
+            Ok(found.flatten())
+        }
+    }
+}
+
+/// tar系のアーカイブを解凍しながら読み込むリーダーを作成します。
+fn tar_reader(
+    archive_type: ArchiveType,
+    file: File,
+) -> Result<Box<dyn Read>, std::io::Error> {
+    Ok(match archive_type {
+        ArchiveType::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        ArchiveType::TarXz => Box::new(xz2::read::XzDecoder::new(file)),
+        ArchiveType::TarZstd => {
+            Box::new(zstd::stream::Decoder::new(file)?)
+        }
+        _ => Box::new(file),
+    })
+}
+
+/// アーカイブのエントリを展開先の中にだけ書き込む構造体です。
+struct Extractor {
+    /// 展開先のディレクトリ（正規化済み）。
+    root: PathBuf,
+    limits: ExtractLimits,
+    entries: usize,
+    total_size: u64,
+    /// 作成したシンボリックリンクとそのリンク先。
+    symlinks: Vec<(PathBuf, String)>,
+    /// 展開の最後に属性を設定するディレクトリ。
+    dirs: Vec<(PathBuf, EntryMetadata)>,
+    /// これまでに展開したエントリの、展開先からの相対パス。
+    seen: HashSet<PathBuf>,
+}
+
+impl Extractor {
+    fn new(
+        to: &Path,
+        limits: ExtractLimits,
+    ) -> Result<Self, ExtractError> {
+        std::fs::create_dir_all(to)?;
+        Ok(Self {
+            root: std::fs::canonicalize(to)?,
+            limits,
+            entries: 0,
+            total_size: 0,
+            symlinks: Vec::new(),
+            dirs: Vec::new(),
+            seen: HashSet::new(),
+        })
+    }
+
+    /// エントリを数え、エントリ名を展開先からの相対パスに変換します。
+    ///
+    /// 空のパス（`./`など）の場合は`None`を返します。
+    /// 後のエントリが先のエントリを黙って上書きしないように、同じパスのエントリが2回目に現れた場合はエラーを返します。
+    fn entry(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<PathBuf>, ExtractError> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(ExtractError::TooManyEntries {
+                limit: self.limits.max_entries,
+            });
+        }
+        let relative = relative_entry_path(name).ok_or_else(|| {
+            ExtractError::UnsafePath { path: name.to_string() }
+        })?;
+        if relative.as_os_str().is_empty() {
+            return Ok(None);
+        }
+        if !self.seen.insert(relative.clone()) {
+            return Err(ExtractError::DuplicateEntry {
+                path: name.to_string(),
+            });
+        }
+        Ok(Some(relative))
+    }
+
+    /// 展開先からの相対パスのディレクトリを、途中のディレクトリも含めて作成します。
+    ///
+    /// 途中にシンボリックリンクがある場合は、その実体が展開先の中にあることを確認します。
+    fn create_dirs(&self, relative: &Path) -> Result<(), ExtractError> {
+        let mut dir = self.root.clone();
+        for component in relative.components() {
+            dir.push(component);
+            match std::fs::symlink_metadata(&dir) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    if !std::fs::canonicalize(&dir)?
+                        .starts_with(&self.root)
+                    {
+                        return Err(ExtractError::UnsafePath {
+                            path: relative.display().to_string(),
+                        });
+                    }
                 }
-                ArchiveType::TarZstd => {
-                    Box::new(zstd::stream::Decoder::new(file)?)
-                        as Box<dyn Read>
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    std::fs::create_dir(&dir)?
                 }
-                _ => unreachable!(),
-            };
-            let mut archive = tar::Archive::new(reader);
-            archive.unpack(to)?;
-            Ok(())
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// エントリを書き込む準備をし、書き込み先のパスを返します。
+    ///
+    /// 親ディレクトリを作成し、同じパスにあるファイルやリンクを削除します。
+    fn prepare(&self, relative: &Path) -> Result<PathBuf, ExtractError> {
+        self.create_dirs(relative.parent().unwrap_or(Path::new("")))?;
+        let destination = self.root.join(relative);
+        if std::fs::symlink_metadata(&destination)
+            .is_ok_and(|metadata| !metadata.is_dir())
+        {
+            std::fs::remove_file(&destination)?;
+        }
+        Ok(destination)
+    }
+
+    /// ディレクトリを作成します。
+    ///
+    /// 中に書き込めなくなったり更新日時が変わったりしないよう、属性は`finish`で設定します。
+    fn create_dir(
+        &mut self,
+        name: &str,
+        metadata: EntryMetadata,
+    ) -> Result<(), ExtractError> {
+        let Some(relative) = self.entry(name)? else {
+            return Ok(());
+        };
+        self.create_dirs(&relative)?;
+        self.dirs.push((self.root.join(relative), metadata));
+        Ok(())
+    }
+
+    fn write_file(
+        &mut self,
+        name: &str,
+        reader: &mut dyn Read,
+        metadata: EntryMetadata,
+    ) -> Result<(), ExtractError> {
+        let Some(relative) = self.entry(name)? else {
+            return Ok(());
+        };
+        let destination = self.prepare(&relative)?;
+        let remaining = self.limits.max_total_size - self.total_size;
+        let mut outfile = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&destination)?;
+        let written =
+            std::io::copy(&mut reader.take(remaining + 1), &mut outfile)?;
+        if written > remaining {
+            return Err(ExtractError::TooLarge {
+                limit: self.limits.max_total_size,
+            });
+        }
+        self.total_size += written;
+        metadata.apply(&outfile)?;
+        Ok(())
+    }
+
+    fn create_symlink(
+        &mut self,
+        name: &str,
+        target: &str,
+    ) -> Result<(), ExtractError> {
+        let Some(relative) = self.entry(name)? else {
+            return Ok(());
+        };
+        if !link_stays_inside(&relative, Path::new(target)) {
+            return Err(ExtractError::UnsafeLink {
+                path: name.to_string(),
+                target: target.to_string(),
+            });
+        }
+        let destination = self.prepare(&relative)?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, &destination)?;
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_file(target, &destination)?;
+        self.symlinks.push((destination, target.to_string()));
+        Ok(())
+    }
+
+    fn create_hard_link(
+        &mut self,
+        name: &str,
+        target: &str,
+    ) -> Result<(), ExtractError> {
+        let Some(relative) = self.entry(name)? else {
+            return Ok(());
+        };
+        let unsafe_link = || ExtractError::UnsafeLink {
+            path: name.to_string(),
+            target: target.to_string(),
+        };
+        let source = relative_entry_path(target)
+            .filter(|source| !source.as_os_str().is_empty())
+            .ok_or_else(unsafe_link)?;
+        let source = std::fs::canonicalize(self.root.join(source))?;
+        if !source.starts_with(&self.root) {
+            return Err(unsafe_link());
+        }
+        let destination = self.prepare(&relative)?;
+        std::fs::hard_link(source, destination)?;
+        Ok(())
+    }
+
+    /// 作成したシンボリックリンクが、リンクを辿っても展開先の外を指さないことを確認し、
+    /// ディレクトリの属性を深い順に設定します。
+    fn finish(self) -> Result<(), ExtractError> {
+        for (link, target) in &self.symlinks {
+            if let Ok(resolved) = std::fs::canonicalize(link)
+                && !resolved.starts_with(&self.root)
+            {
+                std::fs::remove_file(link)?;
+                return Err(ExtractError::UnsafeLink {
+                    path: link
+                        .strip_prefix(&self.root)
+                        .unwrap_or(link)
+                        .display()
+                        .to_string(),
+                    target: target.clone(),
+                });
+            }
+        }
+        for (dir, metadata) in self.dirs.iter().rev() {
+            metadata.apply(&File::open(dir)?)?;
         }
+        Ok(())
     }
 }
 
+/// ファイルの種類を表すモードのビットです。
+const S_IFMT: u32 = 0o170000;
+/// シンボリックリンクを表すモードのビットです。
+const S_IFLNK: u32 = 0o120000;
+
+/// アーカイブのエントリに記録された属性です。
+#[derive(Clone, Copy, Debug, Default)]
+struct EntryMetadata {
+    /// Unixのモード。
+    mode: Option<u32>,
+    /// 更新日時。
+    mtime: Option<SystemTime>,
+}
+
+impl EntryMetadata {
+    /// 展開したファイルまたはディレクトリに属性を設定します。
+    fn apply(&self, file: &File) -> Result<(), std::io::Error> {
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(
+                mode & 0o777,
+            ))?;
+        }
+        if let Some(mtime) = self.mtime {
+            file.set_modified(mtime)?;
+        }
+        Ok(())
+    }
+}
+
+/// UNIX時間（秒）を`SystemTime`に変換します。
+fn from_unix_time(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// ファイルの更新日時をUNIX時間（秒）で返します。
+fn unix_time(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// ファイルのモードと更新日時を記録するzipのオプションを作成します。
+fn zip_file_options(
+    metadata: &std::fs::Metadata,
+) -> zip::write::SimpleFileOptions {
+    let mut options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored);
+    if let Some(mtime) = metadata.modified().ok().and_then(to_zip_datetime)
+    {
+        options = options.last_modified_time(mtime);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        options = options.unix_permissions(metadata.permissions().mode());
+    }
+    options
+}
+
+/// 更新日時をzipの日時（UTC）に変換します。
+fn to_zip_datetime(mtime: SystemTime) -> Option<zip::DateTime> {
+    let mtime = chrono::DateTime::<chrono::Utc>::from(mtime);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(mtime.year()).ok()?,
+        mtime.month() as u8,
+        mtime.day() as u8,
+        mtime.hour() as u8,
+        mtime.minute() as u8,
+        mtime.second() as u8,
+    )
+    .ok()
+}
+
+/// zipの日時（UTC）を更新日時に変換します。
+fn from_zip_datetime(datetime: zip::DateTime) -> Option<SystemTime> {
+    let datetime = chrono::NaiveDate::from_ymd_opt(
+        datetime.year().into(),
+        datetime.month().into(),
+        datetime.day().into(),
+    )?
+    .and_hms_opt(
+        datetime.hour().into(),
+        datetime.minute().into(),
+        datetime.second().into(),
+    )?
+    .and_utc();
+    Some(datetime.into())
+}
+
+/// エントリ名を展開先からの相対パスに変換します。
+///
+/// 絶対パスや`..`を含むパスの場合は`None`を返します。
+fn relative_entry_path(name: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir
+            | Component::RootDir
+            | Component::Prefix(_) => {
+                return None;
+            }
+        }
+    }
+    Some(relative)
+}
+
+/// `relative`に置かれるシンボリックリンクのリンク先が、展開先の中に留まるかを字句的に判定します。
+fn link_stays_inside(relative: &Path, target: &Path) -> bool {
+    let mut depth = relative.components().count().saturating_sub(1);
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent_depth) => depth = parent_depth,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
 pub fn create_archive(
     from: &PathBuf,
     to: &PathBuf,
@@ -243,24 +868,24 @@ pub fn create_archive(
                             .replace('\\', "/")
                     )
                 };
-                if path.is_file() {
+                let options = zip_file_options(&entry.metadata()?);
+                if entry.file_type().is_symlink() {
+                    let target = std::fs::read_link(path)?;
+                    zip.add_symlink(
+                        &name,
+                        target.to_string_lossy(),
+                        options,
+                    )?;
+                } else if entry.file_type().is_file() {
                     let mut f = File::open(path)?;
-                    let options: zip::write::FileOptions<()> =
-                        zip::write::FileOptions::default()
-                            .compression_method(
-                                zip::CompressionMethod::Stored,
-                            );
                     zip.start_file(&name, options)?;
                     std::io::copy(&mut f, &mut zip)?;
-                } else if path.is_dir() {
+                } else if entry.file_type().is_dir() {
                     if has_slash && relative == Path::new("") {
                         continue;
                     }
 
-                    zip.add_directory::<&str, zip::write::ExtendedFileOptions>(
-                        &format!("{}/", name), 
-                        zip::write::FileOptions::default(),
-                    )?;
+                    zip.add_directory(format!("{}/", name), options)?;
                 }
             }
             zip.finish()?;
@@ -327,7 +952,9 @@ pub fn create_archive(
                 let entry = entry?;
                 let path = entry.path();
 
-                if path.is_file() {
+                if entry.file_type().is_file()
+                    || entry.file_type().is_symlink()
+                {
                     let relative_path =
                         path.strip_prefix(from).map_err(|e| {
                             std::io::Error::new(
@@ -362,20 +989,23 @@ pub fn create_archive(
                         )
                     };
 
-                    let mut file_to_archive = File::open(path)?;
-                    let metadata = path.metadata()?;
+                    let metadata = entry.metadata()?;
+                    // arにはリンクの種類がないため、シンボリックリンクは
+                    // モードの種類のビットとリンク先を内容として記録する
+                    let content = if entry.file_type().is_symlink() {
+                        std::fs::read_link(path)?
+                            .to_string_lossy()
+                            .into_owned()
+                            .into_bytes()
+                    } else {
+                        std::fs::read(path)?
+                    };
 
                     let mut header = ar::Header::new(
                         ar_name.into_bytes(),
-                        metadata.len(),
-                    );
-                    header.set_mtime(
-                        metadata
-                            .modified()?
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs(),
+                        content.len() as u64,
                     );
+                    header.set_mtime(unix_time(&metadata));
                     #[cfg(unix)]
                     {
                         use std::os::unix::fs::MetadataExt;
@@ -384,7 +1014,7 @@ pub fn create_archive(
                         header.set_gid(metadata.gid());
                     }
 
-                    builder.append(&header, &mut file_to_archive)?;
+                    builder.append(&header, content.as_slice())?;
                 }
             }
             builder.into_inner()?.flush()?;
@@ -399,10 +1029,11 @@ fn add_directory_contents<B: Write>(
     has_slash: bool,
     dir_name: Option<&str>,
 ) -> Result<(), std::io::Error> {
+    builder.follow_symlinks(false);
     for entry in WalkDir::new(from) {
         let entry = entry?;
         let path = entry.path();
-        let metadata = path.metadata()?;
+        let metadata = entry.metadata()?;
 
         let relative = path.strip_prefix(from).map_err(|e| {
             std::io::Error::new(
@@ -449,23 +1080,23 @@ fn add_directory_contents<B: Write>(
 
         let entry_path_for_append = PathBuf::from(name.clone());
 
-        if path.is_file() {
+        if metadata.is_file() || metadata.is_symlink() {
             builder.append_path_with_name(path, &entry_path_for_append)?;
-        } else if path.is_dir() {
+        } else if metadata.is_dir() {
             let mut dir_entry_name = name;
             if !dir_entry_name.ends_with('/') {
                 dir_entry_name.push('/');
             }
 
             let mut header = Header::new_ustar();
-            header.set_path(&dir_entry_name)?;
-            header.set_entry_type(tar::EntryType::Directory);
-            header.set_size(0);
-
             #[cfg(unix)]
             {
                 header.set_metadata(&metadata);
             }
+            header.set_path(&dir_entry_name)?;
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            header.set_cksum();
 
             builder.append(&header, &mut std::io::empty())?;
         }
@@ -513,6 +1144,312 @@ mod tests {
         );
     }
 
+    /// 指定されたエントリだけを含むtarアーカイブを作成します。
+    fn tar_with_entries(
+        path: &Path,
+        entries: &[(&str, tar::EntryType, &str)],
+    ) {
+        let mut builder = TarBuilder::new(File::create(path).unwrap());
+        for (name, entry_type, content) in entries {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            if *entry_type == tar::EntryType::Symlink {
+                header.set_link_name(content).unwrap();
+                header.set_size(0);
+                builder
+                    .append_data(&mut header, name, std::io::empty())
+                    .unwrap();
+            } else {
+                header.set_size(content.len() as u64);
+                builder
+                    .append_data(&mut header, name, content.as_bytes())
+                    .unwrap();
+            }
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_ar_rejects_path_traversal() {
+        let temp_dir =
+            TempDir::with_prefix("archive_test_ar_unsafe").unwrap();
+        let archive_path = temp_dir.path().join("evil.ar");
+        let mut builder =
+            ArBuilder::new(File::create(&archive_path).unwrap());
+        builder
+            .append(
+                &ar::Header::new(b"../escaped.txt".to_vec(), 4),
+                &b"evil"[..],
+            )
+            .unwrap();
+        drop(builder);
+
+        let extract_dir = temp_dir.path().join("extracted");
+        let result = extract_archive(&archive_path, &extract_dir);
+        assert!(matches!(result, Err(ExtractError::UnsafePath { .. })));
+        assert!(!temp_dir.path().join("escaped.txt").exists());
+    }
+
+    #[test]
+    fn test_tar_rejects_links_outside_destination() {
+        let temp_dir =
+            TempDir::with_prefix("archive_test_tar_link").unwrap();
+        let extract_dir = temp_dir.path().join("extracted");
+
+        let archive_path = temp_dir.path().join("direct.tar");
+        tar_with_entries(
+            &archive_path,
+            &[("dir/link", tar::EntryType::Symlink, "../../outside")],
+        );
+        assert!(matches!(
+            extract_archive(&archive_path, &extract_dir),
+            Err(ExtractError::UnsafeLink { .. })
+        ));
+
+        // リンクを辿ると外に出る場合は、リンク経由の書き込みも拒否される
+        let archive_path = temp_dir.path().join("chained.tar");
+        tar_with_entries(
+            &archive_path,
+            &[
+                ("here", tar::EntryType::Symlink, "."),
+                ("up", tar::EntryType::Symlink, "here/.."),
+                ("up/escaped.txt", tar::EntryType::Regular, "evil"),
+            ],
+        );
+        assert!(matches!(
+            extract_archive(&archive_path, &extract_dir),
+            Err(ExtractError::UnsafePath { .. })
+        ));
+        assert!(!temp_dir.path().join("escaped.txt").exists());
+
+        // 展開先の中を指すリンクは作成される
+        let archive_path = temp_dir.path().join("inside.tar");
+        tar_with_entries(
+            &archive_path,
+            &[
+                ("bin/tool", tar::EntryType::Regular, "#!/bin/sh"),
+                ("tool", tar::EntryType::Symlink, "bin/tool"),
+            ],
+        );
+        let inside_dir = temp_dir.path().join("inside");
+        extract_archive(&archive_path, &inside_dir).unwrap();
+        assert_eq!(
+            fs::read_link(inside_dir.join("tool")).unwrap(),
+            PathBuf::from("bin/tool")
+        );
+    }
+
+    #[test]
+    fn test_extract_limits() {
+        let temp_dir =
+            TempDir::with_prefix("archive_test_limits").unwrap();
+        let archive_path = temp_dir.path().join("many.tar");
+        tar_with_entries(
+            &archive_path,
+            &[
+                ("a.txt", tar::EntryType::Regular, "aaaa"),
+                ("b.txt", tar::EntryType::Regular, "bbbb"),
+                ("c.txt", tar::EntryType::Regular, "cccc"),
+            ],
+        );
+
+        let limits =
+            ExtractLimits { max_entries: 2, ..Default::default() };
+        assert!(matches!(
+            extract_archive_with_limits(
+                &archive_path,
+                &temp_dir.path().join("count"),
+                limits
+            ),
+            Err(ExtractError::TooManyEntries { limit: 2 })
+        ));
+        let limits =
+            ExtractLimits { max_total_size: 10, ..Default::default() };
+        assert!(matches!(
+            extract_archive_with_limits(
+                &archive_path,
+                &temp_dir.path().join("size"),
+                limits
+            ),
+            Err(ExtractError::TooLarge { limit: 10 })
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_entries_are_rejected() {
+        let temp_dir =
+            TempDir::with_prefix("archive_test_duplicate").unwrap();
+        let archive_path = temp_dir.path().join("duplicate.tar");
+        tar_with_entries(
+            &archive_path,
+            &[
+                (
+                    "ipak/project.yaml",
+                    tar::EntryType::Regular,
+                    "name: fake",
+                ),
+                (
+                    "./ipak/project.yaml",
+                    tar::EntryType::Regular,
+                    "name: pkg",
+                ),
+            ],
+        );
+
+        assert!(matches!(
+            read_archive_member(&archive_path, "ipak/project.yaml", 64),
+            Err(ExtractError::DuplicateEntry { .. })
+        ));
+        assert!(matches!(
+            extract_archive(&archive_path, &temp_dir.path().join("out")),
+            Err(ExtractError::DuplicateEntry { .. })
+        ));
+    }
+
+    /// モード、シンボリックリンク、更新日時がアーカイブの作成と展開で保たれることを確認します。
+    #[cfg(unix)]
+    fn assert_metadata_round_trip(archive_type: ArchiveType) {
+        use std::os::unix::fs::{PermissionsExt, symlink};
+
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let set_mode_and_mtime = |path: &Path, mode: u32| {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))
+                .unwrap();
+            File::open(path).unwrap().set_modified(mtime).unwrap();
+        };
+        let temp_dir =
+            TempDir::with_prefix("archive_test_metadata").unwrap();
+        let source_dir = temp_dir.path().join("pkg");
+        fs::create_dir_all(source_dir.join("bin")).unwrap();
+        fs::write(source_dir.join("bin/tool"), b"#!/bin/sh\n").unwrap();
+        fs::write(source_dir.join("config"), b"key: value\n").unwrap();
+        symlink("bin/tool", source_dir.join("tool")).unwrap();
+        set_mode_and_mtime(&source_dir.join("bin/tool"), 0o755);
+        set_mode_and_mtime(&source_dir.join("config"), 0o640);
+        set_mode_and_mtime(&source_dir.join("bin"), 0o750);
+
+        let archive_path =
+            temp_dir.path().join(format!("pkg.{}", archive_type));
+        let source_dir_with_slash =
+            PathBuf::from(format!("{}/", source_dir.to_str().unwrap()));
+        create_archive(
+            &source_dir_with_slash,
+            &archive_path,
+            archive_type,
+        )
+        .unwrap();
+        let extract_dir = temp_dir.path().join("extracted");
+        extract_archive(&archive_path, &extract_dir).unwrap();
+
+        let mode = |path: &str| {
+            fs::metadata(extract_dir.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        let modified = |path: &str| {
+            fs::metadata(extract_dir.join(path))
+                .unwrap()
+                .modified()
+                .unwrap()
+        };
+        assert_eq!(mode("bin/tool"), 0o755, "{}", archive_type);
+        assert_eq!(mode("config"), 0o640, "{}", archive_type);
+        assert_eq!(modified("bin/tool"), mtime, "{}", archive_type);
+        assert_eq!(modified("config"), mtime, "{}", archive_type);
+        assert_eq!(
+            fs::read_link(extract_dir.join("tool")).unwrap(),
+            PathBuf::from("bin/tool"),
+            "{}",
+            archive_type
+        );
+        // arはディレクトリを記録しない
+        if !matches!(archive_type, ArchiveType::UnixAr) {
+            assert_eq!(mode("bin"), 0o750, "{}", archive_type);
+            assert_eq!(modified("bin"), mtime, "{}", archive_type);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_zip_preserves_metadata() {
+        assert_metadata_round_trip(ArchiveType::Zip);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_tar_preserves_metadata() {
+        for archive_type in [
+            ArchiveType::Tar,
+            ArchiveType::TarGz,
+            ArchiveType::TarXz,
+            ArchiveType::TarZstd,
+        ] {
+            assert_metadata_round_trip(archive_type);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_ar_preserves_metadata() {
+        assert_metadata_round_trip(ArchiveType::UnixAr);
+    }
+
+    #[test]
+    fn test_read_archive_member_without_extracting() {
+        let temp_dir =
+            TempDir::with_prefix("archive_test_member").unwrap();
+        let source_dir = temp_dir.path().join("pkg");
+        fs::create_dir_all(source_dir.join("ipak")).unwrap();
+        fs::write(source_dir.join("ipak/project.yaml"), b"name: pkg")
+            .unwrap();
+        fs::write(source_dir.join("payload"), vec![0u8; 4096]).unwrap();
+        let source_dir_with_slash =
+            PathBuf::from(format!("{}/", source_dir.to_str().unwrap()));
+
+        for archive_type in [
+            ArchiveType::Zip,
+            ArchiveType::TarGz,
+            ArchiveType::TarZstd,
+            ArchiveType::UnixAr,
+        ] {
+            let archive_path =
+                temp_dir.path().join(format!("pkg.{}", archive_type));
+            create_archive(
+                &source_dir_with_slash,
+                &archive_path,
+                archive_type,
+            )
+            .unwrap();
+            assert_eq!(
+                read_archive_member(
+                    &archive_path,
+                    "./ipak/project.yaml",
+                    64
+                )
+                .unwrap(),
+                Some(b"name: pkg".to_vec()),
+                "{}",
+                archive_type
+            );
+            assert!(
+                read_archive_member(
+                    &archive_path,
+                    "ipak/missing.yaml",
+                    64
+                )
+                .unwrap()
+                .is_none()
+            );
+            assert!(matches!(
+                read_archive_member(&archive_path, "payload", 64),
+                Err(ExtractError::TooLarge { limit: 64 })
+            ));
+        }
+    }
+
     #[test]
     fn test_zip_with_slash() {
         let temp_dir =
@@ -1,8 +1,10 @@
 //! このモジュールは、コマンドライン引数の解析を定義します。
 //! `clap`クレートを使用して、アプリケーションの様々なコマンドとサブコマンドを構造化します。
 
+use crate::modules::pkg::mark::MarkAs;
 use crate::modules::project::ProjectTemplateType;
 use crate::modules::project::package::PackageTarget;
+use crate::modules::system::env::EnvShell;
 use crate::{modules::project::ExecShell, utils::archive::ArchiveType};
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
@@ -25,6 +27,14 @@ pub struct Args {
     /// Enable debug logging. / デバッグログを有効にします。
     #[arg(long, global = true, conflicts_with = "quiet")]
     pub debug: bool,
+
+    /// Use DIR as the root directory for all paths (or set IPAK_ROOT). / すべてのパスのルートディレクトリとして DIR を使用します (IPAK_ROOT でも指定できます)。
+    #[arg(long, global = true, value_name = "DIR")]
+    pub root: Option<PathBuf>,
+
+    /// Override a setting for this run (repeatable). / この実行に限り設定を上書きします (複数指定可)。
+    #[arg(long = "config", global = true, value_name = "KEY=VALUE")]
+    pub config: Vec<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -41,6 +51,9 @@ pub enum Commands {
     /// Manage packages. / パッケージを管理します。
     #[command(subcommand)]
     Pkg(PkgCommands),
+    /// Manage package repositories. / パッケージリポジトリを管理します。
+    #[command(subcommand)]
+    Repo(RepoCommands),
 }
 #[derive(Subcommand, Debug)]
 pub enum ProjectCommands {
@@ -109,6 +122,9 @@ pub enum ProjectCommands {
         /// Target for packaging (e.g., "tar", "zip"). / パッケージ化のターゲット (例: "tar", "zip")。
         #[arg(long)]
         target: Option<PackageTarget>,
+        /// Sign the package with this secret key. / この秘密鍵でパッケージに署名します。
+        #[arg(long, value_name = "KEY")]
+        sign: Option<PathBuf>,
     },
     /// Display project metadata. / プロジェクトのメタデータを表示します。
     Metadata,
@@ -136,6 +152,126 @@ pub enum SystemCommands {
         #[arg(long)]
         global: bool,
     },
+    /// Recover an operation interrupted by a crash. / クラッシュで中断された操作を回復します。
+    Recover {
+        /// Recover local packages. / ローカルパッケージを回復します。
+        #[arg(long)]
+        local: bool,
+        /// Recover global packages. / グローバルパッケージを回復します。
+        #[arg(long)]
+        global: bool,
+    },
+    /// Manage the database of installed packages. / インストール済みパッケージのデータベースを管理します。
+    #[command(subcommand)]
+    Db(DbCommands),
+    /// Check the database of installed packages for problems. / インストール済みパッケージのデータベースの問題を検査します。
+    Check {
+        /// Repair the problems that can be fixed safely. / 安全に修復できる問題を修復します。
+        #[arg(long)]
+        fix: bool,
+        /// Check local packages. / ローカルパッケージを検査します。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Check global packages. / グローバルパッケージを検査します。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+    /// Manage keys for signing packages. / パッケージの署名に使用する鍵を管理します。
+    #[command(subcommand)]
+    Key(KeyCommands),
+    /// Read and change ipak settings. / ipak の設定を読み込み、変更します。
+    #[command(subcommand)]
+    Config(ConfigCommands),
+    /// Print shell code that puts ipak packages on PATH. / ipak のパッケージを PATH に追加するシェルのコードを出力します。
+    Env {
+        /// Shell to generate code for (defaults to $SHELL). / コードを生成するシェル (既定は $SHELL)。
+        #[arg(long, value_enum)]
+        shell: Option<EnvShell>,
+        /// Include local packages and the local bin directory. / ローカルパッケージとローカルの bin ディレクトリを含めます。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Include only global packages. / グローバルパッケージのみを含めます。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Print the effective value of a setting. / 設定の有効な値を表示します。
+    Get {
+        /// Setting to print (e.g., lock.timeout). / 表示する設定 (例: lock.timeout)。
+        key: String,
+    },
+    /// Write a setting to a configuration file. / 設定ファイルに設定を書き込みます。
+    Set {
+        /// Setting to change (e.g., shell). / 変更する設定 (例: shell)。
+        key: String,
+        /// New value. / 新しい値。
+        value: String,
+        /// Write to the local configuration file. / ローカルの設定ファイルに書き込みます。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Write to the global configuration file. / グローバルの設定ファイルに書き込みます。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+    /// List every setting with its value and where it comes from. / すべての設定を値とその出どころと共に一覧表示します。
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeyCommands {
+    /// Generate a new signing key pair. / 新しい署名用の鍵のペアを作成します。
+    Generate {
+        /// Path to write the secret key to (the public key gets a .pub suffix). / 秘密鍵を書き込むパス (公開鍵には .pub が付きます)。
+        path: PathBuf,
+    },
+    /// Trust a public key for verifying packages. / パッケージの検証に使用する公開鍵を信頼します。
+    Trust {
+        /// Path to the public key file. / 公開鍵のファイルへのパス。
+        public_key_file: PathBuf,
+        /// Trust the key for local packages. / ローカルパッケージに対して鍵を信頼します。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Trust the key for global packages. / グローバルパッケージに対して鍵を信頼します。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+    /// List trusted public keys. / 信頼済みの公開鍵を一覧表示します。
+    List {
+        /// List keys trusted for local packages. / ローカルパッケージに対して信頼済みの鍵を一覧表示します。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// List keys trusted for global packages. / グローバルパッケージに対して信頼済みの鍵を一覧表示します。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommands {
+    /// List backups of the packages list. / パッケージリストのバックアップを一覧表示します。
+    List {
+        /// List local backups. / ローカルのバックアップを一覧表示します。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// List global backups. / グローバルのバックアップを一覧表示します。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+    /// Restore the packages list from a backup. / パッケージリストをバックアップから復元します。
+    Restore {
+        /// Backup to restore (1 is the newest). / 復元するバックアップ (1 が最新)。
+        #[arg(default_value_t = 1)]
+        generation: usize,
+        /// Restore the local packages list. / ローカルのパッケージリストを復元します。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Restore the global packages list. / グローバルのパッケージリストを復元します。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -183,15 +319,27 @@ pub enum PkgCommands {
     },
     /// Install a package. / パッケージをインストールします。
     Install {
-        /// Path to the package file. / パッケージファイルへのパス。
+        /// Path to the package file, or name[@range] to install from a repository. / パッケージファイルへのパス、またはリポジトリからインストールする name[@範囲]。
         #[arg()]
-        file_paths: Vec<PathBuf>,
+        packages: Vec<String>,
         /// Install locally. / ローカルにインストールします。
         #[arg(long, conflicts_with = "global")]
         local: bool,
         /// Install globally. / グローバルにインストールします。
         #[arg(long, conflicts_with = "local")]
         global: bool,
+        /// Allow replacing an installed package with an older version. / インストール済みのパッケージを古いバージョンで置き換えることを許可します。
+        #[arg(long)]
+        allow_downgrade: bool,
+        /// Do not install recommended packages. / 推奨パッケージをインストールしません。
+        #[arg(long)]
+        no_install_recommends: bool,
+        /// Also install suggested packages. / 提案されたパッケージもインストールします。
+        #[arg(long)]
+        install_suggests: bool,
+        /// Install packages that are unsigned or badly signed. / 署名されていない、または署名が正しくないパッケージをインストールします。
+        #[arg(long)]
+        allow_unsigned: bool,
     },
     /// Remove a package (binaries only, config files are kept). / パッケージを削除します。バイナリのみが削除され、設定ファイルは残ったままになります。
     Remove {
@@ -204,6 +352,9 @@ pub enum PkgCommands {
         /// Remove globally. / グローバルで削除します。
         #[arg(long, conflicts_with = "local")]
         global: bool,
+        /// Also remove every package that depends on the given packages. / 指定したパッケージに依存するパッケージもすべて削除します。
+        #[arg(long)]
+        cascade: bool,
     },
     /// Purge a package (completely removed, including config files). / パッケージを削除します。設定ファイルも含めて完全に削除されます。
     Purge {
@@ -217,6 +368,78 @@ pub enum PkgCommands {
         #[arg(long, conflicts_with = "local")]
         global: bool,
     },
+    /// Remove automatically installed packages that are no longer needed. / 不要になった自動インストールのパッケージを削除します。
+    Autoremove {
+        /// Remove local packages. / ローカルのパッケージを削除します。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Remove global packages. / グローバルのパッケージを削除します。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+    /// Mark packages as automatically or manually installed. / パッケージを自動または手動でインストールされたものとしてマークします。
+    Mark {
+        /// How the packages should be marked. / パッケージのマーク方法。
+        #[arg(value_enum)]
+        mark_as: MarkAs,
+        /// Names of the packages to mark. / マークするパッケージの名前。
+        #[arg(required = true)]
+        package_names: Vec<String>,
+        /// Mark local packages. / ローカルのパッケージをマークします。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Mark global packages. / グローバルのパッケージをマークします。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+    /// Show what an installed package depends on. / インストール済みパッケージの依存関係を表示します。
+    Depends {
+        /// Name of the package. / パッケージの名前。
+        #[arg()]
+        package_name: String,
+        /// Follow dependencies recursively. / 依存関係を再帰的にたどります。
+        #[arg(long)]
+        tree: bool,
+        /// Show the packages that depend on it instead. / 代わりにこのパッケージに依存しているパッケージを表示します。
+        #[arg(long)]
+        reverse: bool,
+        /// Print the graph in Graphviz DOT format. / グラフを Graphviz の DOT 形式で出力します。
+        #[arg(long)]
+        dot: bool,
+        /// Query local packages. / ローカルのパッケージを調べます。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Query global packages. / グローバルのパッケージを調べます。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+    /// Show why an installed package is installed. / インストール済みパッケージがインストールされている理由を表示します。
+    Why {
+        /// Name of the package. / パッケージの名前。
+        #[arg()]
+        package_name: String,
+        /// Print the graph in Graphviz DOT format. / グラフを Graphviz の DOT 形式で出力します。
+        #[arg(long)]
+        dot: bool,
+        /// Query local packages. / ローカルのパッケージを調べます。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Query global packages. / グローバルのパッケージを調べます。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+    /// Verify installed files against the recorded checksums. / インストール済みのファイルを記録されたチェックサムと照合します。
+    Verify {
+        /// Name of the package (all installed packages if omitted). / パッケージの名前 (省略した場合はすべてのインストール済みパッケージ)。
+        #[arg()]
+        package_name: Option<String>,
+        /// Verify local packages. / ローカルのパッケージを検証します。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Verify global packages. / グローバルのパッケージを検証します。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
     /// Display package metadata. / パッケージのメタデータを表示します。
     MetaData {
         /// Path to the package to get metadata from. / メタデータを取得するパッケージへのパス。
@@ -236,3 +459,49 @@ pub enum PkgCommands {
         global: bool,
     },
 }
+
+#[derive(Subcommand, Debug)]
+pub enum RepoCommands {
+    /// Generate index.yaml for a directory of packages. / パッケージのディレクトリに index.yaml を生成します。
+    Index {
+        /// Path to the repository directory. / リポジトリディレクトリへのパス。
+        #[arg()]
+        dir: PathBuf,
+    },
+    /// Add a repository. / リポジトリを追加します。
+    Add {
+        /// Name of the repository. / リポジトリの名前。
+        #[arg()]
+        name: String,
+        /// Directory or file:// URL of the repository. / リポジトリのディレクトリまたは file:// URL。
+        #[arg()]
+        url: String,
+        /// Add locally. / ローカルに追加します。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Add globally. / グローバルに追加します。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+    /// Remove a repository. / リポジトリを削除します。
+    Remove {
+        /// Name of the repository to remove. / 削除するリポジトリの名前。
+        #[arg()]
+        name: String,
+        /// Remove locally. / ローカルで削除します。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// Remove globally. / グローバルで削除します。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+    /// List configured repositories. / 設定されているリポジトリを一覧表示します。
+    List {
+        /// List local repositories. / ローカルのリポジトリを一覧表示します。
+        #[arg(long, conflicts_with = "global")]
+        local: bool,
+        /// List global repositories. / グローバルのリポジトリを一覧表示します。
+        #[arg(long, conflicts_with = "local")]
+        global: bool,
+    },
+}
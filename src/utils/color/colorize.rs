@@ -1,4 +1,17 @@
 use super::RGB;
+use crate::modules::system::config;
+
+/// テキストにエスケープシーケンスを付けます。
+///
+/// 設定で色付きの出力が無効になっている場合は、テキストをそのまま返します。
+fn paint(code: &str, text: &str) -> String {
+    if config::get().color() {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
 pub trait Colorize {
     fn red(&self) -> String;
     fn yellow(&self) -> String;
@@ -10,54 +23,54 @@ pub trait Colorize {
 }
 impl Colorize for String {
     fn red(&self) -> String {
-        format!("\x1b[31m{}\x1b[0m", self)
+        paint("31", self)
     }
     fn yellow(&self) -> String {
-        format!("\x1b[33m{}\x1b[0m", self)
+        paint("33", self)
     }
     fn green(&self) -> String {
-        format!("\x1b[32m{}\x1b[0m", self)
+        paint("32", self)
     }
     fn cyan(&self) -> String {
-        format!("\x1b[36m{}\x1b[0m", self)
+        paint("36", self)
     }
     fn blue(&self) -> String {
-        format!("\x1b[34m{}\x1b[0m", self)
+        paint("34", self)
     }
     fn magenta(&self) -> String {
-        format!("\x1b[35m{}\x1b[0m", self)
+        paint("35", self)
     }
     fn rgb(&self, rgb: RGB) -> String {
-        format!(
-            "\x1b[38;2;{};{};{}m{}\x1b[0m",
-            rgb.red, rgb.green, rgb.blue, self
+        paint(
+            &format!("38;2;{};{};{}", rgb.red, rgb.green, rgb.blue),
+            self,
         )
     }
 }
 
 impl Colorize for &str {
     fn red(&self) -> String {
-        format!("\x1b[31m{}\x1b[0m", self)
+        paint("31", self)
     }
     fn yellow(&self) -> String {
-        format!("\x1b[33m{}\x1b[0m", self)
+        paint("33", self)
     }
     fn green(&self) -> String {
-        format!("\x1b[32m{}\x1b[0m", self)
+        paint("32", self)
     }
     fn cyan(&self) -> String {
-        format!("\x1b[36m{}\x1b[0m", self)
+        paint("36", self)
     }
     fn blue(&self) -> String {
-        format!("\x1b[34m{}\x1b[0m", self)
+        paint("34", self)
     }
     fn magenta(&self) -> String {
-        format!("\x1b[35m{}\x1b[0m", self)
+        paint("35", self)
     }
     fn rgb(&self, rgb: RGB) -> String {
-        format!(
-            "\x1b[38;2;{};{};{}m{}\x1b[0m",
-            rgb.red, rgb.green, rgb.blue, self
+        paint(
+            &format!("38;2;{};{};{}", rgb.red, rgb.green, rgb.blue),
+            self,
         )
     }
 }
@@ -73,53 +86,53 @@ pub trait ColorizeBg {
 }
 impl ColorizeBg for String {
     fn red_bg(&self) -> String {
-        format!("\x1b[41m{}\x1b[0m", self)
+        paint("41", self)
     }
     fn yellow_bg(&self) -> String {
-        format!("\x1b[43m{}\x1b[0m", self)
+        paint("43", self)
     }
     fn green_bg(&self) -> String {
-        format!("\x1b[42m{}\x1b[0m", self)
+        paint("42", self)
     }
     fn cyan_bg(&self) -> String {
-        format!("\x1b[46m{}\x1b[0m", self)
+        paint("46", self)
     }
     fn blue_bg(&self) -> String {
-        format!("\x1b[44m{}\x1b[0m", self)
+        paint("44", self)
     }
     fn magenta_bg(&self) -> String {
-        format!("\x1b[45m{}\x1b[0m", self)
+        paint("45", self)
     }
     fn rgb_bg(&self, rgb: RGB) -> String {
-        format!(
-            "\x1b[48;2;{};{};{}m{}\x1b[0m",
-            rgb.red, rgb.green, rgb.blue, self
+        paint(
+            &format!("48;2;{};{};{}", rgb.red, rgb.green, rgb.blue),
+            self,
         )
     }
 }
 impl ColorizeBg for &str {
     fn red_bg(&self) -> String {
-        format!("\x1b[41m{}\x1b[0m", self)
+        paint("41", self)
     }
     fn yellow_bg(&self) -> String {
-        format!("\x1b[43m{}\x1b[0m", self)
+        paint("43", self)
     }
     fn green_bg(&self) -> String {
-        format!("\x1b[42m{}\x1b[0m", self)
+        paint("42", self)
     }
     fn cyan_bg(&self) -> String {
-        format!("\x1b[46m{}\x1b[0m", self)
+        paint("46", self)
     }
     fn blue_bg(&self) -> String {
-        format!("\x1b[44m{}\x1b[0m", self)
+        paint("44", self)
     }
     fn magenta_bg(&self) -> String {
-        format!("\x1b[45m{}\x1b[0m", self)
+        paint("45", self)
     }
     fn rgb_bg(&self, rgb: RGB) -> String {
-        format!(
-            "\x1b[48;2;{};{};{}m{}\x1b[0m",
-            rgb.red, rgb.green, rgb.blue, self
+        paint(
+            &format!("48;2;{};{};{}", rgb.red, rgb.green, rgb.blue),
+            self,
         )
     }
 }
@@ -131,24 +144,24 @@ pub trait StyleModifier {
 
 impl StyleModifier for String {
     fn bold(&self) -> String {
-        format!("\x1b[1m{}\x1b[0m", self)
+        paint("1", self)
     }
     fn italic(&self) -> String {
-        format!("\x1b[3m{}\x1b[0m", self)
+        paint("3", self)
     }
     fn underline(&self) -> String {
-        format!("\x1b[4m{}\x1b[0m", self)
+        paint("4", self)
     }
 }
 
 impl StyleModifier for &str {
     fn bold(&self) -> String {
-        format!("\x1b[1m{}\x1b[0m", self)
+        paint("1", self)
     }
     fn italic(&self) -> String {
-        format!("\x1b[3m{}\x1b[0m", self)
+        paint("3", self)
     }
     fn underline(&self) -> String {
-        format!("\x1b[4m{}\x1b[0m", self)
+        paint("4", self)
     }
 }
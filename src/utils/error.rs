@@ -3,6 +3,7 @@ use std::{fmt, io};
 
 // InstallError と RemoveError をインポート
 use crate::modules::pkg::depend::error::{InstallError, RemoveError};
+use crate::utils::archive::ExtractError;
 
 /// アプリケーション全体で利用されるカスタムエラー構造体です。
 /// エラーの種類と詳細なメッセージを保持します。
@@ -26,6 +27,8 @@ pub enum ErrorKind {
     Install,
     /// パッケージ削除関連のエラー。
     Remove,
+    /// アーカイブ展開関連のエラー。
+    Archive,
 }
 
 impl fmt::Display for ErrorKind {
@@ -36,6 +39,7 @@ impl fmt::Display for ErrorKind {
             Self::Io(io_errorkind) => write!(f, "IO-{}", io_errorkind),
             Self::Install => write!(f, "Package Installation Error"),
             Self::Remove => write!(f, "Package Removal Error"),
+            Self::Archive => write!(f, "Archive Extraction Error"),
         }
     }
 }
@@ -95,6 +99,19 @@ impl From<RemoveError> for Error {
     }
 }
 
+impl From<ExtractError> for Error {
+    fn from(value: ExtractError) -> Self {
+        match value {
+            ExtractError::Io(e) => Error::from(e),
+            _ => Error::new(
+                ErrorKind::Archive,
+                value.to_string(),
+                Some(Box::new(value)),
+            ),
+        }
+    }
+}
+
 impl Error {
     /// その他の種類のエラーを生成します。
     ///
@@ -2,6 +2,8 @@
 //! `Version`構造体はバージョン番号を解析し、比較するための機能を提供します。
 //! `VersionRange`構造体は、特定のバージョン範囲を定義し、バージョンがその範囲内にあるかをチェックする機能を提供します。
 
+#![allow(clippy::collapsible_if)]
+
 use std::{fmt, fmt::Display, str::FromStr};
 
 use serde::{Deserialize, Serialize};